@@ -0,0 +1,57 @@
+// cycles.rs
+
+// Run each ROM in roms/ for CYCLES instructions, without a
+// window, with the interpreter and with the translation cache.
+// Run with "cargo bench".
+
+extern crate chip8;
+#[macro_use]
+extern crate criterion;
+
+use std::fs::{self, File};
+
+use chip8::cpu::{self, CPU};
+use chip8::scheduler::DEFAULT_INSNS_PER_FRAME;
+use criterion::Criterion;
+
+/// Number of instructions executed per iteration.
+const CYCLES: u32 = 10_000;
+
+/// Run CYCLES instructions, with a vertical blank every
+/// DEFAULT_INSNS_PER_FRAME instructions as in the emulator,
+/// but without waiting for the frames to end. The instructions
+/// are taken from the translation cache if `jit` is true.
+fn run(c: &mut CPU, jit: bool) {
+    for n in 0..CYCLES {
+        if n % DEFAULT_INSNS_PER_FRAME == 0 {
+            c.vblank();
+        }
+        let result = if jit { c.execute_cached() } else { c.execute_insn() };
+        if result.is_err() {
+            break;
+        }
+    }
+}
+
+fn bench_roms(crit: &mut Criterion) {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/roms");
+    let mut paths: Vec<_> = fs::read_dir(dir).unwrap().map(|e| e.unwrap().path()).collect();
+    paths.sort();
+    for path in paths {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        for &jit in &[false, true] {
+            let mut c = CPU::headless();
+            c.load_rom(File::open(&path).unwrap(), cpu::PC_START).unwrap();
+            let start = c.save_state();
+
+            let id = if jit { format!("{} jit", name) } else { name.clone() };
+            crit.bench_function(&id, move |b| b.iter(|| {
+                c.load_state(&start).unwrap();
+                run(&mut c, jit);
+            }));
+        }
+    }
+}
+
+criterion_group!(benches, bench_roms);
+criterion_main!(benches);
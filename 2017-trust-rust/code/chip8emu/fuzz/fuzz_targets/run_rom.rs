@@ -0,0 +1,57 @@
+// run_rom.rs
+
+// Run arbitrary ROMs on the headless core, with the interpreter
+// and the translation cache side by side:
+//
+//   cargo fuzz run run_rom -- -timeout=1
+//
+// The first byte of the input picks the quirks and the fault
+// policy, the rest is the ROM. A run is MAX_FRAMES frames at
+// most and takes milliseconds, so a timeout means a frame
+// never ended.
+
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate chip8;
+
+use chip8::cpu::{CPU, PC_START};
+use chip8::error::FaultPolicy;
+use chip8::quirks::{self, Quirks};
+use chip8::scheduler::DEFAULT_INSNS_PER_FRAME;
+
+/// Number of frames a ROM is run for.
+const MAX_FRAMES: u32 = 120;
+
+const POLICIES: [FaultPolicy; 3] = [FaultPolicy::Halt, FaultPolicy::Wrap, FaultPolicy::Ignore];
+
+fuzz_target!(|data: &[u8]| {
+    let (&config, rom) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let quirks: Quirks = quirks::PRESET_NAMES[usize::from(config & 3)].parse().unwrap();
+    let policy = POLICIES[usize::from(config >> 2) % POLICIES.len()];
+
+    let mut cpus = vec![CPU::headless(), CPU::headless()];
+    for c in &mut cpus {
+        c.set_quirks(quirks);
+        c.set_fault_policy(policy);
+        if c.load_rom(rom, PC_START).is_err() {
+            return;
+        }
+    }
+    for _ in 0..MAX_FRAMES {
+        for c in &mut cpus {
+            c.vblank();
+        }
+        for _ in 0..DEFAULT_INSNS_PER_FRAME {
+            let interpreted = cpus[0].execute_insn();
+            let cached = cpus[1].execute_cached();
+            assert_eq!(interpreted, cached);
+            if interpreted.is_err() || cpus[0].exited() {
+                return;
+            }
+        }
+        assert!(cpus[0].save_state() == cpus[1].save_state());
+    }
+});
@@ -0,0 +1,405 @@
+// analyze.rs
+
+// Static analysis of a ROM: the code reachable from its start,
+// split into basic blocks, the subroutines and which ones they
+// call, the writes which modify code, and the bytes which are
+// data or never reached.
+//
+// Only what can be told without running the program is found.
+// "bnnn" jumps to nnn + v0, which depends on v0, so only nnn
+// itself is followed.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fmt::Write;
+
+use disasm;
+use insn::{self, Instruction};
+
+/// How control goes from an instruction to the next one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edge {
+    /// On to the instruction which follows. After a call, that
+    /// is where the subroutine returns to.
+    Next,
+    /// "1nnn", or "bnnn" with v0 = 0.
+    Jump,
+    /// A skip instruction skipping the next instruction.
+    Skip,
+    /// "2nnn", into the subroutine.
+    Call,
+}
+
+/// A basic block: instructions run one after the other, which
+/// are only entered at the first one.
+#[derive(Debug, PartialEq)]
+pub struct Block {
+    /// The address of each instruction.
+    pub insns: Vec<usize>,
+    /// The blocks control goes to after the last instruction.
+    pub edges: Vec<(usize, Edge)>,
+}
+
+/// A "fx33", "fx55" or "5xy2" which writes to memory holding
+/// code, so the program modifies itself.
+#[derive(Debug, PartialEq)]
+pub struct CodeWrite {
+    /// Address of the instruction.
+    pub addr: usize,
+    /// The addresses written, from `start` up to but not
+    /// including `end`.
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RegionKind {
+    /// Bytes which are not code, like sprites. They are
+    /// pointed to by "annn", or are not valid instructions.
+    Data,
+    /// Valid instructions which are never reached.
+    Unreachable,
+}
+
+/// Bytes of the ROM which are not reached as code, from
+/// `start` up to but not including `end`.
+#[derive(Debug, PartialEq)]
+pub struct Region {
+    pub start: usize,
+    pub end: usize,
+    pub kind: RegionKind,
+}
+
+/// Something the analysis could not follow.
+#[derive(Debug, PartialEq)]
+pub enum Warning {
+    /// A "bnnn" at this address; its targets other than nnn
+    /// are not known.
+    IndirectJump(usize),
+    /// Control goes outside the ROM.
+    OutsideRom { from: usize, to: usize },
+    /// The opcode reached at this address is not a valid
+    /// instruction.
+    InvalidOpcode(usize),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Warning::IndirectJump(addr) =>
+                write!(f, "0x{:03x}: indirect jump, only followed for v0 = 0", addr),
+            Warning::OutsideRom { from, to } =>
+                write!(f, "0x{:03x}: goes to 0x{:03x}, outside the ROM", from, to),
+            Warning::InvalidOpcode(addr) =>
+                write!(f, "0x{:03x}: invalid opcode reached", addr),
+        }
+    }
+}
+
+/// What `analyze` found out about a ROM.
+pub struct Analysis {
+    /// The basic blocks, by start address.
+    pub blocks: BTreeMap<usize, Block>,
+    /// The subroutines, by entry point, and the entry points
+    /// of the subroutines each one calls. The start of the ROM
+    /// counts as a subroutine.
+    pub calls: BTreeMap<usize, BTreeSet<usize>>,
+    pub code_writes: Vec<CodeWrite>,
+    pub regions: Vec<Region>,
+    pub warnings: Vec<Warning>,
+    /// The opcode of each instruction reached.
+    opcodes: BTreeMap<usize, u16>,
+    /// The address loaded by each "f000 nnnn" reached, if it
+    /// is in the ROM.
+    operands: BTreeMap<usize, usize>,
+}
+
+/// Where control can go after the instruction "opcode" at
+/// "addr", and whether the instruction ends a basic block.
+/// `next_size` is the size of the instruction after it, which
+/// a skip instruction skips.
+fn successors(addr: usize, opcode: u16, next_size: usize) -> (Vec<(usize, Edge)>, bool) {
+    use insn::Instruction::*;
+
+    match insn::decode(opcode) {
+        Ok(Jump(nnn)) | Ok(JumpOffset(nnn)) => (vec![(nnn, Edge::Jump)], true),
+        Ok(Call(nnn)) => (vec![(nnn, Edge::Call), (addr + 2, Edge::Next)], true),
+        Ok(SkipIfEqConst(..)) | Ok(SkipIfNeConst(..)) | Ok(SkipIfEq(..)) |
+        Ok(SkipIfNe(..)) | Ok(SkipIfKey(_)) | Ok(SkipIfNotKey(_)) =>
+            (vec![(addr + 2, Edge::Next), (addr + 2 + next_size, Edge::Skip)], true),
+        Ok(Return) | Ok(Exit) | Err(_) => (Vec::new(), true),
+        Ok(insn) => (vec![(addr + insn.size(), Edge::Next)], false),
+    }
+}
+
+/// Number of bytes an instruction writes at i, if any.
+fn write_len(insn: Instruction) -> Option<usize> {
+    match insn {
+        Instruction::Bcd(_) => Some(3),
+        Instruction::Store(x) => Some(x + 1),
+        Instruction::StoreRange(x, y) => Some(x.max(y) - x.min(y) + 1),
+        _ => None,
+    }
+}
+
+/// Analyze "rom", loaded at address "origin", starting from its
+/// first instruction.
+pub fn analyze(rom: &[u8], origin: usize) -> Analysis {
+    let end = origin + rom.len();
+    let in_rom = |addr: usize| addr >= origin && addr + 2 <= end;
+    let opcode_at = |addr: usize| {
+        (u16::from(rom[addr - origin]) << 8) | u16::from(rom[addr - origin + 1])
+    };
+    let size_at = |addr: usize| {
+        if in_rom(addr) { insn::decode(opcode_at(addr)).map_or(2, |insn| insn.size()) } else { 2 }
+    };
+
+    // Find every instruction reached, with where it goes next.
+    let mut opcodes = BTreeMap::new();
+    let mut operands = BTreeMap::new();
+    let mut succs = BTreeMap::new();
+    let mut leaders = BTreeSet::new();
+    let mut warnings = Vec::new();
+    let mut todo = Vec::new();
+    if in_rom(origin) {
+        leaders.insert(origin);
+        todo.push(origin);
+    }
+    while let Some(addr) = todo.pop() {
+        if opcodes.contains_key(&addr) {
+            continue;
+        }
+        let opcode = opcode_at(addr);
+        opcodes.insert(addr, opcode);
+        match insn::decode(opcode) {
+            Ok(Instruction::JumpOffset(_)) => warnings.push(Warning::IndirectJump(addr)),
+            Ok(Instruction::LongI) if in_rom(addr + 2) => {
+                operands.insert(addr, usize::from(opcode_at(addr + 2)));
+            },
+            Err(_) => warnings.push(Warning::InvalidOpcode(addr)),
+            _ => {},
+        }
+        let (edges, ends_block) = successors(addr, opcode, size_at(addr + 2));
+        let mut kept = Vec::new();
+        for (to, edge) in edges {
+            if !in_rom(to) {
+                warnings.push(Warning::OutsideRom { from: addr, to });
+                continue;
+            }
+            if ends_block {
+                leaders.insert(to);
+            }
+            todo.push(to);
+            kept.push((to, edge));
+        }
+        succs.insert(addr, (kept, ends_block));
+    }
+    warnings.sort_by_key(|w| match *w {
+        Warning::IndirectJump(addr) | Warning::InvalidOpcode(addr) => addr,
+        Warning::OutsideRom { from, .. } => from,
+    });
+
+    // Split them into basic blocks, each of which starts at a
+    // leader: a target of an instruction which ends a block.
+    let mut blocks = BTreeMap::new();
+    for &start in &leaders {
+        let mut insns = vec![start];
+        let mut addr = start;
+        let edges = loop {
+            let (ref edges, ends_block) = succs[&addr];
+            if ends_block || edges.is_empty() {
+                break edges.clone();
+            }
+            let next = edges[0].0;
+            if leaders.contains(&next) {
+                break edges.clone();
+            }
+            insns.push(next);
+            addr = next;
+        };
+        blocks.insert(start, Block { insns, edges });
+    }
+
+    // A subroutine is made of the blocks reached from its entry
+    // point without following calls.
+    let mut calls = BTreeMap::new();
+    let entries: BTreeSet<usize> = leaders.iter().cloned()
+        .filter(|&a| a == origin || blocks.values()
+                .any(|b: &Block| b.edges.contains(&(a, Edge::Call))))
+        .collect();
+    for &entry in &entries {
+        let mut seen = BTreeSet::new();
+        let mut callees = BTreeSet::new();
+        let mut todo = vec![entry];
+        while let Some(b) = todo.pop() {
+            if !seen.insert(b) {
+                continue;
+            }
+            for &(to, edge) in &blocks[&b].edges {
+                if edge == Edge::Call {
+                    callees.insert(to);
+                } else {
+                    todo.push(to);
+                }
+            }
+        }
+        calls.insert(entry, callees);
+    }
+
+    // Find the value of i at each instruction, where it is the
+    // same whichever way the instruction is reached, to tell
+    // where "fx33" and "fx55" write. None means unknown.
+    let mut i_at: BTreeMap<usize, Option<usize>> = BTreeMap::new();
+    let mut todo = Vec::new();
+    if in_rom(origin) {
+        todo.push((origin, None));
+    }
+    while let Some((addr, i)) = todo.pop() {
+        let i = match i_at.get(&addr) {
+            None => i,
+            Some(&old) if old == i || old.is_none() => continue,
+            Some(_) => None,
+        };
+        i_at.insert(addr, i);
+        let insn = insn::decode(opcodes[&addr]);
+        let is_call = matches!(insn, Ok(Instruction::Call(_)));
+        let i_after = match insn {
+            Ok(Instruction::SetI(nnn)) => Some(nnn),
+            Ok(Instruction::LongI) => operands.get(&addr).cloned(),
+            Ok(Instruction::AddI(_)) | Ok(Instruction::Font(_)) | Ok(Instruction::BigFont(_)) |
+            Ok(Instruction::Store(_)) | Ok(Instruction::Load(_)) => None,
+            _ => i,
+        };
+        for &(to, edge) in &succs[&addr].0 {
+            // The subroutine may have changed i by the time it
+            // returns.
+            let returned = is_call && edge == Edge::Next;
+            todo.push((to, if returned { None } else { i_after }));
+        }
+    }
+
+    let code_bytes: BTreeSet<usize> = opcodes.iter()
+        .flat_map(|(&a, &op)| a..a + insn::decode(op).map_or(2, |insn| insn.size()))
+        .collect();
+    let mut code_writes = Vec::new();
+    for (&addr, &i) in &i_at {
+        let len = insn::decode(opcodes[&addr]).ok().and_then(write_len);
+        if let (Some(start), Some(len)) = (i, len) {
+            if (start..start + len).any(|a| code_bytes.contains(&a)) {
+                code_writes.push(CodeWrite { addr, start, end: start + len });
+            }
+        }
+    }
+
+    // The bytes not reached as code.
+    let pointed_to: BTreeSet<usize> = opcodes.values()
+        .filter_map(|&op| match insn::decode(op) {
+            Ok(Instruction::SetI(nnn)) => Some(nnn),
+            _ => None,
+        })
+        .chain(operands.values().cloned())
+        .collect();
+    let mut regions = Vec::new();
+    let mut addr = origin;
+    while addr < end {
+        if code_bytes.contains(&addr) {
+            addr += 1;
+            continue;
+        }
+        let start = addr;
+        while addr < end && !code_bytes.contains(&addr) {
+            addr += 1;
+        }
+        let bytes = &rom[start - origin..addr - origin];
+        let is_code = bytes.len().is_multiple_of(2) && bytes.chunks(2)
+            .all(|c| insn::decode((u16::from(c[0]) << 8) | u16::from(c[1])).is_ok());
+        let is_pointed_to = pointed_to.range(start..addr).next().is_some();
+        let kind = if is_code && !is_pointed_to { RegionKind::Unreachable } else { RegionKind::Data };
+        regions.push(Region { start, end: addr, kind });
+    }
+
+    Analysis { blocks, calls, code_writes, regions, warnings, opcodes, operands }
+}
+
+/// Quote "lines" as a DOT string, with "\l" (a left justified
+/// line break) after each line.
+fn dot_label(lines: &[String]) -> String {
+    let mut out = String::from("\"");
+    for line in lines {
+        out.push_str(&line.replace('\\', "\\\\").replace('"', "\\\""));
+        out.push_str("\\l");
+    }
+    out.push('"');
+    out
+}
+
+impl Analysis {
+    /// A summary of the analysis, one finding per line.
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{} instructions reached in {} blocks",
+                         self.opcodes.len(), self.blocks.len());
+        for (entry, callees) in &self.calls {
+            let callees: Vec<String> = callees.iter().map(|a| format!("0x{:03x}", a)).collect();
+            let _ = writeln!(out, "subroutine 0x{:03x} calls: {}", entry,
+                             if callees.is_empty() { "none".to_string() } else { callees.join(", ") });
+        }
+        for w in &self.code_writes {
+            let _ = writeln!(out, "0x{:03x}: self-modifying write to 0x{:03x}-0x{:03x}",
+                             w.addr, w.start, w.end - 1);
+        }
+        for r in &self.regions {
+            let kind = match r.kind {
+                RegionKind::Data => "data",
+                RegionKind::Unreachable => "unreachable code",
+            };
+            let _ = writeln!(out, "0x{:03x}-0x{:03x}: {} ({} bytes)",
+                             r.start, r.end - 1, kind, r.end - r.start);
+        }
+        for w in &self.warnings {
+            let _ = writeln!(out, "{}", w);
+        }
+        out
+    }
+
+    /// The control flow graph in Graphviz DOT, with the
+    /// disassembly of each block. Subroutine entry points have
+    /// a double border, and blocks which modify code are red.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph cfg {\n    node [shape=box fontname=\"monospace\"];\n");
+        for (&start, block) in &self.blocks {
+            let mut lines = vec![format!("L{:03x}:", start)];
+            for &addr in &block.insns {
+                let text = match self.operands.get(&addr) {
+                    Some(&nnnn) => disasm::disassemble_at(&[0xf0, 0x00, (nnnn >> 8) as u8, nnnn as u8], 0),
+                    None => disasm::disassemble_insn(self.opcodes[&addr]),
+                };
+                lines.push(format!("  {}", text));
+            }
+            let mut attrs = format!("label={}", dot_label(&lines));
+            if self.calls.contains_key(&start) {
+                attrs.push_str(" peripheries=2");
+            }
+            if self.code_writes.iter().any(|w| block.insns.contains(&w.addr)) {
+                attrs.push_str(" color=red");
+            }
+            let _ = writeln!(out, "    L{:03x} [{}];", start, attrs);
+        }
+        for (&start, block) in &self.blocks {
+            for &(to, edge) in &block.edges {
+                let attrs = match edge {
+                    Edge::Next | Edge::Jump => "",
+                    Edge::Skip => " [style=dashed label=\"skip\"]",
+                    Edge::Call => " [style=dotted label=\"call\"]",
+                };
+                let _ = writeln!(out, "    L{:03x} -> L{:03x}{};", start, to, attrs);
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[cfg(test)]
+#[path="./analyze_test.rs"]
+mod analyze_test;
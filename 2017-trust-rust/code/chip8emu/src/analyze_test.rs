@@ -0,0 +1,128 @@
+use super::*;
+
+use std::fs;
+
+use rom;
+
+#[test]
+fn test_blocks_and_calls() {
+    let rom = [0x00, 0xe0,  // 200: clear
+               0xa2, 0x10,  // 202: i := 0x210
+               0x22, 0x0c,  // 204: :call 0x20c
+               0x30, 0x01,  // 206: if v0 != 0x01 then
+               0x12, 0x00,  // 208: jump 0x200
+               0x12, 0x0a,  // 20a: jump 0x20a
+               0x60, 0x01,  // 20c: v0 := 0x01
+               0x00, 0xee,  // 20e: return
+               0xf0, 0x90,  // 210: sprite
+               0x90, 0xf0];
+    let a = analyze(&rom, 0x200);
+    let blocks: Vec<(usize, Block)> = vec![
+        (0x200, Block { insns: vec![0x200, 0x202, 0x204],
+                        edges: vec![(0x20c, Edge::Call), (0x206, Edge::Next)] }),
+        (0x206, Block { insns: vec![0x206], edges: vec![(0x208, Edge::Next), (0x20a, Edge::Skip)] }),
+        (0x208, Block { insns: vec![0x208], edges: vec![(0x200, Edge::Jump)] }),
+        (0x20a, Block { insns: vec![0x20a], edges: vec![(0x20a, Edge::Jump)] }),
+        (0x20c, Block { insns: vec![0x20c, 0x20e], edges: vec![] }),
+    ];
+    assert_eq!(a.blocks, blocks.into_iter().collect());
+    assert_eq!(a.calls, vec![(0x200, vec![0x20c].into_iter().collect()),
+                             (0x20c, BTreeSet::new())].into_iter().collect());
+    assert_eq!(a.regions, vec![Region { start: 0x210, end: 0x214, kind: RegionKind::Data }]);
+    assert!(a.code_writes.is_empty());
+    assert!(a.warnings.is_empty());
+}
+
+#[test]
+fn test_code_writes() {
+    let rom = [0xa2, 0x06,  // 200: i := 0x206
+               0xf0, 0x33,  // 202: bcd v0, over the jump
+               0x12, 0x06,  // 204: jump 0x206
+               0x12, 0x06]; // 206: jump 0x206
+    assert_eq!(analyze(&rom, 0x200).code_writes,
+               vec![CodeWrite { addr: 0x202, start: 0x206, end: 0x209 }]);
+
+    // The subroutine may change i, so where "save" writes is
+    // not known.
+    let rom = [0xa2, 0x06,  // 200: i := 0x206
+               0x22, 0x06,  // 202: :call 0x206
+               0xf0, 0x55,  // 204: save v0
+               0x00, 0xee]; // 206: return
+    assert!(analyze(&rom, 0x200).code_writes.is_empty());
+}
+
+#[test]
+fn test_regions_and_warnings() {
+    let rom = [0x30, 0x00,  // 200: if v0 != 0x00 then
+               0xb2, 0x10,  // 202: jump0 0x210, outside the ROM
+               0x01, 0x23,  // 204: invalid
+               0x00, 0xe0,  // 206: clear, never reached
+               0x00, 0xee]; // 208: return, never reached
+    let a = analyze(&rom, 0x200);
+    assert_eq!(a.regions, vec![Region { start: 0x206, end: 0x20a, kind: RegionKind::Unreachable }]);
+    assert_eq!(a.warnings, vec![Warning::IndirectJump(0x202),
+                                Warning::OutsideRom { from: 0x202, to: 0x210 },
+                                Warning::InvalidOpcode(0x204)]);
+    assert_eq!(a.report(), concat!(
+        "3 instructions reached in 3 blocks\n",
+        "subroutine 0x200 calls: none\n",
+        "0x206-0x209: unreachable code (4 bytes)\n",
+        "0x202: indirect jump, only followed for v0 = 0\n",
+        "0x202: goes to 0x210, outside the ROM\n",
+        "0x204: invalid opcode reached\n"));
+}
+
+#[test]
+fn test_to_dot() {
+    let rom = [0x22, 0x04,  // 200: :call 0x204
+               0x12, 0x02,  // 202: jump 0x202
+               0x30, 0x00,  // 204: if v0 != 0x00 then
+               0x00, 0xee,  // 206: return
+               0x00, 0xee]; // 208: return
+    assert_eq!(analyze(&rom, 0x200).to_dot(), concat!(
+        "digraph cfg {\n",
+        "    node [shape=box fontname=\"monospace\"];\n",
+        "    L200 [label=\"L200:\\l  :call 0x204\\l\" peripheries=2];\n",
+        "    L202 [label=\"L202:\\l  jump 0x202\\l\"];\n",
+        "    L204 [label=\"L204:\\l  if v0 != 0x00 then\\l\" peripheries=2];\n",
+        "    L206 [label=\"L206:\\l  return\\l\"];\n",
+        "    L208 [label=\"L208:\\l  return\\l\"];\n",
+        "    L200 -> L204 [style=dotted label=\"call\"];\n",
+        "    L200 -> L202;\n",
+        "    L202 -> L202;\n",
+        "    L204 -> L206;\n",
+        "    L204 -> L208 [style=dashed label=\"skip\"];\n",
+        "}\n"));
+}
+
+#[test]
+fn test_xochip() {
+    let rom = [0x30, 0x00,  // 200: if v0 != 0x00 then
+               0xf0, 0x00,  // 202: i := long 0x020c, skipped whole
+               0x02, 0x0c,
+               0xf0, 0x00,  // 206: i := long 0x020c
+               0x02, 0x0c,
+               0x50, 0x12,  // 20a: save v0 - v1, over the code at 0x20c
+               0x12, 0x0a]; // 20c: jump 0x20a
+    let a = analyze(&rom, 0x200);
+    assert_eq!(a.blocks[&0x200].edges, vec![(0x202, Edge::Next), (0x206, Edge::Skip)]);
+    assert_eq!(a.blocks[&0x202].insns, vec![0x202]);
+    assert_eq!(a.blocks[&0x206].insns, vec![0x206]);
+    assert_eq!(a.blocks[&0x20a].insns, vec![0x20a, 0x20c]);
+    assert_eq!(a.code_writes, vec![CodeWrite { addr: 0x20a, start: 0x20c, end: 0x20e }]);
+    assert!(a.regions.is_empty());
+    assert!(a.to_dot().contains("L206 [label=\"L206:\\l  i := long 0x020c\\l\"];"));
+}
+
+#[test]
+fn test_bundled_roms() {
+    for (name, _) in rom::list_roms(rom::BUNDLED_ROMS_DIR).unwrap() {
+        let data = fs::read(format!("{}/{}", rom::BUNDLED_ROMS_DIR, name)).unwrap();
+        let a = analyze(&data, 0x200);
+        assert!(a.blocks.contains_key(&0x200), "{}", name);
+        // Every byte of the ROM is either code or in a region.
+        let in_regions: usize = a.regions.iter().map(|r| r.end - r.start).sum();
+        let code: BTreeSet<usize> = a.opcodes.keys().flat_map(|&a| vec![a, a + 1]).collect();
+        assert_eq!(in_regions + code.range(0x200..0x200 + data.len()).count(), data.len(), "{}", name);
+    }
+}
@@ -0,0 +1,276 @@
+// asm.rs
+
+// An assembler for the Octo style mnemonics produced by
+// `disasm::disassemble`.
+//
+// References:
+// (1) <http://johnearnest.github.io/Octo/docs/Manual.html>
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+/// Error returned when a program can not be assembled.
+#[derive(Debug, PartialEq)]
+pub struct AsmError {
+    /// Line number, starting at 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AsmError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+/// An operand which is a location in memory.
+enum Addr {
+    Num(u16),
+    Label(String),
+}
+
+/// What a line of the source assembles to.
+enum Item {
+    /// ": name" defines a label at the current address.
+    Label(String),
+    /// An instruction. If there is an address operand, it
+    /// is stored in the lower 12 bits of the opcode.
+    Insn(u16, Option<Addr>),
+    /// "i := long", with its 16 bit address.
+    Long(Addr),
+    /// Data bytes.
+    Bytes(Vec<u8>),
+}
+
+impl Item {
+    fn size(&self) -> usize {
+        match *self {
+            Item::Label(_) => 0,
+            Item::Insn(_, _) => 2,
+            Item::Long(_) => 4,
+            Item::Bytes(ref b) => b.len(),
+        }
+    }
+}
+
+/// Parse a number: hex with "0x", binary with "0b", or decimal.
+fn parse_num(s: &str) -> Option<usize> {
+    if let Some(hex) = s.strip_prefix("0x") {
+        usize::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = s.strip_prefix("0b") {
+        usize::from_str_radix(bin, 2).ok()
+    } else if s.starts_with(|c: char| c.is_ascii_digit()) {
+        s.parse().ok()
+    } else {
+        None
+    }
+}
+
+fn is_label(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        && parse_reg(s).is_err()
+}
+
+/// Parse a register name, "v0" to "vf".
+fn parse_reg(s: &str) -> Result<u16, String> {
+    if s.len() == 2 && (s.starts_with('v') || s.starts_with('V')) {
+        if let Ok(x) = u16::from_str_radix(&s[1..], 16) {
+            return Ok(x);
+        }
+    }
+    Err(format!("expected a register, found '{}'", s))
+}
+
+/// Parse a number which must not be larger than "max".
+fn parse_bounded(s: &str, max: usize) -> Result<u16, String> {
+    match parse_num(s) {
+        Some(n) if n <= max => Ok(n as u16),
+        Some(_) => Err(format!("{} is too large, the maximum is 0x{:x}", s, max)),
+        None => Err(format!("expected a number, found '{}'", s)),
+    }
+}
+
+/// Parse an address which must not be larger than "max".
+fn parse_addr(s: &str, max: usize) -> Result<Addr, String> {
+    if is_label(s) {
+        Ok(Addr::Label(s.to_string()))
+    } else {
+        parse_bounded(s, max).map(Addr::Num)
+    }
+}
+
+/// Opcode of "vX op vY" for an arithmetic or logical operator.
+fn alu_opcode(op: &str) -> Option<u16> {
+    let n = match op {
+        ":=" => 0x0,
+        "|=" => 0x1,
+        "&=" => 0x2,
+        "^=" => 0x3,
+        "+=" => 0x4,
+        "-=" => 0x5,
+        ">>=" => 0x6,
+        "=-" => 0x7,
+        "<<=" => 0xe,
+        _ => return None,
+    };
+    Some(0x8000 | n)
+}
+
+/// Parse one line, without its comment.
+fn parse_line(words: &[&str]) -> Result<Option<Item>, String> {
+    let insn = |opcode: u16| Ok(Some(Item::Insn(opcode, None)));
+    let with_addr = |opcode: u16, a: &str| Ok(Some(Item::Insn(opcode, Some(parse_addr(a, 0xfff)?))));
+    let reg_op = |opcode: u16, r: &str| Ok(Some(Item::Insn(opcode | (parse_reg(r)? << 8), None)));
+    let reg_range = |opcode: u16, x: &str, y: &str| {
+        Ok(Some(Item::Insn(opcode | (parse_reg(x)? << 8) | (parse_reg(y)? << 4), None)))
+    };
+
+    match *words {
+        [] => Ok(None),
+        [":", name] if is_label(name) => Ok(Some(Item::Label(name.to_string()))),
+        [":", name] => Err(format!("bad label name '{}'", name)),
+        ["clear"] => insn(0x00e0),
+        ["return"] => insn(0x00ee),
+        ["scroll-down", n] => insn(0x00c0 | parse_bounded(n, 0xf)?),
+        ["scroll-up", n] => insn(0x00d0 | parse_bounded(n, 0xf)?),
+        ["scroll-right"] => insn(0x00fb),
+        ["scroll-left"] => insn(0x00fc),
+        ["exit"] => insn(0x00fd),
+        ["lores"] => insn(0x00fe),
+        ["hires"] => insn(0x00ff),
+        ["jump", a] => with_addr(0x1000, a),
+        [":call", a] => with_addr(0x2000, a),
+        ["jump0", a] => with_addr(0xb000, a),
+        ["if", x, "-key", "then"] => reg_op(0xe09e, x),
+        ["if", x, "key", "then"] => reg_op(0xe0a1, x),
+        ["if", x, op, rhs, "then"] if op == "==" || op == "!=" => {
+            let x = parse_reg(x)? << 8;
+            let opcode = match (op, parse_reg(rhs)) {
+                ("!=", Ok(y)) => 0x5000 | x | (y << 4),
+                (_, Ok(y)) => 0x9000 | x | (y << 4),
+                ("!=", Err(_)) => 0x3000 | x | parse_bounded(rhs, 0xff)?,
+                (_, Err(_)) => 0x4000 | x | parse_bounded(rhs, 0xff)?,
+            };
+            insn(opcode)
+        },
+        ["i", ":=", "long", a] => Ok(Some(Item::Long(parse_addr(a, 0xffff)?))),
+        ["i", ":=", "hex", x] => reg_op(0xf029, x),
+        ["i", ":=", "bighex", x] => reg_op(0xf030, x),
+        ["i", ":=", a] => with_addr(0xa000, a),
+        ["i", "+=", x] => reg_op(0xf01e, x),
+        ["delay", ":=", x] => reg_op(0xf015, x),
+        ["buzzer", ":=", x] => reg_op(0xf018, x),
+        ["audio"] => insn(0xf002),
+        ["plane", n] => insn(0xf001 | (parse_bounded(n, 0xf)? << 8)),
+        ["sprite", x, y, n] => {
+            let opcode = 0xd000 | (parse_reg(x)? << 8) | (parse_reg(y)? << 4);
+            insn(opcode | parse_bounded(n, 0xf)?)
+        },
+        ["bcd", x] => reg_op(0xf033, x),
+        ["pitch", ":=", x] => reg_op(0xf03a, x),
+        ["save", x] => reg_op(0xf055, x),
+        ["load", x] => reg_op(0xf065, x),
+        ["save", x, "-", y] => reg_range(0x5002, x, y),
+        ["load", x, "-", y] => reg_range(0x5003, x, y),
+        ["saveflags", x] => reg_op(0xf075, x),
+        ["loadflags", x] => reg_op(0xf085, x),
+        [x, ":=", "random", n] => reg_op(0xc000 | parse_bounded(n, 0xff)?, x),
+        [x, ":=", "delay"] => reg_op(0xf007, x),
+        [x, ":=", "key"] => reg_op(0xf00a, x),
+        [x, op, rhs] if parse_reg(x).is_ok() => {
+            let x = parse_reg(x)? << 8;
+            let opcode = match (op, parse_reg(rhs)) {
+                (":=", Err(_)) => 0x6000 | x | parse_bounded(rhs, 0xff)?,
+                ("+=", Err(_)) => 0x7000 | x | parse_bounded(rhs, 0xff)?,
+                (_, Ok(y)) => match alu_opcode(op) {
+                    Some(opcode) => opcode | x | (y << 4),
+                    None => return Err(format!("unknown operator '{}'", op)),
+                },
+                (_, Err(e)) => return Err(e),
+            };
+            insn(opcode)
+        },
+        _ if parse_num(words[0]).is_some() => {
+            let bytes = words.iter()
+                .map(|w| parse_bounded(w, 0xff).map(|b| b as u8))
+                .collect::<Result<Vec<u8>, String>>()?;
+            Ok(Some(Item::Bytes(bytes)))
+        },
+        _ => Err(format!("can not parse '{}'", words.join(" "))),
+    }
+}
+
+/// Assemble "src" into the bytes of a program which is loaded
+/// at address "origin".
+///
+/// Each line holds one instruction, a label definition
+/// (": name") or a list of data bytes. Comments start with
+/// '#'. Labels can be used wherever an address is expected,
+/// before or after they are defined.
+pub fn assemble(src: &str, origin: usize) -> Result<Vec<u8>, AsmError> {
+    let mut items = Vec::new();
+    let mut labels = HashMap::new();
+    let mut addr = origin;
+
+    for (n, line) in src.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let item = parse_line(&words).map_err(|message| AsmError { line: n + 1, message })?;
+        if let Some(item) = item {
+            if let Item::Label(ref name) = item {
+                if labels.insert(name.clone(), addr).is_some() {
+                    return Err(AsmError { line: n + 1,
+                                          message: format!("label '{}' defined twice", name) });
+                }
+            }
+            addr += item.size();
+            items.push((n + 1, item));
+        }
+    }
+
+    // The value of an address operand, which must not be
+    // larger than "max".
+    let resolve = |a: Addr, max: usize, line: usize| match a {
+        Addr::Num(n) => Ok(n),
+        Addr::Label(name) => match labels.get(&name) {
+            Some(&a) if a <= max => Ok(a as u16),
+            Some(_) => Err(AsmError {
+                line, message: format!("label '{}' is out of reach", name) }),
+            None => Err(AsmError {
+                line, message: format!("undefined label '{}'", name) }),
+        },
+    };
+    let mut rom = Vec::new();
+    for (line, item) in items {
+        match item {
+            Item::Label(_) => {},
+            Item::Bytes(bytes) => rom.extend(bytes),
+            Item::Insn(opcode, a) => {
+                let target = match a {
+                    None => 0,
+                    Some(a) => resolve(a, 0xfff, line)?,
+                };
+                let opcode = opcode | target;
+                rom.push((opcode >> 8) as u8);
+                rom.push(opcode as u8);
+            },
+            Item::Long(a) => {
+                let target = resolve(a, 0xffff, line)?;
+                rom.extend_from_slice(&[0xf0, 0x00, (target >> 8) as u8, target as u8]);
+            },
+        }
+    }
+    Ok(rom)
+}
+
+#[cfg(test)]
+#[path="./asm_test.rs"]
+mod asm_test;
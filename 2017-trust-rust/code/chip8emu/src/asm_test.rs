@@ -0,0 +1,97 @@
+use super::*;
+use disasm;
+use std::fs::{self, File};
+use std::io::prelude::*;
+
+fn asm(src: &str) -> Vec<u8> {
+    assemble(src, 0x200).unwrap()
+}
+
+fn asm_err(src: &str) -> AsmError {
+    assemble(src, 0x200).unwrap_err()
+}
+
+#[test]
+fn test_assemble_insns() {
+    assert_eq!(asm("clear"), [0x00, 0xe0]);
+    assert_eq!(asm("scroll-down 0xa"), [0x00, 0xca]);
+    assert_eq!(asm("scroll-up 3"), [0x00, 0xd3]);
+    assert_eq!(asm("if v3 != 0x12 then"), [0x33, 0x12]);
+    assert_eq!(asm("if v3 == 18 then"), [0x43, 0x12]);
+    assert_eq!(asm("if v3 != v4 then"), [0x53, 0x40]);
+    assert_eq!(asm("if v3 == v4 then"), [0x93, 0x40]);
+    assert_eq!(asm("if vA key then"), [0xea, 0xa1]);
+    assert_eq!(asm("v5 := 0b101"), [0x65, 0x05]);
+    assert_eq!(asm("v5 += 1"), [0x75, 0x01]);
+    assert_eq!(asm("v5 += v6"), [0x85, 0x64]);
+    assert_eq!(asm("v5 <<= v6"), [0x85, 0x6e]);
+    assert_eq!(asm("v5 := random 0xff"), [0xc5, 0xff]);
+    assert_eq!(asm("v5 := key"), [0xf5, 0x0a]);
+    assert_eq!(asm("i := 0x123"), [0xa1, 0x23]);
+    assert_eq!(asm("i := hex v2"), [0xf2, 0x29]);
+    assert_eq!(asm("i += v2"), [0xf2, 0x1e]);
+    assert_eq!(asm("sprite v1 v2 15"), [0xd1, 0x2f]);
+    assert_eq!(asm("loadflags v7"), [0xf7, 0x85]);
+    assert_eq!(asm("audio"), [0xf0, 0x02]);
+    assert_eq!(asm("pitch := v7"), [0xf7, 0x3a]);
+    assert_eq!(asm("i := long 0x1234"), [0xf0, 0x00, 0x12, 0x34]);
+    assert_eq!(asm("save v2 - v5"), [0x52, 0x52]);
+    assert_eq!(asm("load v5 - v2"), [0x55, 0x23]);
+    assert_eq!(asm("plane 3"), [0xf3, 0x01]);
+}
+
+#[test]
+fn test_assemble_data_and_comments() {
+    assert_eq!(asm("# a comment\n\n0x12 0x34 5  # data\n"), [0x12, 0x34, 0x05]);
+}
+
+#[test]
+fn test_assemble_labels() {
+    let src = "\
+        jump main\n\
+        : sprite-data\n\
+        0xf0 0x90\n\
+        : main\n\
+        i := sprite-data\n\
+        :call main\n";
+    assert_eq!(asm(src), [0x12, 0x04, 0xf0, 0x90, 0xa2, 0x02, 0x22, 0x04]);
+
+    // "i := long" reaches beyond 0xfff, "jump" does not.
+    assert_eq!(assemble("i := long far\n: far\n", 0x1000).unwrap(), [0xf0, 0x00, 0x10, 0x04]);
+    assert_eq!(assemble("jump far\n: far\n", 0x1000).unwrap_err().message,
+               "label 'far' is out of reach");
+}
+
+#[test]
+fn test_assemble_errors() {
+    assert_eq!(asm_err("clear\nfoo bar"),
+               AsmError { line: 2, message: "can not parse 'foo bar'".to_string() });
+    assert_eq!(asm_err("jump nowhere").message, "undefined label 'nowhere'");
+    assert_eq!(asm_err(": a\n: a").message, "label 'a' defined twice");
+    assert_eq!(asm_err("v0 := 0x100").message, "0x100 is too large, the maximum is 0xff");
+    assert_eq!(asm_err("vg := 1").message, "can not parse 'vg := 1'");
+    assert_eq!(asm_err("v0 %= v1").message, "unknown operator '%='");
+    assert_eq!(asm_err("sprite v0 3 1").message, "expected a register, found '3'");
+    assert_eq!(asm_err("jump 0x1000").message, "0x1000 is too large, the maximum is 0xfff");
+}
+
+#[test]
+fn test_round_trip_insns() {
+    for opcode in 0..=0xffffu32 {
+        let opcode = opcode as u16;
+        let rom = [(opcode >> 8) as u8, opcode as u8];
+        assert_eq!(asm(&disasm::disassemble(&rom, 0x200)), rom, "opcode {:04x}", opcode);
+    }
+}
+
+#[test]
+fn test_round_trip_roms() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/roms");
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let mut rom = Vec::new();
+        File::open(&path).unwrap().read_to_end(&mut rom).unwrap();
+        let src = disasm::disassemble(&rom, 0x200);
+        assert_eq!(asm(&src), rom, "{}", path.display());
+    }
+}
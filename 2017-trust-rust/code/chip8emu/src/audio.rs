@@ -0,0 +1,121 @@
+// audio.rs
+
+// The buzzer. CHIP-8 plays a tone while the sound timer is
+// non-zero; XO-CHIP programs can replace the tone with a
+// pattern of their own.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Size in bytes of the XO-CHIP audio pattern buffer: 128
+/// one bit samples.
+pub const PATTERN_SIZE: usize = 16;
+
+/// Pitch register value at which the audio pattern is played
+/// at PATTERN_BASE_RATE.
+pub const DEFAULT_PITCH: u8 = 64;
+
+/// Samples per second of the audio pattern at DEFAULT_PITCH.
+const PATTERN_BASE_RATE: f64 = 4000.0;
+
+/// Frequency in Hz of the default square wave.
+pub const DEFAULT_FREQUENCY: u32 = 440;
+
+/// Default volume, in percent of the maximum.
+pub const DEFAULT_VOLUME: u32 = 25;
+
+/// Samples per second at which the audio pattern is played
+/// for a given value of the pitch register. Every 48 steps
+/// double the rate, so that 4 steps make a semitone.
+pub fn pattern_rate(pitch: u8) -> f64 {
+    PATTERN_BASE_RATE * 2f64.powf((f64::from(pitch) - f64::from(DEFAULT_PITCH)) / 48.0)
+}
+
+/// What the buzzer plays.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Waveform {
+    /// A square wave at the frequency chosen by the user.
+    Square,
+    /// An XO-CHIP audio pattern: 128 one bit samples, most
+    /// significant bit first, played in a loop at the given
+    /// number of samples per second.
+    Pattern([u8; PATTERN_SIZE], f64),
+}
+
+/// The buzzer attached to the CPU.
+pub trait Audio {
+    /// Start (true) or stop (false) playing. Called once per
+    /// frame and whenever the sound timer is set, so most
+    /// calls do not change anything.
+    fn set_playing(&mut self, playing: bool);
+
+    /// Select what to play from now on. A buzzer starts out
+    /// with Waveform::Square.
+    fn set_waveform(&mut self, waveform: Waveform);
+}
+
+/// A shared buzzer, so that its user can still get at it
+/// after handing it over to the CPU.
+impl<A: Audio + ?Sized> Audio for Rc<RefCell<A>> {
+    fn set_playing(&mut self, playing: bool) {
+        self.borrow_mut().set_playing(playing)
+    }
+
+    fn set_waveform(&mut self, waveform: Waveform) {
+        self.borrow_mut().set_waveform(waveform)
+    }
+}
+
+/// A buzzer which makes no sound.
+pub struct NullAudio;
+
+impl Audio for NullAudio {
+    fn set_playing(&mut self, _playing: bool) {}
+
+    fn set_waveform(&mut self, _waveform: Waveform) {}
+}
+
+/// Something that happened to a RecordingAudio.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AudioEvent {
+    Start,
+    Stop,
+    Waveform(Waveform),
+}
+
+/// A buzzer which remembers what it was asked to play, for
+/// the tests. Calls which do not change anything are not
+/// recorded.
+#[derive(Default)]
+pub struct RecordingAudio {
+    pub events: Vec<AudioEvent>,
+    playing: bool,
+}
+
+impl RecordingAudio {
+    pub fn new() -> RecordingAudio {
+        RecordingAudio { events: Vec::new(), playing: false }
+    }
+
+    /// True if the buzzer is sounding.
+    pub fn playing(&self) -> bool {
+        self.playing
+    }
+}
+
+impl Audio for RecordingAudio {
+    fn set_playing(&mut self, playing: bool) {
+        if playing != self.playing {
+            self.playing = playing;
+            self.events.push(if playing { AudioEvent::Start } else { AudioEvent::Stop });
+        }
+    }
+
+    fn set_waveform(&mut self, waveform: Waveform) {
+        self.events.push(AudioEvent::Waveform(waveform));
+    }
+}
+
+#[cfg(test)]
+#[path="./audio_test.rs"]
+mod audio_test;
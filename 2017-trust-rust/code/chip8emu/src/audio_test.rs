@@ -0,0 +1,25 @@
+use super::*;
+
+#[test]
+fn test_pattern_rate() {
+    assert_eq!(pattern_rate(DEFAULT_PITCH), 4000.0);
+    assert_eq!(pattern_rate(DEFAULT_PITCH + 48), 8000.0);
+    assert_eq!(pattern_rate(DEFAULT_PITCH - 48), 2000.0);
+    // 12 semitones make an octave.
+    let semitone = pattern_rate(DEFAULT_PITCH + 4) / pattern_rate(DEFAULT_PITCH);
+    assert!((semitone.powi(12) - 2.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_recording_audio() {
+    let mut a = RecordingAudio::new();
+    a.set_playing(false);
+    a.set_playing(true);
+    a.set_playing(true);
+    assert!(a.playing());
+    a.set_waveform(Waveform::Square);
+    a.set_playing(false);
+    assert_eq!(a.events, [AudioEvent::Start,
+                          AudioEvent::Waveform(Waveform::Square),
+                          AudioEvent::Stop]);
+}
@@ -0,0 +1,71 @@
+// chip8asm.rs
+
+// Assemble Octo style mnemonics into a ROM, or disassemble
+// a ROM into mnemonics, or analyze the code of a ROM.
+//
+//   chip8asm --disassemble roms/PONG > pong.8o
+//   chip8asm pong.8o --output PONG
+//   chip8asm --analyze roms/PONG
+//   chip8asm --dot roms/PONG | dot -Tsvg > pong.svg
+
+extern crate chip8;
+extern crate structopt;
+#[macro_use]
+extern crate structopt_derive;
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::process;
+
+use chip8::{analyze, asm, disasm};
+use structopt::StructOpt;
+
+/// Programs are loaded at this address.
+const ORIGIN: usize = 0x200;
+
+#[derive(StructOpt, Debug)]
+struct Opt {
+    #[structopt(short = "d", long = "disassemble", help = "Disassemble the input ROM instead of assembling it")]
+    disassemble: bool,
+    #[structopt(long = "analyze", help = "Report the subroutines, self-modifying writes, data and unreachable code of the input ROM")]
+    analyze: bool,
+    #[structopt(long = "dot", help = "Write the control flow graph of the input ROM in Graphviz DOT")]
+    dot: bool,
+    #[structopt(short = "o", long = "output", help = "Name of the output file. Default is stdout")]
+    output: Option<String>,
+    #[structopt(help = "Name of the input file")]
+    input: String,
+}
+
+fn run(opt: &Opt) -> Result<(), String> {
+    let mut input = Vec::new();
+    File::open(&opt.input)
+        .and_then(|mut f| f.read_to_end(&mut input))
+        .map_err(|e| format!("{}: {}", opt.input, e))?;
+
+    let output = if opt.disassemble {
+        disasm::disassemble(&input, ORIGIN).into_bytes()
+    } else if opt.analyze {
+        analyze::analyze(&input, ORIGIN).report().into_bytes()
+    } else if opt.dot {
+        analyze::analyze(&input, ORIGIN).to_dot().into_bytes()
+    } else {
+        let src = String::from_utf8(input).map_err(|_| format!("{}: not UTF-8 text", opt.input))?;
+        asm::assemble(&src, ORIGIN).map_err(|e| format!("{}: {}", opt.input, e))?
+    };
+
+    let written = match opt.output {
+        Some(ref name) => File::create(name).and_then(|mut f| f.write_all(&output)),
+        None => io::stdout().write_all(&output),
+    };
+    written.map_err(|e| format!("failed to write output: {}", e))
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    if let Err(e) = run(&opt) {
+        eprintln!("chip8asm: {}", e);
+        process::exit(1);
+    }
+}
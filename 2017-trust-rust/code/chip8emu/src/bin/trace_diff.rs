@@ -0,0 +1,68 @@
+// trace_diff.rs
+
+// Compare two execution traces, written by "chip8_emu --trace"
+// or converted from the log of another emulator, and report
+// where they first differ:
+//
+//   chip8_emu --gamefile roms/PONG --headless 10000 --seed 1 --trace a.trace
+//   trace-diff a.trace other-emulator.trace
+//
+// The exit status is 0 if the traces agree, 1 if they differ
+// and 2 on errors.
+
+extern crate chip8;
+extern crate structopt;
+#[macro_use]
+extern crate structopt_derive;
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::process;
+
+use chip8::trace;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+struct Opt {
+    #[structopt(help = "The first trace")]
+    a: String,
+    #[structopt(help = "The trace to compare it with")]
+    b: String,
+}
+
+fn read(filename: &str) -> Result<String, String> {
+    let mut trace = String::new();
+    File::open(filename)
+        .and_then(|mut f| f.read_to_string(&mut trace))
+        .map_err(|e| format!("{}: {}", filename, e))?;
+    Ok(trace)
+}
+
+/// Return whether the traces agree.
+fn run(opt: &Opt) -> Result<bool, String> {
+    let (a, b) = (read(&opt.a)?, read(&opt.b)?);
+    let records_a = trace::parse(&a).map_err(|e| format!("{}: {}", opt.a, e))?;
+    let records_b = trace::parse(&b).map_err(|e| format!("{}: {}", opt.b, e))?;
+    match trace::diff(records_a, records_b) {
+        Some(d) => {
+            println!("{}", d);
+            Ok(false)
+        },
+        None => {
+            println!("traces agree");
+            Ok(true)
+        },
+    }
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    match run(&opt) {
+        Ok(true) => {},
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("trace-diff: {}", e);
+            process::exit(2);
+        },
+    }
+}
@@ -0,0 +1,146 @@
+// capture.rs
+
+// Saving what is on the screen: PNG screenshots, and
+// animated GIFs of gameplay.
+
+use std::io;
+use std::io::prelude::*;
+
+use gif;
+use png;
+
+use render::Image;
+use scheduler::FRAME_RATE;
+
+/// Only every CAPTURE_INTERVAL-th frame goes into a GIF.
+/// Browsers slow down GIFs whose frames last less than 2/100
+/// of a second, so 60 frames per second cannot be kept up.
+const CAPTURE_INTERVAL: u64 = 2;
+
+/// Write `img` to `w` as a PNG file.
+pub fn write_png<W: Write>(img: &Image, w: W) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(w, img.width, img.height);
+    encoder.set_color(png::ColorType::RGB);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&img.rgb)?;
+    Ok(())
+}
+
+fn gif_error(e: gif::EncodingError) -> io::Error {
+    match e {
+        gif::EncodingError::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e.to_string()),
+    }
+}
+
+/// Time of the start of frame `n`, in 1/100 s, the unit of
+/// GIF frame delays.
+fn centiseconds(n: u64) -> u64 {
+    n * 100 / FRAME_RATE
+}
+
+/// A GIF frame showing `img`. Pictures of the emulator
+/// have few colours, so they fit in a GIF palette exactly.
+fn gif_frame(img: &Image) -> gif::Frame<'static> {
+    let (width, height) = (img.width as u16, img.height as u16);
+    let mut palette: Vec<&[u8]> = Vec::new();
+    let mut indices = Vec::with_capacity(img.rgb.len() / 3);
+    for color in img.rgb.chunks(3) {
+        let index = match palette.iter().position(|&c| c == color) {
+            Some(index) => index,
+            None => {
+                palette.push(color);
+                palette.len() - 1
+            },
+        };
+        if palette.len() > 256 {
+            return gif::Frame::from_rgb(width, height, &img.rgb);
+        }
+        indices.push(index as u8);
+    }
+    let palette: Vec<u8> = palette.concat();
+    gif::Frame::from_palette_pixels(width, height, &indices, &palette, None)
+}
+
+/// `img` made `width` x `height`: scaled up if it fits
+/// exactly a whole number of times, otherwise cropped or
+/// padded with black at the right and bottom.
+fn fit(img: &Image, width: u32, height: u32) -> Image {
+    let factor = width / img.width;
+    if factor >= 1 && img.width * factor == width && img.height * factor == height {
+        return img.scaled(factor);
+    }
+    let mut rgb = vec![0; (width * height * 3) as usize];
+    for y in 0..height.min(img.height) {
+        let len = (width.min(img.width) * 3) as usize;
+        let (src, dst) = ((y * img.width * 3) as usize, (y * width * 3) as usize);
+        rgb[dst..dst + len].copy_from_slice(&img.rgb[src..src + len]);
+    }
+    Image { width, height, rgb }
+}
+
+/// Records an animated GIF, frame by frame. Frames which
+/// look the same as the previous one are merged into it.
+pub struct GifRecorder<W: Write> {
+    encoder: gif::Encoder<W>,
+    width: u32,
+    height: u32,
+    /// Number of frames captured so far.
+    frames: u64,
+    /// The last picture, not written yet as it may be shown
+    /// for more frames, and the frame it was first shown in.
+    pending: Option<(Image, u64)>,
+}
+
+impl<W: Write> GifRecorder<W> {
+    /// Start recording a `width` x `height` GIF to `w`. It
+    /// loops forever.
+    pub fn new(w: W, width: u32, height: u32) -> io::Result<GifRecorder<W>> {
+        let mut encoder = gif::Encoder::new(w, width as u16, height as u16, &[])
+            .map_err(gif_error)?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(gif_error)?;
+        Ok(GifRecorder { encoder, width, height, frames: 0, pending: None })
+    }
+
+    /// Capture the picture of the next frame. Pictures smaller
+    /// than the GIF, like those of the 64x32 screen in a GIF
+    /// which also shows the 128x64 one, are scaled up to fit.
+    pub fn frame(&mut self, img: &Image) -> io::Result<()> {
+        let n = self.frames;
+        self.frames += 1;
+        if !n.is_multiple_of(CAPTURE_INTERVAL) {
+            return Ok(());
+        }
+        let img = fit(img, self.width, self.height);
+        if let Some((ref last, _)) = self.pending {
+            if *last == img {
+                return Ok(());
+            }
+        }
+        self.flush(n)?;
+        self.pending = Some((img, n));
+        Ok(())
+    }
+
+    /// Write the pending picture, which was shown until
+    /// frame `end`.
+    fn flush(&mut self, end: u64) -> io::Result<()> {
+        if let Some((img, start)) = self.pending.take() {
+            let mut frame = gif_frame(&img);
+            frame.delay = (centiseconds(end) - centiseconds(start)) as u16;
+            self.encoder.write_frame(&frame).map_err(gif_error)?;
+        }
+        Ok(())
+    }
+
+    /// Write the last picture and end the GIF.
+    pub fn finish(mut self) -> io::Result<W> {
+        let end = self.frames;
+        self.flush(end)?;
+        self.encoder.into_inner()
+    }
+}
+
+#[cfg(test)]
+#[path="./capture_test.rs"]
+mod capture_test;
@@ -0,0 +1,54 @@
+use super::*;
+use std::io::Cursor;
+
+fn image(width: u32, height: u32, color: u8) -> Image {
+    Image { width, height, rgb: vec![color; (width * height * 3) as usize] }
+}
+
+#[test]
+fn test_write_png() {
+    let mut img = image(3, 2, 0);
+    img.rgb[3..6].copy_from_slice(&[1, 2, 3]);
+    let mut buf = Vec::new();
+    write_png(&img, &mut buf).unwrap();
+
+    let (info, mut reader) = png::Decoder::new(Cursor::new(buf)).read_info().unwrap();
+    assert_eq!((info.width, info.height), (3, 2));
+    let mut rgb = vec![0; info.buffer_size()];
+    reader.next_frame(&mut rgb).unwrap();
+    assert_eq!(rgb, img.rgb);
+}
+
+/// The (delay, first pixel) of each frame of a GIF.
+fn gif_frames(buf: Vec<u8>) -> Vec<(u16, Vec<u8>)> {
+    let mut opts = gif::DecodeOptions::new();
+    opts.set_color_output(gif::ColorOutput::RGBA);
+    let mut d = opts.read_info(Cursor::new(buf)).unwrap();
+    let mut frames = Vec::new();
+    while let Some(f) = d.read_next_frame().unwrap() {
+        frames.push((f.delay, f.buffer[0..3].to_vec()));
+    }
+    frames
+}
+
+#[test]
+fn test_gif() {
+    let mut g = GifRecorder::new(Vec::new(), 4, 2).unwrap();
+    // 30 frames of black, 6 of white at half the size, then
+    // black again.
+    for n in 0..40 {
+        let img = if (30..36).contains(&n) { image(2, 1, 255) } else { image(4, 2, 0) };
+        g.frame(&img).unwrap();
+    }
+    let frames = gif_frames(g.finish().unwrap());
+    assert_eq!(frames, [(50, vec![0, 0, 0]), (10, vec![255, 255, 255]), (6, vec![0, 0, 0])]);
+}
+
+#[test]
+fn test_fit() {
+    assert_eq!(fit(&image(2, 1, 7), 4, 2), image(4, 2, 7));
+    let padded = fit(&image(3, 1, 7), 4, 2);
+    assert_eq!(&padded.rgb[0..12], &[7, 7, 7, 7, 7, 7, 7, 7, 7, 0, 0, 0]);
+    assert!(padded.rgb[12..].iter().all(|&c| c == 0));
+    assert_eq!(fit(&image(8, 4, 1), 4, 2), image(4, 2, 1));
+}
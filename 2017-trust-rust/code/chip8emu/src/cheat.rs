@@ -0,0 +1,187 @@
+// cheat.rs
+
+// Cheats. To find where a game keeps a value, like the number
+// of lives, memory is searched: every address starts out as a
+// candidate, and each step of the search keeps those whose
+// value compares with the one at the previous step as asked,
+// for example "decreased" after a life is lost. The address
+// found can then be frozen, so that the CPU writes a value to
+// it every frame, or the code changing it patched.
+//
+// Cheats can be kept in a file, where each is a section:
+//
+//   # Cheats for the ROM named GAME.
+//   [infinite lives]
+//   freeze 0x3c5 3
+//
+//   # Make the first jump of the game skip its title screen.
+//   [no title]
+//   patch 0x200 12 40
+//
+// "patch ADDR BYTE..." writes the bytes from ADDR on, once the
+// ROM is loaded, and "freeze ADDR BYTE" writes the byte at
+// ADDR every frame. Addresses and bytes are hex, with or
+// without 0x.
+
+use std::error::Error;
+use std::fmt;
+
+use cpu::CPU;
+
+/// How the value at a candidate address must compare with its
+/// value at the previous step of a search to stay a candidate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Predicate {
+    /// The value is this one, whatever it was before.
+    Equal(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+}
+
+impl Predicate {
+    fn holds(self, old: u8, new: u8) -> bool {
+        match self {
+            Predicate::Equal(val) => new == val,
+            Predicate::Changed => new != old,
+            Predicate::Unchanged => new == old,
+            Predicate::Increased => new > old,
+            Predicate::Decreased => new < old,
+        }
+    }
+}
+
+/// A search of memory for the address of a value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Search {
+    /// The addresses still candidates, with their value at the
+    /// last step.
+    candidates: Vec<(usize, u8)>,
+}
+
+impl Search {
+    /// A search with every address of `mem` as a candidate.
+    pub fn new(mem: &[u8]) -> Search {
+        Search { candidates: mem.iter().cloned().enumerate().collect() }
+    }
+
+    /// Keep the candidates whose value in `mem` satisfies
+    /// `pred`, and remember their new value for the next step.
+    pub fn narrow(&mut self, mem: &[u8], pred: Predicate) {
+        self.candidates.retain(|&(addr, old)| addr < mem.len() && pred.holds(old, mem[addr]));
+        for &mut (addr, ref mut val) in &mut self.candidates {
+            *val = mem[addr];
+        }
+    }
+
+    /// The candidates, as (address, value), by address.
+    pub fn candidates(&self) -> &[(usize, u8)] {
+        &self.candidates
+    }
+}
+
+/// Error returned when a cheat file can not be parsed.
+#[derive(Debug, PartialEq)]
+pub struct CheatError {
+    /// Line number, starting at 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for CheatError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+/// What a cheat does to memory.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    /// Write the bytes from the address on, once.
+    Patch(usize, Vec<u8>),
+    /// Write the byte at the address every frame.
+    Freeze(usize, u8),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cheat {
+    pub name: String,
+    pub actions: Vec<Action>,
+}
+
+impl Cheat {
+    /// Apply the patches of the cheat to the memory of `cpu`,
+    /// and freeze its addresses. Fails, changing nothing, if
+    /// an address is beyond the end of memory.
+    pub fn enable(&self, cpu: &mut CPU) -> Result<(), String> {
+        let size = cpu.mem().len();
+        for action in &self.actions {
+            let (addr, len) = match *action {
+                Action::Patch(addr, ref bytes) => (addr, bytes.len()),
+                Action::Freeze(addr, _) => (addr, 1),
+            };
+            if addr.checked_add(len).is_none_or(|end| end > size) {
+                return Err(format!("cheat '{}': address 0x{:x} is beyond the end of memory", self.name, addr));
+            }
+        }
+        for action in &self.actions {
+            match *action {
+                Action::Patch(addr, ref bytes) => cpu.poke(addr, bytes),
+                Action::Freeze(addr, val) => cpu.freeze(addr, val),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse a hex number, with or without a "0x" prefix.
+fn parse_hex(s: &str) -> Option<usize> {
+    usize::from_str_radix(s.trim_start_matches("0x"), 16).ok()
+}
+
+/// Parse a cheat file.
+pub fn parse(src: &str) -> Result<Vec<Cheat>, CheatError> {
+    let mut cheats: Vec<Cheat> = Vec::new();
+    for (index, line) in src.lines().enumerate() {
+        let err = |message: String| CheatError { line: index + 1, message };
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('[') {
+            if !line.ends_with(']') || line.len() < 3 {
+                return Err(err(format!("bad section header '{}'", line)));
+            }
+            cheats.push(Cheat { name: line[1..line.len() - 1].trim().to_string(), actions: Vec::new() });
+            continue;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let addr = words.get(1).and_then(|a| parse_hex(a));
+        let bytes: Option<Vec<u8>> = words.iter().skip(2)
+            .map(|b| parse_hex(b).filter(|&b| b <= 0xff).map(|b| b as u8))
+            .collect();
+        let action = match (words[0], addr, bytes) {
+            ("patch", Some(addr), Some(bytes)) if !bytes.is_empty() => Action::Patch(addr, bytes),
+            ("freeze", Some(addr), Some(ref bytes)) if bytes.len() == 1 => Action::Freeze(addr, bytes[0]),
+            ("patch", _, _) => return Err(err("usage: patch ADDR BYTE...".to_string())),
+            ("freeze", _, _) => return Err(err("usage: freeze ADDR BYTE".to_string())),
+            _ => return Err(err(format!("expected patch or freeze, found '{}'", words[0]))),
+        };
+        match cheats.last_mut() {
+            Some(cheat) => cheat.actions.push(action),
+            None => return Err(err("expected a [cheat name] before the first patch or freeze".to_string())),
+        }
+    }
+    Ok(cheats)
+}
+
+#[cfg(test)]
+#[path="./cheat_test.rs"]
+mod cheat_test;
@@ -0,0 +1,105 @@
+// cheat_test.rs
+
+use super::*;
+
+#[test]
+fn test_search() {
+    let mut mem = vec![5, 3, 3, 0, 7];
+    let mut s = Search::new(&mem);
+    assert_eq!(s.candidates().len(), 5);
+
+    // A life is lost.
+    mem = vec![5, 2, 3, 0, 6];
+    s.narrow(&mem, Predicate::Decreased);
+    assert_eq!(s.candidates(), &[(1, 2), (4, 6)]);
+    mem = vec![5, 2, 9, 1, 6];
+    s.narrow(&mem, Predicate::Unchanged);
+    assert_eq!(s.candidates(), &[(1, 2), (4, 6)]);
+    mem = vec![5, 1, 9, 1, 6];
+    s.narrow(&mem, Predicate::Changed);
+    assert_eq!(s.candidates(), &[(1, 1)]);
+    mem = vec![5, 3, 9, 1, 6];
+    s.narrow(&mem, Predicate::Increased);
+    assert_eq!(s.candidates(), &[(1, 3)]);
+    s.narrow(&mem, Predicate::Equal(2));
+    assert!(s.candidates().is_empty());
+
+    let mut s = Search::new(&mem);
+    s.narrow(&mem, Predicate::Equal(1));
+    assert_eq!(s.candidates(), &[(3, 1)]);
+}
+
+#[test]
+fn test_parse() {
+    let src = "\
+# Cheats for PONG.
+[infinite lives]
+freeze 0x3c5 3   # the lives
+freeze 3c6 ff
+
+[no title]
+patch 0x200 12 0x40
+";
+    assert_eq!(parse(src), Ok(vec![
+        Cheat { name: "infinite lives".to_string(),
+                actions: vec![Action::Freeze(0x3c5, 3), Action::Freeze(0x3c6, 0xff)] },
+        Cheat { name: "no title".to_string(), actions: vec![Action::Patch(0x200, vec![0x12, 0x40])] },
+    ]));
+    assert_eq!(parse("# nothing\n"), Ok(vec![]));
+}
+
+#[test]
+fn test_parse_errors() {
+    let err = |src: &str| parse(src).unwrap_err();
+    assert_eq!(err("freeze 0x3c5 3"), CheatError {
+        line: 1, message: "expected a [cheat name] before the first patch or freeze".to_string() });
+    assert_eq!(err("[a]\n\nfreeze 0x3c5").line, 3);
+    assert_eq!(err("[a]\nfreeze 0x3c5 3 4").message, "usage: freeze ADDR BYTE");
+    assert_eq!(err("[a]\npatch 0x200").message, "usage: patch ADDR BYTE...");
+    assert_eq!(err("[a]\npatch 0x200 100").message, "usage: patch ADDR BYTE...");
+    assert_eq!(err("[a]\npoke 0x200 1").message, "expected patch or freeze, found 'poke'");
+    assert_eq!(err("[a").message, "bad section header '[a'");
+}
+
+#[test]
+fn test_enable() {
+    let mut c = CPU::headless();
+    let cheat = Cheat {
+        name: "test".to_string(),
+        actions: vec![Action::Patch(0x200, vec![0x12, 0x00]), Action::Freeze(0x300, 9)],
+    };
+    cheat.enable(&mut c).unwrap();
+    assert_eq!(&c.mem()[0x200..0x202], &[0x12, 0x00]);
+    assert_eq!(c.mem()[0x300], 9);
+
+    // The program changes the frozen value, which is written
+    // back at the next frame.
+    c.poke(0x300, &[1]);
+    c.vblank();
+    assert_eq!(c.mem()[0x300], 9);
+    assert!(c.unfreeze(0x300));
+    assert!(!c.unfreeze(0x300));
+    c.poke(0x300, &[1]);
+    c.vblank();
+    assert_eq!(c.mem()[0x300], 1);
+
+    let beyond = Cheat {
+        name: "beyond".to_string(),
+        actions: vec![Action::Freeze(0x301, 1), Action::Patch(0xfff, vec![1, 2])],
+    };
+    assert_eq!(beyond.enable(&mut c),
+               Err("cheat 'beyond': address 0xfff is beyond the end of memory".to_string()));
+    assert_eq!(c.mem()[0x301], 0);
+    assert!(c.frozen().is_empty());
+    let overflow = Cheat { name: "overflow".to_string(), actions: vec![Action::Freeze(usize::MAX, 1)] };
+    assert!(overflow.enable(&mut c).is_err());
+
+    // An address beyond the end of memory wraps around.
+    c.freeze(0x1300, 2);
+    assert_eq!(c.mem()[0x300], 2);
+    assert!(c.unfreeze(0x1300));
+    assert!(c.frozen().is_empty());
+    c.poke(usize::MAX, &[3, 4]);
+    assert_eq!(c.mem()[0xfff], 3);
+    assert_eq!(c.mem()[0], 4);
+}
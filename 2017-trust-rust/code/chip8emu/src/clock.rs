@@ -0,0 +1,72 @@
+// clock.rs
+
+// Sources of time for the frame scheduler.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub trait Clock {
+    /// Time elapsed since some fixed point in the past.
+    /// It never goes backwards.
+    fn now(&self) -> Duration;
+
+    /// Wait for "d".
+    fn sleep(&mut self, d: Duration);
+}
+
+/// The host's monotonic clock.
+pub struct MonotonicClock {
+    start: Instant,
+}
+
+impl MonotonicClock {
+    pub fn new() -> MonotonicClock {
+        MonotonicClock { start: Instant::now() }
+    }
+}
+
+impl Default for MonotonicClock {
+    fn default() -> MonotonicClock {
+        MonotonicClock::new()
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&mut self, d: Duration) {
+        thread::sleep(d);
+    }
+}
+
+/// A clock which only moves when it is told to, so that runs
+/// are deterministic. Sleeping returns at once and moves the
+/// clock forward.
+#[derive(Default)]
+pub struct VirtualClock {
+    now: Duration,
+}
+
+impl VirtualClock {
+    pub fn new() -> VirtualClock {
+        VirtualClock { now: Duration::from_secs(0) }
+    }
+
+    /// Move the clock forward by "d", as if the host had been
+    /// busy for that long.
+    pub fn advance(&mut self, d: Duration) {
+        self.now += d;
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Duration {
+        self.now
+    }
+
+    fn sleep(&mut self, d: Duration) {
+        self.advance(d);
+    }
+}
@@ -4,16 +4,41 @@
 /// (1) <http://devernay.free.fr/hacks/chip8/C8TECH10.HTM>
 /// (2) <https://en.wikipedia.org/wiki/CHIP-8>
 
+use std::cmp;
+use std::collections::BTreeMap;
+use std::io;
 use std::io::prelude::*;
 use std::fs::File;
-use std::collections::HashMap;
-use rand;
-
-use screen;
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use audio::{self, Audio, NullAudio, Waveform, PATTERN_SIZE};
+use error::{Chip8Error, FaultPolicy};
+use display::{Display, Keypad, KeyState, NUM_KEYS, SCREEN_WIDTH, SCREEN_HEIGHT};
+use display::{HIRES_SCREEN_WIDTH, HIRES_SCREEN_HEIGHT};
+use font;
+use headless;
+use insn::{self, Instruction};
+use jit::BlockCache;
+use profile::Profiler;
+use quirks::Quirks;
+use state::{self, State};
 
 /// CHIP-8 Memory is 4K bytes in size
 const MEM_SIZE: usize = 4096;
 
+/// XO-CHIP memory is 64K bytes in size, all of it reachable
+/// with "f000 nnnn". Used with the "large_memory" quirk.
+const LARGE_MEM_SIZE: usize = 0x10000;
+
+/// "i" is a 16 bit register, as on XO-CHIP: adding to it
+/// wraps around past 0xffff.
+const I_MASK: usize = 0xffff;
+
+/// The XO-CHIP display has 2 bit planes. Bit n of a pixel
+/// value is the pixel on plane n, so the value selects one of
+/// 4 colours.
+const ALL_PLANES: u8 = 0b11;
+
 /// There are 16 general purpose registers in the CHIP-8,
 /// named V0 to VF. VF is used as a flag register in some
 /// instructions and it is better to avoid using it for 
@@ -23,7 +48,7 @@ const NUM_REGS: usize = 16;
 /// The stack has 24 entries, each one 2 bytes long, for a 
 /// total size of 48 bytes.
 /// 
-/// It occupies locations from 0xea0 to 0xeff, both inclusive.
+/// It occupies locations from 0xea0 to 0xecf, both inclusive.
 /// 
 /// A push operation first increments the stack pointer by 2
 /// and then stores a value at the location that is now pointed
@@ -31,17 +56,39 @@ const NUM_REGS: usize = 16;
 /// should store the value at 0xea0.
 const SP_BOTTOM: usize = 0xe9e;
 
+/// Number of entries in the stack.
+const STACK_SIZE: usize = 24;
+
+/// The stack pointer when the stack is full.
+const SP_TOP: usize = SP_BOTTOM + 2 * STACK_SIZE;
+
 /// Machine code is stored in memory starting at location
 /// 0x200.
 pub const PC_START: usize = 0x200;
 
-/// The Instruction Pointer type 
-type InsnPtr = fn(&mut CPU) -> ();
+/// SUPER-CHIP has 8 "RPL user flags", named after the HP48
+/// calculator registers they were stored in. Programs use
+/// them to keep data, like high scores, between runs.
+const NUM_RPL_FLAGS: usize = 8;
+
+/// A random number generator started from `seed`. The seed
+/// is scrambled first (with the SplitMix64 finalizer), as
+/// xorshift gives similar first numbers for similar seeds and
+/// must not be seeded with all zeroes.
+fn seeded_rng(seed: u64) -> XorShiftRng {
+    let mix = |mut z: u64| {
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    let (a, b) = (mix(seed), mix(seed ^ 0x9e37_79b9_7f4a_7c15) | 1);
+    XorShiftRng::from_seed([a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32])
+}
 
 pub struct CPU {
-    /// 4K Memory. 2 byte objects are stored in big-endian
-    /// format.
-    mem: [u8; MEM_SIZE],
+    /// 4K Memory, or 64K with the "large_memory" quirk. 2 byte
+    /// objects are stored in big-endian format.
+    mem: Vec<u8>,
     
     /// The 16 general purpose registers, 8 bits wide.
     v: [u8; NUM_REGS],
@@ -57,9 +104,17 @@ pub struct CPU {
     /// from CHIP-8 programs.
     sp: usize,
 
-    /// The CPU has a screen attached to it. During
-    /// unit tests, the value of this field will be None.
-    screen: Option<screen::Screen>,
+    /// The display attached to the CPU. During unit
+    /// tests, this is an in-memory framebuffer.
+    display: Box<dyn Display>,
+
+    /// The keypad attached to the CPU. During unit
+    /// tests, keys are pressed by the test itself.
+    keypad: Box<dyn Keypad>,
+
+    /// The key pressed while "fx0a" waits, once there is one.
+    /// The instruction completes when this key is released.
+    key_wait: Option<u8>,
 
     /// The delay timer.
     delay: u8,
@@ -67,22 +122,363 @@ pub struct CPU {
     /// The sound timer.
     sound: u8,
 
+    /// The buzzer, which sounds while the sound timer is
+    /// non-zero.
+    audio: Box<dyn Audio>,
+
+    /// The XO-CHIP audio pattern, once a program has loaded
+    /// one with "f002". Until then the buzzer plays a square
+    /// wave.
+    pattern: Option<[u8; PATTERN_SIZE]>,
+
+    /// The XO-CHIP pitch register, which sets the playback
+    /// rate of the audio pattern.
+    pitch: u8,
+
+    /// Interpreter specific behaviour of some instructions.
+    quirks: Quirks,
+
+    /// What to do when an instruction faults.
+    fault_policy: FaultPolicy,
+
+    /// True if the SUPER-CHIP high resolution mode is on.
+    hires: bool,
+
+    /// The XO-CHIP bit planes that drawing, clearing and
+    /// scrolling act on, as a mask of ALL_PLANES. Only the
+    /// first one to begin with, so that CHIP-8 programs see
+    /// a display of one plane.
+    planes: u8,
+
+    /// True once the program has executed the SUPER-CHIP
+    /// "exit" instruction.
+    exited: bool,
+
+    /// The SUPER-CHIP RPL user flags.
+    rpl: [u8; NUM_RPL_FLAGS],
+
+    /// The file the RPL user flags are saved to, if any.
+    rpl_file: Option<String>,
+
+    /// True if a vertical blank happened since the last sprite
+    /// was drawn. Only used with the "display_wait" quirk.
+    vblank: bool,
+
+    /// Source of the random numbers of "cxnn". Seeded with
+    /// `set_seed`, so that a run can be reproduced.
+    rng: XorShiftRng,
+
+    /// Instructions decoded by `execute_cached`.
+    cache: BlockCache,
+
+    /// Counts the instructions executed, if profiling.
+    profiler: Option<Box<Profiler>>,
+
+    /// Values written to memory every frame by cheats, by
+    /// address.
+    frozen: BTreeMap<usize, u8>,
 } 
 
 impl CPU {
-    pub fn new(screen: Option<screen::Screen>) -> Self {
+    pub fn new(display: Box<dyn Display>, keypad: Box<dyn Keypad>) -> Self {
+        let mut mem = vec![0; MEM_SIZE];
+        mem[font::FONT_START..font::FONT_START + font::FONT.len()]
+            .copy_from_slice(&font::FONT);
+        mem[font::BIG_FONT_START..font::BIG_FONT_START + font::BIG_FONT.len()]
+            .copy_from_slice(&font::BIG_FONT);
         CPU { 
-            mem: [0; MEM_SIZE],
+            mem,
             v: [0; NUM_REGS],
             i: 0,
             pc: PC_START,
             sp: SP_BOTTOM,
-            screen: screen,
+            display,
+            keypad,
+            key_wait: None,
             delay: 0,
             sound: 0,
+            audio: Box::new(NullAudio),
+            pattern: None,
+            pitch: audio::DEFAULT_PITCH,
+            quirks: Quirks::default(),
+            fault_policy: FaultPolicy::default(),
+            hires: false,
+            planes: 1,
+            exited: false,
+            rpl: [0; NUM_RPL_FLAGS],
+            rpl_file: None,
+            vblank: true,
+            rng: seeded_rng(0),
+            cache: BlockCache::new(MEM_SIZE),
+            profiler: None,
+            frozen: BTreeMap::new(),
+        }
+    }
+
+    /// Restart the random number generator from `seed`. Two
+    /// CPUs given the same seed draw the same numbers. The
+    /// CPU starts out with seed 0, as there is no source of
+    /// randomness on every platform (WebAssembly has none);
+    /// frontends pick a random seed themselves.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = seeded_rng(seed);
+    }
+
+    /// Count the instructions executed from now on, by address,
+    /// opcode, frame and subroutine.
+    pub fn enable_profiler(&mut self) {
+        self.profiler = Some(Box::new(Profiler::new(self.pc)));
+    }
+
+    /// The profile, if profiling.
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_deref()
+    }
+
+    /// Save the RPL user flags to `filename` whenever the
+    /// program stores them. Flags saved by a previous run
+    /// are loaded from it if it exists. Fails, changing
+    /// nothing, if it exists but can not be read.
+    pub fn set_rpl_file(&mut self, filename: &str) -> io::Result<()> {
+        let mut buf = Vec::new();
+        match File::open(filename).and_then(|mut f| f.read_to_end(&mut buf)) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e),
+            Ok(_) => {
+                for (index, val) in buf.iter().take(NUM_RPL_FLAGS).enumerate() {
+                    self.rpl[index] = *val;
+                }
+            },
+        }
+        self.rpl_file = Some(filename.to_string());
+        Ok(())
+    }
+
+    /// Attach a buzzer. The CPU starts out with one which
+    /// makes no sound.
+    pub fn set_audio(&mut self, audio: Box<dyn Audio>) {
+        self.audio = audio;
+        self.audio.set_waveform(self.waveform());
+        self.update_sound();
+    }
+
+    /// Take a snapshot of the CPU and the display.
+    pub fn save_state(&self) -> State {
+        let (width, height, pixels) = self.framebuffer();
+        State {
+            mem: self.mem.clone(),
+            v: self.v.to_vec(),
+            i: self.i,
+            pc: self.pc,
+            sp: self.sp,
+            delay: self.delay,
+            sound: self.sound,
+            pattern: self.pattern.map_or(Vec::new(), |p| p.to_vec()),
+            pitch: self.pitch,
+            hires: self.hires,
+            planes: self.planes,
+            exited: self.exited,
+            rpl: self.rpl.to_vec(),
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Restore a snapshot taken by `save_state`. The CPU is
+    /// left unchanged if the snapshot does not fit this machine.
+    pub fn load_state(&mut self, st: &State) -> io::Result<()> {
+        let hires_size = (u32::from(HIRES_SCREEN_WIDTH), u32::from(HIRES_SCREEN_HEIGHT));
+        let lores_size = (u32::from(SCREEN_WIDTH), u32::from(SCREEN_HEIGHT));
+        let size = if st.hires { hires_size } else { lores_size };
+        if st.mem.len() != self.mem.len() || st.v.len() != NUM_REGS ||
+            st.sp < SP_BOTTOM || st.sp > SP_TOP || (st.sp - SP_BOTTOM) & 1 != 0 ||
+            st.rpl.len() != NUM_RPL_FLAGS || (st.width, st.height) != size ||
+            st.pixels.len() != (st.width * st.height) as usize ||
+            !(st.pattern.is_empty() || st.pattern.len() == PATTERN_SIZE) ||
+            st.planes & !ALL_PLANES != 0 {
+            return Err(state::invalid_data("save state does not fit this machine"));
+        }
+        for (addr, (old, new)) in self.mem.iter().zip(&st.mem).enumerate() {
+            if old != new {
+                self.cache.invalidate(addr, 1);
+            }
+        }
+        self.mem.copy_from_slice(&st.mem);
+        self.v.copy_from_slice(&st.v);
+        self.i = st.i;
+        self.pc = st.pc;
+        self.sp = st.sp;
+        self.delay = st.delay;
+        self.sound = st.sound;
+        self.pattern = if st.pattern.is_empty() {
+            None
+        } else {
+            let mut pattern = [0; PATTERN_SIZE];
+            pattern.copy_from_slice(&st.pattern);
+            Some(pattern)
+        };
+        self.pitch = st.pitch;
+        self.audio.set_waveform(self.waveform());
+        self.update_sound();
+        self.exited = st.exited;
+        self.key_wait = None;
+        self.rpl.copy_from_slice(&st.rpl);
+        self.set_hires(st.hires);
+        self.planes = st.planes;
+        for y in 0..st.height {
+            for x in 0..st.width {
+                let val = st.pixels[(y * st.width + x) as usize];
+                self.display.draw_pixel(x, y, val);
+            }
+        }
+        self.display.present();
+        Ok(())
+    }
+
+    /// The width and height of the display, and the value of
+    /// each of its pixels, row by row.
+    pub fn framebuffer(&self) -> (u32, u32, Vec<u8>) {
+        let (width, height) = (self.screen_width(), self.screen_height());
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(self.display.get_pixel(x, y));
+            }
+        }
+        (width, height, pixels)
+    }
+
+    /// Return true if the program has exited.
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    /// The Program Counter.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// The address register.
+    pub fn i(&self) -> usize {
+        self.i
+    }
+
+    /// The Stack Pointer.
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
+    /// The 16 general purpose registers.
+    pub fn v(&self) -> &[u8] {
+        &self.v
+    }
+
+    /// The whole memory.
+    pub fn mem(&self) -> &[u8] {
+        &self.mem
+    }
+
+    /// The XO-CHIP bit planes selected with "fn01", as a mask.
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    /// The delay timer.
+    pub fn delay(&self) -> u8 {
+        self.delay
+    }
+
+    /// The sound timer.
+    pub fn sound(&self) -> u8 {
+        self.sound
+    }
+
+    /// What the buzzer plays when the sound timer is non-zero.
+    pub fn waveform(&self) -> Waveform {
+        match self.pattern {
+            Some(p) => Waveform::Pattern(p, audio::pattern_rate(self.pitch)),
+            None => Waveform::Square,
         }
     }
 
+    /// The return addresses on the stack, from the bottom
+    /// of the stack to the top.
+    pub fn stack(&self) -> Vec<usize> {
+        let mut addrs = Vec::new();
+        let mut p = SP_BOTTOM + 2;
+        while p <= self.sp && p + 1 < self.mem.len() {
+            addrs.push((usize::from(self.mem[p]) << 8) | usize::from(self.mem[p + 1]));
+            p += 2;
+        }
+        addrs
+    }
+
+    /// Width of the screen in the current resolution.
+    fn screen_width(&self) -> u32 {
+        u32::from(if self.hires { HIRES_SCREEN_WIDTH } else { SCREEN_WIDTH })
+    }
+
+    /// Height of the screen in the current resolution.
+    fn screen_height(&self) -> u32 {
+        u32::from(if self.hires { HIRES_SCREEN_HEIGHT } else { SCREEN_HEIGHT })
+    }
+
+    /// Select the behaviour of the instructions on which
+    /// CHIP-8 interpreters disagree. A change of the
+    /// "large_memory" quirk resizes the memory, keeping what
+    /// fits in the new size; it is meant to be set before the
+    /// ROM is loaded.
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+        let size = if quirks.large_memory { LARGE_MEM_SIZE } else { MEM_SIZE };
+        if size != self.mem.len() {
+            self.mem.resize(size, 0);
+            self.cache = BlockCache::new(size);
+        }
+    }
+
+    /// Write `bytes` to memory from `addr` on, from outside the
+    /// program, as cheats and the debugger do. Addresses
+    /// beyond the end of memory wrap around.
+    pub fn poke(&mut self, addr: usize, bytes: &[u8]) {
+        let addr = addr % self.mem.len();
+        for (n, &b) in bytes.iter().enumerate() {
+            self.write_mem(addr + n, b);
+        }
+    }
+
+    /// Write `val` at `addr` now and at every vertical blank
+    /// from now on, so that the program can not change it for
+    /// long.
+    pub fn freeze(&mut self, addr: usize, val: u8) {
+        let addr = addr % self.mem.len();
+        self.frozen.insert(addr, val);
+        self.write_mem(addr, val);
+    }
+
+    /// Stop writing to `addr`, which wraps like in `freeze`,
+    /// every frame. Return false if it was not frozen.
+    pub fn unfreeze(&mut self, addr: usize) -> bool {
+        self.frozen.remove(&(addr % self.mem.len())).is_some()
+    }
+
+    /// The frozen addresses, with their values.
+    pub fn frozen(&self) -> &BTreeMap<usize, u8> {
+        &self.frozen
+    }
+
+    /// Select what happens when an instruction faults.
+    pub fn set_fault_policy(&mut self, policy: FaultPolicy) {
+        self.fault_policy = policy;
+    }
+
+    /// A CPU with an in-memory framebuffer and a keypad
+    /// on which no key is ever pressed.
+    pub fn headless() -> Self {
+        CPU::new(Box::new(headless::Framebuffer::new()), Box::new(KeyState::new()))
+    }
+
     /// Increment the program counter.
     /// download?logged_out=1&lang=en
     /// Each instruction is 2 bytes long, so 
@@ -91,24 +487,17 @@ impl CPU {
         self.pc += 2 * n;
     }
     
-    /// Get the 12 bit memory address encoded as part of 
-    /// the instruction.
-    /// 
-    /// If you have an instruction of the form "1nnn", you
-    /// need to get "nnn", the 12 bit address.
-    fn get_address(&self) -> usize {
-        let address_high_nibble = self.mem[self.pc] & 0xf;
-        let address_low_byte = self.mem[self.pc + 1];
-        ((address_high_nibble as usize) << 8) + address_low_byte as usize    
-
+    /// Read the byte at `addr`. Addresses beyond the end
+    /// of memory wrap around; see `check`.
+    fn read_mem(&self, addr: usize) -> u8 {
+        self.mem[addr % self.mem.len()]
     }
 
-    /// Get the 8 bit constant encoded as part of the instruction.
-    /// 
-    /// If you have an instruction say "7xnn", this function 
-    /// returns "nn".
-    fn get_constant(&self) -> u8 {
-        self.mem[self.pc + 1] 
+    /// Write `val` at `addr`, which wraps like in `read_mem`.
+    fn write_mem(&mut self, addr: usize, val: u8) {
+        let addr = addr % self.mem.len();
+        self.mem[addr] = val;
+        self.cache.invalidate(addr, 1);
     }
 
     /// Copy 2 bytes from a usize value to top-of-stack.
@@ -116,6 +505,7 @@ impl CPU {
     fn copy_16bits_to_tos(&mut self, src: usize) {
         self.mem[self.sp] = ((src >> 8) & 0xffusize) as u8;
         self.mem[self.sp + 1] = (src & 0xffusize) as u8;        
+        self.cache.invalidate(self.sp, 2);
     }
 
     /// Return the 2 byte value taken from top-of-stack.
@@ -123,22 +513,10 @@ impl CPU {
         ((self.mem[self.sp] as usize) << 8) | (self.mem[self.sp + 1] as usize)
     }
 
-    /// Return the lower nibble of the high byte of the 
-    /// instruction pointed to by PC.
-    fn nibble_x(&self) -> usize {
-        (self.mem[self.pc] & 0xf) as usize
-    }
-
-    /// Return the high nibble of the low byte of the
-    /// instruction pointed to by PC.
-    fn nibble_y(&self) -> usize {
-        ((self.mem[self.pc + 1] >> 4) & 0xf) as usize
-    }
-
     /// Execute a jump instruction of the form "1nnn"
     /// where nnn represents a memory address.
-    fn jmp(&mut self) {
-        self.pc = self.get_address();
+    fn jmp(&mut self, addr: usize) {
+        self.pc = addr;
     }
 
     /// Call subroutine.
@@ -148,26 +526,45 @@ impl CPU {
     /// the stack pointer by 2 and copies the address of the next
     /// instruction to the new location on the stack. It then sets
     /// the program counter to "nnn".
-    fn call(&mut self) {
-        let target_address = self.get_address();
+    /// 
+    /// With the stack full, the "wrap" fault policy makes the
+    /// call overwrite the oldest entry.
+    fn call(&mut self, addr: usize) {
         let next_insn_address = self.pc + 2;
+        if self.sp >= SP_TOP {
+            self.sp = SP_BOTTOM;
+        }
         self.sp += 2;
         self.copy_16bits_to_tos(next_insn_address);
-        self.pc = target_address;
+        self.pc = addr;
     }
 
     /// Subroutine return. Opcode "0x00ee".
+    /// 
+    /// With the stack empty, the "wrap" fault policy makes it
+    /// return to the address in the top entry.
     fn ret(&mut self) {
+        if self.sp <= SP_BOTTOM {
+            self.sp = SP_TOP;
+        }
         self.pc = self.get_16bits_from_tos();
         self.sp -= 2;
     }
 
+    /// Skip the next instruction: 4 bytes, or 6 if it is the
+    /// 4 byte XO-CHIP "f000 nnnn".
+    fn skip_next_insn(&mut self) {
+        let pc = self.pc;
+        let long = self.read_mem(pc + 2) == 0xf0 && self.read_mem(pc + 3) == 0x00;
+        self.inc_pc(if long { 3 } else { 2 });
+    }
+
     /// Skip next instruction if v[x] == nn.
     /// 
     /// This instruction is of the form "3xnn".
-    fn skip_if_vx_eq_nn(&mut self) {
-        if self.v[self.nibble_x()] == self.get_constant() {
-            self.inc_pc(2); // skip next instruction
+    fn skip_if_vx_eq_nn(&mut self, x: usize, nn: u8) {
+        if self.v[x] == nn {
+            self.skip_next_insn();
             return;
         }
         self.inc_pc(1);
@@ -176,9 +573,9 @@ impl CPU {
     /// Skip next instruction if v[x] != nn.
     /// 
     /// This instruction is of the form "4xnn".
-    fn skip_if_vx_ne_nn(&mut self) {
-        if self.v[self.nibble_x()] != self.get_constant() {
-            self.inc_pc(2); // skip next instruction
+    fn skip_if_vx_ne_nn(&mut self, x: usize, nn: u8) {
+        if self.v[x] != nn {
+            self.skip_next_insn();
             return;
         }
         self.inc_pc(1);
@@ -187,9 +584,9 @@ impl CPU {
     /// Skip the next instruction if v[x] == v[y].
     /// 
     /// This instruction is of the form "5xy0".
-    fn skip_if_vx_eq_vy(&mut self) {
-        if self.v[self.nibble_x()] == self.v[self.nibble_y()] {
-            self.inc_pc(2); // skip next instruction
+    fn skip_if_vx_eq_vy(&mut self, x: usize, y: usize) {
+        if self.v[x] == self.v[y] {
+            self.skip_next_insn();
             return;
         }
         self.inc_pc(1);
@@ -198,65 +595,73 @@ impl CPU {
     /// Set v[x] to nn.
     /// 
     /// This instruction is of the form "6xnn".
-    fn set_vx_to_nn(&mut self) {
-        self.v[self.nibble_x()] = self.get_constant();
+    fn set_vx_to_nn(&mut self, x: usize, nn: u8) {
+        self.v[x] = nn;
         self.inc_pc(1);
     }
 
     /// Add nn to v[x] without changing carry.
     /// 
     /// This instruction is of the form "7xnn"
-    fn add_nn_to_vx(&mut self) {
-        self.v[self.nibble_x()] = self.v[self.nibble_x()]
-                                      .wrapping_add(self.get_constant());
+    fn add_nn_to_vx(&mut self, x: usize, nn: u8) {
+        self.v[x] = self.v[x].wrapping_add(nn);
         self.inc_pc(1);
     }
 
     /// Assign v[y] to v[x]
     /// 
     /// This instruction is of the form "8xy0"
-    fn assign_vy_to_vx(&mut self) {
-        self.v[self.nibble_x()] = self.v[self.nibble_y()];
+    fn assign_vy_to_vx(&mut self, x: usize, y: usize) {
+        self.v[x] = self.v[y];
         self.inc_pc(1);
     }
 
+    /// Clear v[f] after a logic instruction, if the
+    /// "vf_reset" quirk is enabled.
+    fn reset_vf_after_logic_op(&mut self) {
+        if self.quirks.vf_reset {
+            self.v[0xf] = 0;
+        }
+    }
+
     /// v[x] = v[x] | v[y]
     /// 
     /// This instruction is of the form "8xy1"
-    fn assign_vx_or_vy_to_vx(&mut self) {
-        self.v[self.nibble_x()] = self.v[self.nibble_x()] | self.v[self.nibble_y()];
+    fn assign_vx_or_vy_to_vx(&mut self, x: usize, y: usize) {
+        self.v[x] |= self.v[y];
+        self.reset_vf_after_logic_op();
         self.inc_pc(1);
     }
 
     /// v[x] = v[x] & v[y]
     /// 
     /// This instruction is of the form "8xy2"
-    fn assign_vx_and_vy_to_vx(&mut self) {
-        self.v[self.nibble_x()] = self.v[self.nibble_x()] & self.v[self.nibble_y()];
+    fn assign_vx_and_vy_to_vx(&mut self, x: usize, y: usize) {
+        self.v[x] &= self.v[y];
+        self.reset_vf_after_logic_op();
         self.inc_pc(1);
     }
 
     /// v[x] = v[x] ^ v[y]
     /// 
     /// This instruction is of the form "8xy3"
-    fn assign_vx_xor_vy_to_vx(&mut self) {
-        self.v[self.nibble_x()] = self.v[self.nibble_x()] ^ self.v[self.nibble_y()];
+    fn assign_vx_xor_vy_to_vx(&mut self, x: usize, y: usize) {
+        self.v[x] ^= self.v[y];
+        self.reset_vf_after_logic_op();
         self.inc_pc(1);
     }
 
     /// v[x] = v[x] + v[y]. v[f] set to 1 if there is a carry,
     /// otherwise set to 0.
     /// 
+    /// Like in every instruction which sets a flag, v[f] is set
+    /// after v[x], so it holds the flag when x is f.
+    /// 
     /// This instruction is of the form "8xy4"
-    fn assign_vx_plus_vy_to_vx(&mut self) {
-        let r = u16::from(self.v[self.nibble_x()]) + u16::from(self.v[self.nibble_y()]);
-        self.v[0xf] = 0;
-        if r > 255 {
-            self.v[self.nibble_x()] = (r - 256u16) as u8;
-            self.v[0xf] = 1; // carry flag set to 1
-        } else {
-            self.v[self.nibble_x()] = r as u8;
-        }
+    fn assign_vx_plus_vy_to_vx(&mut self, x: usize, y: usize) {
+        let (r, carry) = self.v[x].overflowing_add(self.v[y]);
+        self.v[x] = r;
+        self.v[0xf] = carry as u8;
         self.inc_pc(1);
     }
 
@@ -264,13 +669,13 @@ impl CPU {
     /// v[f] is set to 1 if there is NO borrow. Set to 0 otherwise.
     /// 
     /// This instruction has the form "8xy5".
-    fn assign_vx_minus_vy_to_vx(&mut self) {
-        let (vx, vy) = (self.v[self.nibble_x()], self.v[self.nibble_y()]);
+    fn assign_vx_minus_vy_to_vx(&mut self, x: usize, y: usize) {
+        let (vx, vy) = (self.v[x], self.v[y]);
         if vx >= vy { // No borrow
-            self.v[self.nibble_x()] = vx - vy;
+            self.v[x] = vx - vy;
             self.v[0xf] = 1;
         } else {
-            self.v[self.nibble_x()] = vx.wrapping_sub(vy);
+            self.v[x] = vx.wrapping_sub(vy);
             self.v[0xf] = 0;
         }
         self.inc_pc(1);
@@ -286,10 +691,13 @@ impl CPU {
     /// and the instruction described in the Wikipedia page. This
     /// implementation follows the Python version available here:
     /// <https://github.com/craigthomas/Chip8Python/blob/master/chip8/cpu.py>
-    fn shr_vx(&mut self) {
-        let vx = self.v[self.nibble_x()];
-        self.v[0xf] = vx & 1;
-        self.v[self.nibble_x()] = vx >> 1;
+    /// With the "shift_uses_vy" quirk, v[x] = v[y] >> 1 as described
+    /// in the Wikipedia page.
+    fn shr_vx(&mut self, x: usize, y: usize) {
+        let src = if self.quirks.shift_uses_vy { y } else { x };
+        let vs = self.v[src];
+        self.v[x] = vs >> 1;
+        self.v[0xf] = vs & 1;
         self.inc_pc(1);
     }
     
@@ -297,13 +705,13 @@ impl CPU {
     /// otherwise set to 0.
     /// 
     /// This instruction is of the form: "8xy7"
-    fn assign_vy_minus_vx_to_vx(&mut self) {
-        let (vx, vy) = (self.v[self.nibble_x()], self.v[self.nibble_y()]);
+    fn assign_vy_minus_vx_to_vx(&mut self, x: usize, y: usize) {
+        let (vx, vy) = (self.v[x], self.v[y]);
         if vy >= vx { // No borrow 
-            self.v[self.nibble_x()] = vy - vx;
+            self.v[x] = vy - vx;
             self.v[0xf] = 1;
         } else {
-            self.v[self.nibble_x()] = vy.wrapping_sub(vx);
+            self.v[x] = vy.wrapping_sub(vx);
             self.v[0xf] = 0;
         }
         self.inc_pc(1);
@@ -319,20 +727,22 @@ impl CPU {
     /// instruction too is implemented differently from what
     /// is given in the Wikipedia page. This implementation is
     /// based on the Python project whose URL is given in the
-    /// comment to the "shr_vx" function.
-    fn shl_vx(&mut self) {
-        let vx = self.v[self.nibble_x()];
-        self.v[0xf] = (vx >> 7) & 1; 
-        self.v[self.nibble_x()] = vx << 1;
+    /// comment to the "shr_vx" function. The "shift_uses_vy"
+    /// quirk applies here too.
+    fn shl_vx(&mut self, x: usize, y: usize) {
+        let src = if self.quirks.shift_uses_vy { y } else { x };
+        let vs = self.v[src];
+        self.v[x] = vs << 1;
+        self.v[0xf] = (vs >> 7) & 1;
         self.inc_pc(1);
     }
 
     /// Skip the next instruction if v[x] not equal to v[y].
     /// 
     /// This instruction has the form: "9xy0".
-    fn skip_if_vx_ne_vy(&mut self) {
-        if self.v[self.nibble_x()] != self.v[self.nibble_y()] {
-            self.inc_pc(2);
+    fn skip_if_vx_ne_vy(&mut self, x: usize, y: usize) {
+        if self.v[x] != self.v[y] {
+            self.skip_next_insn();
         } else {
             self.inc_pc(1);
         }
@@ -342,18 +752,33 @@ impl CPU {
     /// instruction to the i register.
     /// 
     /// This instruction has the form "annn".
-    fn assign_address_to_ireg(&mut self) {
-        self.i = self.get_address();
+    fn assign_address_to_ireg(&mut self, addr: usize) {
+        self.i = addr;
         self.inc_pc(1);
     }
 
+    /// Assign the 16 bit address stored in the 2 bytes after
+    /// the instruction to the i register, and go on to the
+    /// instruction after them.
+    /// 
+    /// This instruction has the form "f000 nnnn" (XO-CHIP).
+    fn assign_long_address_to_ireg(&mut self) {
+        let pc = self.pc;
+        self.i = (usize::from(self.read_mem(pc + 2)) << 8) | usize::from(self.read_mem(pc + 3));
+        self.inc_pc(2);
+    }
+
     /// Get the 12 bit address encoded as part of the 
     /// instruction, add v[0] to it and jump to that
     /// location.
     /// 
     /// This instruction has the form: "bnnn".
-    fn jmp_to_address_plus_v0(&mut self) {
-        self.pc = usize::from(self.v[0]) + self.get_address();
+    /// 
+    /// With the "jump_uses_vx" quirk, the instruction is read as
+    /// "bxnn" and v[x] is added instead of v[0].
+    fn jmp_to_address_plus_v0(&mut self, addr: usize) {
+        let r = if self.quirks.jump_uses_vx { addr >> 8 } else { 0 };
+        self.pc = usize::from(self.v[r]) + addr;
     }
 
     /// v[x] = rand() & nn
@@ -362,9 +787,9 @@ impl CPU {
     /// instruction with a 1 byte random number.
     /// 
     /// This instruction has the form: "cxnn".
-    fn assign_rand_bitand_const_to_vx(&mut self) {
-        let randval = if cfg!(test) { 0xff } else { rand::random::<u8>() };
-        self.v[self.nibble_x()] = self.get_constant() & randval;
+    fn assign_rand_bitand_const_to_vx(&mut self, x: usize, nn: u8) {
+        let randval: u8 = self.rng.gen();
+        self.v[x] = nn & randval;
         self.inc_pc(1);
     }
 
@@ -372,8 +797,8 @@ impl CPU {
     /// Assign to i the sum of v[x] and i
     /// 
     /// This instruction has the form: "fx1e"
-    fn assign_i_plus_vx_to_i(&mut self) {
-        self.i += usize::from(self.v[self.nibble_x()]);
+    fn assign_i_plus_vx_to_i(&mut self, x: usize) {
+        self.i = (self.i + usize::from(self.v[x])) & I_MASK;
         self.inc_pc(1);
     }
 
@@ -384,12 +809,13 @@ impl CPU {
     /// memory address.
     /// 
     /// This instruction has the form: "0xfx33".
-    fn store_bcd_of_vx_to_mem(&mut self) {
-        let vx = self.v[self.nibble_x()];
+    fn store_bcd_of_vx_to_mem(&mut self, x: usize) {
+        let vx = self.v[x];
         let (a, b, c) = (vx / 100, (vx / 10) % 10, vx % 10);
-        self.mem[self.i] = a; // most significant digit at lowest address
-        self.mem[self.i + 1] = b;
-        self.mem[self.i + 2] = c;
+        let i = self.i;
+        self.write_mem(i, a); // most significant digit at lowest address
+        self.write_mem(i + 1, b);
+        self.write_mem(i + 2, c);
         self.inc_pc(1);
     }
 
@@ -398,9 +824,16 @@ impl CPU {
     /// address is stored in the "i" register.
     /// 
     /// This instruction has the form: "0xfx55".
-    fn store_v0_to_vx_to_mem(&mut self) {
-        for n in 0..self.nibble_x() + 1 {
-            self.mem[self.i + n] = self.v[n];
+    /// 
+    /// With the "load_store_increments_i" quirk, "i" is
+    /// incremented by x + 1.
+    fn store_v0_to_vx_to_mem(&mut self, x: usize) {
+        for n in 0..x + 1 {
+            let (i, vn) = (self.i, self.v[n]);
+            self.write_mem(i + n, vn);
+        }
+        if self.quirks.load_store_increments_i {
+            self.i = (self.i + x + 1) & I_MASK;
         }
         self.inc_pc(1);
     }
@@ -410,9 +843,49 @@ impl CPU {
     /// to registers v[0], v[1], ..., v[x].
     /// 
     /// This instruction has the form: 0xfx65.
-    fn fill_v0_to_vx_from_mem(&mut self) {
-        for n in 0..self.nibble_x() + 1 {
-            self.v[n] = self.mem[self.i + n];
+    /// 
+    /// The "load_store_increments_i" quirk applies here too.
+    fn fill_v0_to_vx_from_mem(&mut self, x: usize) {
+        for n in 0..x + 1 {
+            self.v[n] = self.read_mem(self.i + n);
+        }
+        if self.quirks.load_store_increments_i {
+            self.i = (self.i + x + 1) & I_MASK;
+        }
+        self.inc_pc(1);
+    }
+
+    /// The registers from v[x] to v[y], in that order, which
+    /// is backwards if x > y.
+    fn reg_range(x: usize, y: usize) -> Vec<usize> {
+        if x <= y {
+            (x..y + 1).collect()
+        } else {
+            (y..x + 1).rev().collect()
+        }
+    }
+
+    /// Store v[x] to v[y] to memory locations starting from
+    /// the one whose address is stored in the "i" register.
+    /// "i" is not changed, whatever the quirks.
+    /// 
+    /// This instruction has the form: "5xy2" (XO-CHIP).
+    fn store_vx_to_vy_to_mem(&mut self, x: usize, y: usize) {
+        for (n, r) in CPU::reg_range(x, y).into_iter().enumerate() {
+            let (i, vr) = (self.i, self.v[r]);
+            self.write_mem(i + n, vr);
+        }
+        self.inc_pc(1);
+    }
+
+    /// Copy memory locations starting from the one whose
+    /// address is stored in the "i" register to v[x] to v[y].
+    /// "i" is not changed.
+    /// 
+    /// This instruction has the form: "5xy3" (XO-CHIP).
+    fn fill_vx_to_vy_from_mem(&mut self, x: usize, y: usize) {
+        for (n, r) in CPU::reg_range(x, y).into_iter().enumerate() {
+            self.v[r] = self.read_mem(self.i + n);
         }
         self.inc_pc(1);
     }
@@ -436,137 +909,201 @@ impl CPU {
     /// A pixel is drawn by Xoring it to the value already present on
     /// the screen at that location.
     /// 
+    /// The position (v[x], v[y]) always wraps around the screen.
+    /// With the "clip_sprites" quirk, the parts of the sprite that
+    /// go beyond the right or bottom edge are not drawn; otherwise
+    /// they wrap around too.
+    /// 
+    /// SUPER-CHIP: if "n" is 0, a 16x16 sprite is drawn. Each of its
+    /// rows is stored in 2 bytes, so it takes up 32 bytes in memory.
+    /// 
+    /// XO-CHIP: the sprite is drawn on each plane selected with
+    /// "fn01". With both planes selected, the sprite of the first
+    /// plane is followed in memory by the one of the second; v[f]
+    /// is set if a pixel is turned off on either plane.
+    /// 
+    /// With the "display_wait" quirk, nothing is drawn until the
+    /// next vertical blank; the PC is not incremented, so the
+    /// instruction is executed again.
+    /// 
     /// References:
     /// (1) <http://www.emulator101.com/chip-8-sprites.html>
     /// (2) <http://tibasicdev.wikidot.com/68k:sprites> (Explains the Xor logic)
-    fn draw_sprite(&mut self) {
+    fn draw_sprite(&mut self, x: usize, y: usize, n: u8) {
+        if self.quirks.display_wait {
+            if !self.vblank {
+                return;
+            }
+            self.vblank = false;
+        }
         let mut flipped = false;
         let mut r:bool;
-        let (x, y) = (self.v[self.nibble_x()], self.v[self.nibble_y()]);
-        let n = usize::from(self.mem[self.pc + 1] & 0xf);
+        let (width, height) = (self.screen_width(), self.screen_height());
+        let x = u32::from(self.v[x]) % width;
+        let y = u32::from(self.v[y]) % height;
+        let n = usize::from(n);
+        let (rows, bytes_per_row) = if n == 0 { (16, 2) } else { (n, 1) };
+        let clip = self.quirks.clip_sprites;
 
         self.v[0xf] = 0;
-        if let Some(ref mut scr) = self.screen {
-            for y_index in 0usize .. n {
-                let val = self.mem[self.i + y_index];
-                r = CPU::draw_sprite_row(
-                        scr,    
-                        val, u32::from(x), 
-                        (u32::from(y) + y_index as u32) % u32::from(screen::SCREEN_HEIGHT));
+        let (mut sprite, planes) = (self.i, self.planes);
+        for plane in (0..8).map(|p| 1u8 << p).filter(|p| planes & p != 0) {
+            for y_index in 0usize .. rows {
+                let row = y + y_index as u32;
+                if clip && row >= height {
+                    break;
+                }
+                let addr = sprite + y_index * bytes_per_row;
+                let val = if bytes_per_row == 2 {
+                    (u16::from(self.read_mem(addr)) << 8) | u16::from(self.read_mem(addr + 1))
+                } else {
+                    u16::from(self.read_mem(addr)) << 8
+                };
+                r = self.draw_sprite_row(val, 8 * bytes_per_row as u32, x, row % height, plane);
                 if r { flipped = true; }
             }
-            scr.canvas.present();
-            if flipped { 
-                self.v[0xf] = 1;
-            }
-        } else {
-            panic!("Error: screen not attached!")
+            sprite += rows * bytes_per_row;
+        }
+        self.display.present();
+        if flipped { 
+            self.v[0xf] = 1;
         }
         self.inc_pc(1);
     }
 
-    /// Draw a row of the sprite at position x, y.
+    /// Draw a row of the sprite at position x, y, on the bit
+    /// plane "plane" (a mask with one bit set).
     /// 
-    /// "val" represents the byte to be drawn.
-    /// Each bit of "val", starting from the leftmost
-    /// one, will be plotted at (x, y), (x+1, y), (x+2, y)
-    /// etc. If "x" exceeds the screen width, it will wrap
-    /// to 0.
+    /// The leftmost "nbits" bits of "val" represent the
+    /// row to be drawn (8 bits, or 16 for a SUPER-CHIP
+    /// 16x16 sprite). Each of these bits, starting from
+    /// the leftmost one, will be plotted at (x, y), (x+1, y),
+    /// (x+2, y) etc. If "x" exceeds the screen width, it will
+    /// wrap to 0, unless the "clip_sprites" quirk is set, in
+    /// which case the rest of the row is not drawn.
     /// 
-    /// Pixel plotting is done by Xoring the current pixel
-    /// color with the sprite color.
-    fn draw_sprite_row(scr: &mut screen::Screen, val: u8, x: u32, y: u32) -> bool {
+    /// Pixel plotting is done by Xoring the bit of the plane
+    /// in the current pixel color with the sprite color.
+    fn draw_sprite_row(&mut self, val: u16, nbits: u32, x: u32, y: u32, plane: u8) -> bool {
+        let width = self.screen_width();
         let mut flipped = false;
-        for i in 0..8 {
-            let _x = (x + i) % u32::from(screen::SCREEN_WIDTH);
-            let current_color = scr.get_pixel(_x, y);
-            let sprite_color = (val >> (7 - i)) & 1;
+        for i in 0..nbits {
+            if self.quirks.clip_sprites && x + i >= width {
+                break;
+            }
+            let _x = (x + i) % width;
+            let current_color = self.display.get_pixel(_x, y);
+            let sprite_color = ((val >> (15 - i)) & 1) as u8 * plane;
             let new_color = current_color ^ sprite_color;
-            if (current_color == 1) && (new_color == 0) {
+            if (current_color & plane != 0) && (new_color & plane == 0) {
                 flipped = true;
             } 
-            scr.draw_pixel(_x, y, screen::PIXEL_COLORS[new_color as usize]);
+            self.display.draw_pixel(_x, y, new_color);
         }
         flipped
     }
 
-    /// Get key press. Pressed key stored in v[x]. Operation
-    /// is blocking.
+    /// Wait for a key to be pressed and released, and store
+    /// it in v[x]. Like in the original interpreter, the
+    /// instruction completes on the release, not the press.
+    /// Until then the PC is not incremented, so the
+    /// instruction is executed again; the timers keep
+    /// running meanwhile.
     /// 
     /// This instruction has the form "fx0a".
-    fn get_key(&mut self) {
-        let x = self.nibble_x();
-        if let Some(ref mut scr) = self.screen {
-            self.v[x] = scr.read_key_blocking().expect("error: invalid key pressed!");
-        } else {
-            panic!("get_key: screen not attached!")
+    fn get_key(&mut self, x: usize) {
+        match self.key_wait {
+            None => {
+                let keypad = &self.keypad;
+                self.key_wait = (0..NUM_KEYS as u8).find(|&k| keypad.is_pressed(k));
+            },
+            Some(k) if !self.keypad.is_pressed(k) => {
+                self.key_wait = None;
+                self.v[x] = k;
+                self.inc_pc(1);
+            },
+            Some(_) => {},
         }
-        self.inc_pc(1);
     }
 
-    /// Skip the next instruction if the read key
-    /// is equal to the one whose code is stored in
-    /// v[x].
+    /// Skip the next instruction if the key whose code
+    /// is stored in v[x] is held down.
     /// 
     /// This instruction has the form: "ex9e".
-    fn skip_if_key_eq_vx(&mut self) {
-        let x = self.nibble_x();
-        let mut n = 1;
-        if let Some(ref mut scr) = self.screen {
-            let key = scr.read_key_noblocking();
-            if let Some(k) = key {
-                if k == self.v[x] {
-                    n = 2; // skip next instruction
-                }
-            }
+    fn skip_if_key_eq_vx(&mut self, x: usize) {
+        if self.keypad.is_pressed(self.v[x]) {
+            self.skip_next_insn();
         } else {
-            panic!("error: screen not attached!");
+            self.inc_pc(1);
         }
-        self.inc_pc(n);
     }
 
-    /// Skip the next instruction if the read key
-    /// is not equal to the one whose code is stored
-    /// in v[x].
+    /// Skip the next instruction if the key whose code
+    /// is stored in v[x] is not held down.
     /// 
     /// This instruction has the form: "exa1".
-    fn skip_if_key_ne_vx(&mut self) {
-        let x = self.nibble_x();
-        let mut n = 2;
-        if let Some(ref mut scr) = self.screen {
-            let key = scr.read_key_noblocking();
-            if let Some(k) = key {
-                if k == self.v[x] {
-                    n = 1; // don't skip next instruction
-                }
-            }
+    fn skip_if_key_ne_vx(&mut self, x: usize) {
+        if self.keypad.is_pressed(self.v[x]) {
+            self.inc_pc(1);
         } else {
-            panic!("error: screen not attached!");
+            self.skip_next_insn();
         }
-        self.inc_pc(n);
     }
 
     /// Set v[x] to value of delay timer register.
     /// 
     /// This instruction has the form: "fx07".
-    fn copy_delay_reg_to_vx(&mut self){
-        self.v[self.nibble_x()] = self.delay;
+    fn copy_delay_reg_to_vx(&mut self, x: usize){
+        self.v[x] = self.delay;
         self.inc_pc(1);
     }
 
     /// Copy the value in v[x] to the delay timer register.
     /// 
     /// This instruction has the form: "fx15".
-    fn copy_vx_to_delay_reg(&mut self){
-        self.delay = self.v[self.nibble_x()];
+    fn copy_vx_to_delay_reg(&mut self, x: usize){
+        self.delay = self.v[x];
         self.inc_pc(1);
     }
 
     /// Copy the value in v[x] to the sound timer register.
     /// 
     /// This instruction has the form: "fx18".
-    fn copy_vx_to_sound_reg(&mut self){
-        self.sound = self.v[self.nibble_x()];
+    fn copy_vx_to_sound_reg(&mut self, x: usize){
+        self.sound = self.v[x];
+        self.update_sound();
+        self.inc_pc(1);
+    }
+
+    /// Make the buzzer sound if and only if the sound timer
+    /// is non-zero.
+    fn update_sound(&mut self) {
+        self.audio.set_playing(self.sound > 0);
+    }
+
+    /// Load the 16 byte XO-CHIP audio pattern starting at
+    /// the address in "i". "i" is not changed.
+    ///
+    /// This instruction has the form: "f002".
+    fn load_audio_pattern(&mut self) {
+        let mut pattern = [0; PATTERN_SIZE];
+        for (index, val) in pattern.iter_mut().enumerate() {
+            *val = self.read_mem(self.i + index);
+        }
+        self.pattern = Some(pattern);
+        self.audio.set_waveform(self.waveform());
+        self.inc_pc(1);
+    }
+
+    /// Set the XO-CHIP pitch register to v[x]. This changes
+    /// the playback rate of the audio pattern, if any.
+    ///
+    /// This instruction has the form: "fx3a".
+    fn set_pitch_to_vx(&mut self, x: usize) {
+        self.pitch = self.v[x];
+        if self.pattern.is_some() {
+            self.audio.set_waveform(self.waveform());
+        }
         self.inc_pc(1);
     }
 
@@ -576,21 +1113,128 @@ impl CPU {
     /// by 5 bytes in memory.
     /// 
     /// This instruction has the form: "fx29".
-    fn set_ireg_to_sprite_address(&mut self){
-        self.i = usize::from(self.v[self.nibble_x()] * 5);
+    fn set_ireg_to_sprite_address(&mut self, x: usize){
+        self.i = font::FONT_START + usize::from(self.v[x] & 0xf) * font::FONT_CHAR_SIZE;
         self.inc_pc(1);
     } 
 
-    /// Load program code / font data into memory starting
-    /// at the location mem[offset].
-    pub fn load_rom(&mut self, filename: &str, offset: usize) {
-        let mut f = File::open(filename).
-                    expect(&format!("load_rom: failed to load {}", filename));
+    /// Set the "i" register to the address of the SUPER-CHIP
+    /// 8x10 sprite character stored in v[x].
+    /// 
+    /// This instruction has the form: "fx30".
+    fn set_ireg_to_big_sprite_address(&mut self, x: usize){
+        let c = usize::from(self.v[x] & 0xf);
+        self.i = font::BIG_FONT_START + c * font::BIG_FONT_CHAR_SIZE;
+        self.inc_pc(1);
+    }
+
+    /// Store v[0] to v[x] in the RPL user flags. Only 8 flags
+    /// exist, so x is limited to 7. The flags are then saved
+    /// to the RPL file, if there is one.
+    /// 
+    /// This instruction has the form: "fx75".
+    fn store_v0_to_vx_to_rpl(&mut self, x: usize) {
+        let x = x.min(NUM_RPL_FLAGS - 1);
+        self.rpl[..x + 1].copy_from_slice(&self.v[..x + 1]);
+        if let Some(ref filename) = self.rpl_file {
+            if let Err(e) = File::create(filename).and_then(|mut f| f.write_all(&self.rpl)) {
+                eprintln!("warning: failed to save RPL flags to {}: {}", filename, e);
+            }
+        }
+        self.inc_pc(1);
+    }
+
+    /// Copy the RPL user flags to v[0] to v[x]. x is limited
+    /// to 7.
+    /// 
+    /// This instruction has the form: "fx85".
+    fn fill_v0_to_vx_from_rpl(&mut self, x: usize) {
+        let x = x.min(NUM_RPL_FLAGS - 1);
+        self.v[..x + 1].copy_from_slice(&self.rpl[..x + 1]);
+        self.inc_pc(1);
+    }
+
+    /// Select the XO-CHIP bit planes in the mask "n" for
+    /// drawing, clearing and scrolling. The display has only 2
+    /// planes, so the other bits are ignored.
+    /// 
+    /// This instruction has the form: "fn01".
+    fn select_planes(&mut self, n: u8) {
+        self.planes = n & ALL_PLANES;
+        self.inc_pc(1);
+    }
+
+    /// Turn the pixels of the selected planes OFF. Those of the
+    /// other planes are kept.
+    fn clear_planes(&mut self) {
+        if self.planes == ALL_PLANES {
+            self.display.clear();
+            return;
+        }
+        for y in 0..self.screen_height() {
+            for x in 0..self.screen_width() {
+                let val = self.display.get_pixel(x, y);
+                self.display.draw_pixel(x, y, val & !self.planes);
+            }
+        }
+    }
+
+    /// Move the selected planes of the whole screen "dx" pixels
+    /// to the right and "dy" pixels down (negative values move
+    /// them left / up). Pixels moved in from beyond the edges
+    /// are OFF.
+    fn scroll(&mut self, dx: i32, dy: i32) {
+        let (width, height) = (self.screen_width() as i32, self.screen_height() as i32);
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(self.display.get_pixel(x as u32, y as u32));
+            }
+        }
+        for y in 0..height {
+            for x in 0..width {
+                let (src_x, src_y) = (x - dx, y - dy);
+                let val = if src_x >= 0 && src_x < width && src_y >= 0 && src_y < height {
+                    pixels[(src_y * width + src_x) as usize]
+                } else {
+                    0
+                };
+                let kept = pixels[(y * width + x) as usize] & !self.planes;
+                self.display.draw_pixel(x as u32, y as u32, kept | (val & self.planes));
+            }
+        }
+        self.display.present();
+    }
+
+    /// Switch between the 64x32 and the SUPER-CHIP 128x64
+    /// resolutions. The screen is cleared.
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        let (width, height) = (self.screen_width(), self.screen_height());
+        self.display.set_resolution(width, height);
+    }
+
+    /// Load a ROM, read from `rom` until its end, into memory
+    /// at address `offset`. `rom` may be a file, or a byte
+    /// slice. What happens to a ROM which does not fit depends
+    /// on the fault policy.
+    pub fn load_rom<R: Read>(&mut self, mut rom: R, offset: usize) -> Result<(), Chip8Error> {
         let mut buf = Vec::new();
-        f.read_to_end(&mut buf).expect("load_rom: failed to read from file");
-        for (index, val) in buf.iter().enumerate() {
-            self.mem[offset + index] = *val;
+        rom.read_to_end(&mut buf)?;
+        let mut len = buf.len();
+        let mem_size = self.mem.len();
+        if offset + len > mem_size {
+            match self.fault_policy {
+                FaultPolicy::Halt =>
+                    return Err(Chip8Error::RomTooLarge { size: len, offset }),
+                FaultPolicy::Wrap => {},
+                FaultPolicy::Ignore => len = mem_size.saturating_sub(offset),
+            }
         }
+        for (index, val) in buf[..len].iter().enumerate() {
+            self.write_mem(offset + index, *val);
+        }
+        Ok(())
     }
 
     pub fn decrement_counters(&mut self) {
@@ -600,122 +1244,212 @@ impl CPU {
         if self.delay > 0 {
             self.delay -= 1;
         }
+        self.update_sound();
     }
 
-    /// Execute the instruction pointed to by the PC.
-    pub fn execute_insn(&mut self) {
-        // Return from subroutine.
-        // Instruction: 0x00ee
-        if (self.mem[self.pc] == 0x0) && (self.mem[self.pc + 1] == 0xee) {
-            self.ret();
-            return;
+    /// Signal a vertical blank, which happens at the start of
+    /// every 60 Hz frame: the timers are decremented, frozen
+    /// addresses are written to and, with the "display_wait"
+    /// quirk, a waiting "dxyn" may draw.
+    pub fn vblank(&mut self) {
+        self.decrement_counters();
+        self.vblank = true;
+        self.display.vblank();
+        if let Some(ref mut p) = self.profiler {
+            p.frame();
         }
-        // Clear the screen.
-        // Instruction format: 0x00e0
-        if (self.mem[self.pc] == 0x0) && (self.mem[self.pc + 1] == 0xe0) {
-            if let Some(ref mut scr) = self.screen {
-                scr.canvas.set_draw_color(screen::PIXEL_COLORS[0]);
-                scr.canvas.clear();
-            } else {
-                panic!("Error: screen not attached!");
-            }
-            self.inc_pc(1);
-            return;    
+        // Memory may have shrunk since an address was frozen.
+        for (&addr, &val) in self.frozen.range(..self.mem.len()) {
+            self.mem[addr] = val;
+            self.cache.invalidate(addr, 1);
         }
-        // Skip next instruction if key whose code is stored in
-        // v[x] is pressed.
-        if (((self.mem[self.pc] >> 4) & 0xf) == 0xe) && 
-            (self.mem[self.pc + 1] == 0x9e) {
-                self.skip_if_key_eq_vx();
-                return;
+    }
+
+    /// Execute the instruction pointed to by the PC. Does
+    /// nothing once the program has exited.
+    /// 
+    /// If the instruction faults, for example because its
+    /// opcode is not valid, what happens depends on the fault
+    /// policy. With FaultPolicy::Halt, an error is returned
+    /// and the CPU is left unchanged.
+    pub fn execute_insn(&mut self) -> Result<(), Chip8Error> {
+        if self.exited {
+            return Ok(());
         }
-        // Skip next instruction if key whose code is stored in 
-        // v[x] is not pressed.
-        if (((self.mem[self.pc] >> 4) & 0xf) == 0xe) &&
-            (self.mem[self.pc + 1] == 0xa1) {
-                self.skip_if_key_ne_vx();
-                return;
+        let (pc, mem_size) = (self.pc, self.mem.len());
+        if pc + 1 >= mem_size {
+            // There is no instruction to skip, so Ignore wraps
+            // around too.
+            if self.fault_policy == FaultPolicy::Halt {
+                return Err(Chip8Error::AddressOutOfRange { pc, addr: cmp::max(pc, mem_size) });
             }
+            self.pc %= mem_size;
+        }
+        let opcode = (u16::from(self.read_mem(self.pc)) << 8) | u16::from(self.read_mem(self.pc + 1));
+        let result = insn::decode(opcode)
+            .map_err(|_| Chip8Error::InvalidOpcode { pc: self.pc, opcode })
+            .and_then(|insn| self.check(insn).map(|_| insn));
+        match result {
+            Ok(insn) => {
+                self.execute(insn);
+                Ok(())
+            },
+            Err(e) if self.fault_policy == FaultPolicy::Halt => Err(e),
+            Err(_) => {
+                self.inc_pc(1);
+                Ok(())
+            },
+        }
+    }
 
-        // Get the leftmost nibble
-        let t = (self.mem[self.pc] >> 4) & 0xf;
-        if ((t >= 1) && (t <= 7)) || ((t >= 9) && (t <= 0xd)) {
-            let insn = INSN_LUT1.get(&t).expect("Bad Instruction");
-            insn(self);
-            return;            
-        }         
-        if t == 8 {
-            // Get the rightmost nibble
-            let t = self.mem[self.pc + 1] & 0xf;
-            let insn = INSN_LUT2.get(&t).expect("Bad Instruction");
-            insn(self);
-            return;
+    /// Execute the instruction pointed to by the PC, like
+    /// `execute_insn` does, but decoded with the translation
+    /// cache. Instructions which are not valid or would fault
+    /// are left to `execute_insn`.
+    pub fn execute_cached(&mut self) -> Result<(), Chip8Error> {
+        if self.exited {
+            return Ok(());
         }
-        if t == 0xf {
-            let t = self.mem[self.pc + 1];
-            let insn = INSN_LUT3.get(&t).expect("Bad Instruction");
-            insn(self);
-            return;
+        let insn = match self.cache.next(&self.mem, self.pc) {
+            Some(insn) => insn,
+            None => return self.execute_insn(),
+        };
+        if self.check(insn).is_err() {
+            return self.execute_insn();
         }
+        self.execute(insn);
+        Ok(())
     }
-} 
 
-/// INSN_LUT1 is an instruction lookup table; used for decoding an 
-/// instruction based on its leftmost nibble.
-lazy_static! {
-     static  ref  INSN_LUT1:HashMap<u8, InsnPtr> = hashmap! {
-        1 => CPU::jmp as InsnPtr,
-        2 => CPU::call as InsnPtr,
-        3 => CPU::skip_if_vx_eq_nn as InsnPtr,
-        4 => CPU::skip_if_vx_ne_nn as InsnPtr,
-        5 => CPU::skip_if_vx_eq_vy as InsnPtr,
-        6 => CPU::set_vx_to_nn as InsnPtr,
-        7 => CPU::add_nn_to_vx as InsnPtr,
-        9 => CPU::skip_if_vx_ne_vy as InsnPtr,
-        0xa => CPU::assign_address_to_ireg as InsnPtr,
-        0xb => CPU::jmp_to_address_plus_v0 as InsnPtr,
-        0xc => CPU::assign_rand_bitand_const_to_vx as InsnPtr,
-        0xd => CPU::draw_sprite as InsnPtr,
-    };
-}
+    /// Number of blocks in the translation cache.
+    pub fn cached_blocks(&self) -> usize {
+        self.cache.num_blocks()
+    }
 
-/// There are multiple instructions whose opcodes start with
-/// the leftmost nibble equal to 8. These instructions are uniquely
-/// identified based on the value of their rightmost nibble. INSN_LUT2
-/// is used to perform this identification.
-lazy_static! {
-    static ref INSN_LUT2: HashMap<u8, InsnPtr> = hashmap! {
-        0 => CPU::assign_vy_to_vx as InsnPtr,
-        1 => CPU::assign_vx_or_vy_to_vx as InsnPtr,
-        2 => CPU::assign_vx_and_vy_to_vx as InsnPtr,
-        3 => CPU::assign_vx_xor_vy_to_vx as InsnPtr,
-        4 => CPU::assign_vx_plus_vy_to_vx as InsnPtr,
-        5 => CPU::assign_vx_minus_vy_to_vx as InsnPtr,
-        6 => CPU::shr_vx as InsnPtr,
-        7 => CPU::assign_vy_minus_vx_to_vx as InsnPtr,
-        0xe => CPU::shl_vx as InsnPtr,
-    };
-}
+    /// Return an error if the `len` bytes from `addr` on are
+    /// not all in memory.
+    fn check_range(&self, addr: usize, len: usize) -> Result<(), Chip8Error> {
+        let mem_size = self.mem.len();
+        if addr + len > mem_size {
+            return Err(Chip8Error::AddressOutOfRange { pc: self.pc, addr: cmp::max(addr, mem_size) });
+        }
+        Ok(())
+    }
 
-/// There are multiple instructions whose opcodes start
-/// with the leftmost nibble equal to 0xf. INSN_LUT3 identifies
-/// these instructions based on the value of their least 
-/// significant byte. 
-lazy_static! {
-    static ref INSN_LUT3: HashMap<u8, InsnPtr> = hashmap! {
-        0x1e => CPU::assign_i_plus_vx_to_i as InsnPtr,
-        0x33 => CPU::store_bcd_of_vx_to_mem as InsnPtr,
-        0x55 => CPU::store_v0_to_vx_to_mem as InsnPtr,
-        0x65 => CPU::fill_v0_to_vx_from_mem as InsnPtr,
-        0x0A => CPU::get_key as InsnPtr,
-        0x07 => CPU::copy_delay_reg_to_vx as InsnPtr,
-        0x15 => CPU::copy_vx_to_delay_reg as InsnPtr,
-        0x18 => CPU::copy_vx_to_sound_reg as InsnPtr,
-        0x29 => CPU::set_ireg_to_sprite_address as InsnPtr,
-    };
-}
+    /// Return the fault that executing "insn" would cause, if
+    /// any. With the "wrap" fault policy, memory accesses and
+    /// the stack wrap around instead of faulting.
+    fn check(&self, insn: Instruction) -> Result<(), Chip8Error> {
+        use insn::Instruction::*;
+
+        if self.fault_policy == FaultPolicy::Wrap {
+            return Ok(());
+        }
+        match insn {
+            Call(_) if self.sp >= SP_TOP => Err(Chip8Error::StackOverflow { pc: self.pc }),
+            Return if self.sp <= SP_BOTTOM => Err(Chip8Error::StackUnderflow { pc: self.pc }),
+            Draw(_, _, n) => {
+                let size = if n == 0 { 32 } else { usize::from(n) };
+                self.check_range(self.i, size * self.planes.count_ones() as usize)
+            },
+            Bcd(_) => self.check_range(self.i, 3),
+            Store(x) | Load(x) => self.check_range(self.i, x + 1),
+            StoreRange(x, y) | LoadRange(x, y) => self.check_range(self.i, x.max(y) - x.min(y) + 1),
+            LongI => self.check_range(self.pc + 2, 2),
+            Audio => self.check_range(self.i, PATTERN_SIZE),
+            _ => Ok(()),
+        }
+    }
+
+    /// Execute a decoded instruction.
+    fn execute(&mut self, insn: Instruction) {
+        use insn::Instruction::*;
+
+        if let Some(ref mut p) = self.profiler {
+            p.record(self.pc, insn);
+        }
+
+        match insn {
+            ScrollDown(n) => {
+                self.scroll(0, i32::from(n));
+                self.inc_pc(1);
+            },
+            ScrollUp(n) => {
+                self.scroll(0, -i32::from(n));
+                self.inc_pc(1);
+            },
+            Clear => {
+                self.clear_planes();
+                self.inc_pc(1);
+            },
+            Return => self.ret(),
+            ScrollRight => {
+                self.scroll(4, 0);
+                self.inc_pc(1);
+            },
+            ScrollLeft => {
+                self.scroll(-4, 0);
+                self.inc_pc(1);
+            },
+            Exit => self.exited = true,
+            Lores => {
+                self.set_hires(false);
+                self.inc_pc(1);
+            },
+            Hires => {
+                self.set_hires(true);
+                self.inc_pc(1);
+            },
+            Jump(addr) => self.jmp(addr),
+            Call(addr) => self.call(addr),
+            SkipIfEqConst(x, nn) => self.skip_if_vx_eq_nn(x, nn),
+            SkipIfNeConst(x, nn) => self.skip_if_vx_ne_nn(x, nn),
+            SkipIfEq(x, y) => self.skip_if_vx_eq_vy(x, y),
+            StoreRange(x, y) => self.store_vx_to_vy_to_mem(x, y),
+            LoadRange(x, y) => self.fill_vx_to_vy_from_mem(x, y),
+            SetConst(x, nn) => self.set_vx_to_nn(x, nn),
+            AddConst(x, nn) => self.add_nn_to_vx(x, nn),
+            Assign(x, y) => self.assign_vy_to_vx(x, y),
+            Or(x, y) => self.assign_vx_or_vy_to_vx(x, y),
+            And(x, y) => self.assign_vx_and_vy_to_vx(x, y),
+            Xor(x, y) => self.assign_vx_xor_vy_to_vx(x, y),
+            Add(x, y) => self.assign_vx_plus_vy_to_vx(x, y),
+            Sub(x, y) => self.assign_vx_minus_vy_to_vx(x, y),
+            ShiftRight(x, y) => self.shr_vx(x, y),
+            SubReverse(x, y) => self.assign_vy_minus_vx_to_vx(x, y),
+            ShiftLeft(x, y) => self.shl_vx(x, y),
+            SkipIfNe(x, y) => self.skip_if_vx_ne_vy(x, y),
+            SetI(addr) => self.assign_address_to_ireg(addr),
+            JumpOffset(addr) => self.jmp_to_address_plus_v0(addr),
+            Random(x, nn) => self.assign_rand_bitand_const_to_vx(x, nn),
+            Draw(x, y, n) => self.draw_sprite(x, y, n),
+            SkipIfKey(x) => self.skip_if_key_eq_vx(x),
+            SkipIfNotKey(x) => self.skip_if_key_ne_vx(x),
+            LongI => self.assign_long_address_to_ireg(),
+            Planes(n) => self.select_planes(n),
+            Audio => self.load_audio_pattern(),
+            GetDelay(x) => self.copy_delay_reg_to_vx(x),
+            WaitKey(x) => self.get_key(x),
+            SetDelay(x) => self.copy_vx_to_delay_reg(x),
+            SetSound(x) => self.copy_vx_to_sound_reg(x),
+            AddI(x) => self.assign_i_plus_vx_to_i(x),
+            Font(x) => self.set_ireg_to_sprite_address(x),
+            BigFont(x) => self.set_ireg_to_big_sprite_address(x),
+            Bcd(x) => self.store_bcd_of_vx_to_mem(x),
+            Pitch(x) => self.set_pitch_to_vx(x),
+            Store(x) => self.store_v0_to_vx_to_mem(x),
+            Load(x) => self.fill_v0_to_vx_from_mem(x),
+            StoreFlags(x) => self.store_v0_to_vx_to_rpl(x),
+            LoadFlags(x) => self.fill_v0_to_vx_from_rpl(x),
+        }
+    }
+} 
 
 #[cfg(test)]
 #[path="./cpu_test.rs"]
 mod cpu_test;
+
+#[cfg(test)]
+#[path="./cpu_prop_test.rs"]
+mod cpu_prop_test;
     
\ No newline at end of file
@@ -0,0 +1,127 @@
+use super::*;
+
+use proptest::prelude::*;
+
+// Property tests: the instructions are run on CPUs in random
+// states, and what they did is checked against a simpler
+// model of each instruction.
+
+/// A headless CPU with registers `v` and `i`, the memory
+/// `mem`, `depth` return addresses on the stack, and "opcode"
+/// at `pc`.
+fn cpu_with(v: [u8; NUM_REGS], i: usize, mem: &[u8], depth: usize, pc: usize, opcode: u16) -> CPU {
+    let mut c = CPU::headless();
+    c.mem.copy_from_slice(mem);
+    c.v = v;
+    c.i = i;
+    c.sp = SP_BOTTOM + 2 * depth;
+    c.pc = pc;
+    c.mem[pc] = (opcode >> 8) as u8;
+    c.mem[pc + 1] = opcode as u8;
+    c
+}
+
+fn registers() -> impl Strategy<Value = [u8; NUM_REGS]> {
+    prop::array::uniform16(any::<u8>())
+}
+
+fn memory() -> impl Strategy<Value = Vec<u8>> {
+    prop::collection::vec(any::<u8>(), MEM_SIZE)
+}
+
+/// Addresses an instruction can be at, with room for the
+/// instruction after it.
+fn pc() -> impl Strategy<Value = usize> {
+    (PC_START / 2..MEM_SIZE / 2 - 2).prop_map(|n| 2 * n)
+}
+
+proptest! {
+    /// Every instruction goes on to the next one, except the
+    /// ones which change the flow of control; an instruction
+    /// which faults changes nothing.
+    #[test]
+    fn prop_pc_advance(v in registers(), i in 0..MEM_SIZE, mem in memory(),
+                       depth in 0..STACK_SIZE + 1, pc in pc(), opcode in any::<u16>()) {
+        use insn::Instruction::*;
+
+        let mut c = cpu_with(v, i, &mem, depth, pc, opcode);
+        let before = c.save_state();
+        let return_addr = c.get_16bits_from_tos();
+        // "f000 nnnn" is skipped whole.
+        let next_len = if mem[pc + 2] == 0xf0 && mem[pc + 3] == 0x00 { 4 } else { 2 };
+        let skip = |cond: bool| if cond { pc + 2 + next_len } else { pc + 2 };
+        if c.execute_insn().is_err() {
+            prop_assert!(c.save_state() == before);
+            return Ok(());
+        }
+        let expected = match insn::decode(opcode).unwrap() {
+            Jump(nnn) | Call(nnn) => nnn,
+            Return => return_addr,
+            JumpOffset(nnn) => nnn + usize::from(v[0]),
+            SkipIfEqConst(x, nn) => skip(v[x] == nn),
+            SkipIfNeConst(x, nn) => skip(v[x] != nn),
+            SkipIfEq(x, y) => skip(v[x] == v[y]),
+            SkipIfNe(x, y) => skip(v[x] != v[y]),
+            // No key is ever pressed.
+            SkipIfKey(_) => pc + 2,
+            SkipIfNotKey(_) => pc + 2 + next_len,
+            LongI => pc + 4,
+            WaitKey(_) | Exit => pc,
+            _ => pc + 2,
+        };
+        prop_assert_eq!(c.pc, expected);
+        if let Ok(Call(_)) = insn::decode(opcode) {
+            prop_assert_eq!(c.sp, SP_BOTTOM + 2 * depth + 2);
+            prop_assert_eq!(c.get_16bits_from_tos(), pc + 2);
+        }
+    }
+
+    /// The arithmetic instructions set v[f] to their carry,
+    /// borrow or shifted out bit, even when x is f.
+    #[test]
+    fn prop_arithmetic_flags(v in registers(), x in 0..NUM_REGS, y in 0..NUM_REGS,
+                             op in prop::sample::select(vec![4u16, 5, 6, 7, 0xe]),
+                             shift_uses_vy in any::<bool>()) {
+        let opcode = 0x8000 | (x as u16) << 8 | (y as u16) << 4 | op;
+        let mut c = cpu_with(v, 0, &[0; MEM_SIZE], 0, PC_START, opcode);
+        c.set_quirks(Quirks { shift_uses_vy, ..Quirks::default() });
+        c.execute_insn().unwrap();
+
+        let (vx, vy) = (v[x], v[y]);
+        let src = if shift_uses_vy { vy } else { vx };
+        let (result, flag) = match op {
+            4 => (vx.wrapping_add(vy), u16::from(vx) + u16::from(vy) > 0xff),
+            5 => (vx.wrapping_sub(vy), vx >= vy),
+            7 => (vy.wrapping_sub(vx), vy >= vx),
+            6 => (src >> 1, src & 1 == 1),
+            _ => (src << 1, src & 0x80 != 0),
+        };
+        prop_assert_eq!(c.v[0xf], flag as u8);
+        if x != 0xf {
+            prop_assert_eq!(c.v[x], result);
+        }
+        for r in (0..0xf).filter(|&r| r != x) {
+            prop_assert_eq!(c.v[r], v[r]);
+        }
+        prop_assert_eq!(c.pc, PC_START + 2);
+    }
+
+    /// "fx33" stores the decimal digits of v[x] at i, and
+    /// changes no other byte.
+    #[test]
+    fn prop_bcd(v in registers(), x in 0..NUM_REGS, i in 0..MEM_SIZE - 2, mem in memory()) {
+        let opcode = 0xf033 | (x as u16) << 8;
+        let mut c = cpu_with(v, i, &mem, 0, PC_START, opcode);
+        let before = c.mem.clone();
+        c.execute_insn().unwrap();
+
+        let digits = &c.mem[i..i + 3];
+        prop_assert!(digits.iter().all(|&d| d < 10));
+        let value = 100 * u16::from(digits[0]) + 10 * u16::from(digits[1]) + u16::from(digits[2]);
+        prop_assert_eq!(value, u16::from(v[x]));
+        for a in (0..MEM_SIZE).filter(|&a| a < i || a >= i + 3) {
+            prop_assert_eq!(c.mem[a], before[a]);
+        }
+        prop_assert_eq!(c.i, i);
+    }
+}
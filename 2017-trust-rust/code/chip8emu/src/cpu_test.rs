@@ -1,29 +1,35 @@
 
 use super::*;
+use asm;
+use audio::{AudioEvent, RecordingAudio};
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::rc::Rc;
 
-/// Tests all instructions except those doing I/O.
+/// Tests all instructions. The I/O instructions use the in-memory
+/// framebuffer from `headless` and a KeyState keypad.
 
 #[test]
 fn test_jump(){
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x1055
     // Jump to 0x55
     c.mem[0] = 0x10;
     c.mem[1] = 0x55;   
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.pc, 0x55);
 }
 
 #[test]
 fn test_call() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x2134
     // Call subroutine at 0x134
     c.mem[0] = 0x21;
     c.mem[1] = 0x34;
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.pc, 0x134);
     assert_eq!(c.sp, SP_BOTTOM + 2);
     assert_eq!(c.mem[c.sp], 0x0);
@@ -32,7 +38,7 @@ fn test_call() {
 
 #[test]
 fn test_ret() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x2134
     // Call subroutine at 0x134
@@ -43,8 +49,8 @@ fn test_ret() {
     c.mem[0x134] = 0x00;
     c.mem[0x135] = 0xee;
 
-    c.execute_insn(); // call 0x134   
-    c.execute_insn(); // ret
+    c.execute_insn().unwrap(); // call 0x134   
+    c.execute_insn().unwrap(); // ret
 
     assert_eq!(c.sp, SP_BOTTOM);
     assert_eq!(c.pc, 0x2);    
@@ -52,7 +58,7 @@ fn test_ret() {
 
 #[test]
 fn test1_skip_if_vx_eq_nn() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x3a24
     // Skip next instruction if self.v[0xa] == 0x24
@@ -61,13 +67,13 @@ fn test1_skip_if_vx_eq_nn() {
     c.mem[0] = 0x3a;
     c.mem[1] = 0x24;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.pc, 0x4);
 }
 
 #[test]
 fn test2_skip_if_vx_eq_nn() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x3a24
     // Skip next instruction if self.v[0xa] == 0x24
@@ -76,13 +82,13 @@ fn test2_skip_if_vx_eq_nn() {
     c.mem[0] = 0x3a;
     c.mem[1] = 0x24;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.pc, 0x2);
 }
 
 #[test]
 fn test1_skip_if_vx_ne_nn() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x4a24
     // Skip next instruction if self.v[0xa] != 0x24
@@ -91,13 +97,13 @@ fn test1_skip_if_vx_ne_nn() {
     c.mem[0] = 0x4a;
     c.mem[1] = 0x24;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.pc, 0x4);
 }
 
 #[test]
 fn test2_skip_if_vx_ne_nn() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x4a24
     // Skip next instruction if self.v[0xa] == 0x24
@@ -106,13 +112,13 @@ fn test2_skip_if_vx_ne_nn() {
     c.mem[0] = 0x4a;
     c.mem[1] = 0x24;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.pc, 0x2);
 }
 
 #[test]
 fn test1_skip_if_vx_eq_vy() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x52b0
     // Skip next instruction if self.v[0x2] == self.v[0xb]
@@ -122,13 +128,13 @@ fn test1_skip_if_vx_eq_vy() {
     c.mem[0] = 0x52;
     c.mem[1] = 0xb0;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.pc, 0x4);
 }
 
 #[test]
 fn test2_skip_if_vx_eq_vy() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x52b0
     // Skip next instruction if self.v[0x2] == self.v[0xb]
@@ -138,13 +144,13 @@ fn test2_skip_if_vx_eq_vy() {
     c.mem[0] = 0x52;
     c.mem[1] = 0xb0;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.pc, 0x2);
 }
 
 #[test]
 fn test_set_vx_to_nn() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x6c2b
     // Set self.v[0xc] to 0x2b
@@ -152,14 +158,14 @@ fn test_set_vx_to_nn() {
     c.mem[0] = 0x6c;
     c.mem[1] = 0x2b;
     
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.v[0xc], 0x2b);
     assert_eq!(c.pc, 2);
 }
 
 #[test]
 fn test_add_nn_to_vx() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x7405
     // Add 0x5 to self.v[0x4] without changing carry.
@@ -169,7 +175,7 @@ fn test_add_nn_to_vx() {
     c.mem[0] = 0x74;
     c.mem[1] = 0x05;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(t, c.v[0xf]);
     assert_eq!(c.v[0x4], 4);
     assert_eq!(c.pc, 2);
@@ -177,7 +183,7 @@ fn test_add_nn_to_vx() {
 
 #[test]
 fn test_assign_vy_to_vx() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x82b0
     // v[2] = v[0xb]
@@ -187,7 +193,7 @@ fn test_assign_vy_to_vx() {
     c.mem[0] = 0x82;
     c.mem[1] = 0xb0;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.v[2], c.v[0xb]);    
     assert_eq!(c.v[2], 20);
     assert_eq!(c.pc, 2);
@@ -195,7 +201,7 @@ fn test_assign_vy_to_vx() {
 
 #[test]
 fn test_assign_vx_or_vy_to_vx() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x85c1
     // v[5] = v[5] | v[0xc]
@@ -205,14 +211,14 @@ fn test_assign_vx_or_vy_to_vx() {
     c.mem[0] = 0x85;
     c.mem[1] = 0xc1;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.v[5], 0x9);
     assert_eq!(c.pc, 2);
 }
 
 #[test]
 fn test_assign_vx_and_vy_to_vx() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Insruction: 0x85c2
     // v[5] = v[5] & v[0xc] 
@@ -222,14 +228,14 @@ fn test_assign_vx_and_vy_to_vx() {
     c.mem[0] = 0x85;
     c.mem[1] = 0xc2;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.v[5], 0);
     assert_eq!(c.pc, 2);   
 }
 
 #[test]
 fn test_assign_vx_xor_vy_to_vx() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x85c3
     // v[5] = v[5] ^ v[0xc]
@@ -239,14 +245,14 @@ fn test_assign_vx_xor_vy_to_vx() {
     c.mem[0] = 0x85;
     c.mem[1] = 0xc3;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.v[5], 0);
     assert_eq!(c.pc, 2);
 }
 
 #[test]
 fn test1_assign_vx_plus_vy_to_vx() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x85c4
     // v[5] = v[5] + v[0xc]
@@ -256,7 +262,7 @@ fn test1_assign_vx_plus_vy_to_vx() {
     c.mem[0] = 0x85;
     c.mem[1] = 0xc4;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.v[5], 9);
     assert_eq!(c.v[0xf], 1);
     assert_eq!(c.pc, 2);
@@ -264,7 +270,7 @@ fn test1_assign_vx_plus_vy_to_vx() {
 
 #[test]
 fn test2_assign_vx_plus_vy_to_vx() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x85c4
     // v[5] = v[5] + v[0xc]
@@ -274,7 +280,7 @@ fn test2_assign_vx_plus_vy_to_vx() {
     c.mem[0] = 0x85;
     c.mem[1] = 0xc4;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.v[5], 255);
     assert_eq!(c.v[0xf], 0);
     assert_eq!(c.pc, 2);
@@ -282,7 +288,7 @@ fn test2_assign_vx_plus_vy_to_vx() {
 
 #[test] 
 fn test1_assign_vx_minus_vy_to_vx() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x89d5
     // v[9] = v[9] - v[0xd]
@@ -292,7 +298,7 @@ fn test1_assign_vx_minus_vy_to_vx() {
     c.mem[0] = 0x89;
     c.mem[1] = 0xd5;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.v[9], 10); 
     assert_eq!(c.v[0xf], 1); 
     assert_eq!(c.pc, 2);
@@ -300,7 +306,7 @@ fn test1_assign_vx_minus_vy_to_vx() {
 
 #[test] 
 fn test2_assign_vx_minus_vy_to_vx() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x89d5
     // v[9] = v[9] - v[0xd]
@@ -310,7 +316,7 @@ fn test2_assign_vx_minus_vy_to_vx() {
     c.mem[0] = 0x89;
     c.mem[1] = 0xd5;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.v[9], 246); 
     assert_eq!(c.v[0xf], 0); 
     assert_eq!(c.pc, 2);
@@ -318,7 +324,7 @@ fn test2_assign_vx_minus_vy_to_vx() {
 
 #[test]
 fn test_shr_vx() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x8706
     // v[7] = v[7] >> 1
@@ -327,7 +333,7 @@ fn test_shr_vx() {
     c.mem[0] = 0x87;
     c.mem[1] = 0x06;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.v[7], 1);
     assert_eq!(c.v[0xf], 1);
     assert_eq!(c.pc, 2);
@@ -335,7 +341,7 @@ fn test_shr_vx() {
 
 #[test]
 fn test1_assign_vy_minus_vx_to_vx() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x89e7
     // v[9] = v[0xe] - v[0x9]
@@ -345,7 +351,7 @@ fn test1_assign_vy_minus_vx_to_vx() {
     c.mem[0] = 0x89;
     c.mem[1] = 0xe7;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.v[9], 3);
     assert_eq!(c.v[0xf], 1);
     assert_eq!(c.pc, 2);
@@ -354,7 +360,7 @@ fn test1_assign_vy_minus_vx_to_vx() {
 
 #[test]
 fn test2_assign_vy_minus_vx_to_vx() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x89e7
     // v[9] = v[0xe] - v[0x9]
@@ -364,7 +370,7 @@ fn test2_assign_vy_minus_vx_to_vx() {
     c.mem[0] = 0x89;
     c.mem[1] = 0xe7;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.v[9], 253);
     assert_eq!(c.v[0xf], 0);
     assert_eq!(c.pc, 2);
@@ -373,7 +379,7 @@ fn test2_assign_vy_minus_vx_to_vx() {
 
 #[test]
 fn test_shl_vx() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x870e
     // v[7] = v[7] << 1
@@ -382,14 +388,14 @@ fn test_shl_vx() {
     c.mem[0] = 0x87;
     c.mem[1] = 0x0e;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.v[7], 4);
     assert_eq!(c.pc, 2);
 }
 
 #[test]
 fn test1_skip_if_vx_ne_vy() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x9560
     // skip if v[5] != v[6]
@@ -399,13 +405,13 @@ fn test1_skip_if_vx_ne_vy() {
     c.mem[0] = 0x95;
     c.mem[1] = 0x60;
     
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.pc, 2);
 }
 
 #[test]
 fn test2_skip_if_vx_ne_vy() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0x9560
     // skip if v[5] != v[6]
@@ -415,13 +421,13 @@ fn test2_skip_if_vx_ne_vy() {
     c.mem[0] = 0x95;
     c.mem[1] = 0x60;
     
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.pc, 4);
 }
 
 #[test]
 fn test_assign_address_to_ireg() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0xa123
     // i = 0x123
@@ -429,14 +435,14 @@ fn test_assign_address_to_ireg() {
     c.mem[0] = 0xa1;
     c.mem[1] = 0x23;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.i, 0x123);
     assert_eq!(c.pc, 2);
 }
 
 #[test]
 fn test_jmp_to_address_plus_v0() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0xb123
     // pc = 0x123 + v[0]
@@ -445,30 +451,50 @@ fn test_jmp_to_address_plus_v0() {
     c.mem[0] = 0xb1;
     c.mem[1] = 0x23;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.pc, 0x126);
 }
 
 #[test]
 fn test_assign_rand_bitand_const_to_vx() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0xc75a
     // v[7] = rand() & 0x5a
-    // When the test code is being executed,
-    // value of rand() will be 0xff.
 
     c.mem[0] = 0xc7;
     c.mem[1] = 0x5a;
 
-    c.execute_insn();
-    assert_eq!(c.v[7], 0x5a);
+    c.execute_insn().unwrap();
+    assert_eq!(c.v[7] & !0x5a, 0);
     assert_eq!(c.pc, 2);
 }
 
+#[test]
+fn test_rand_is_seeded() {
+    let draws = |seed| {
+        let mut c = cpu_with_program("
+            : loop
+            v0 := random 0xff
+            jump loop
+        ");
+        c.set_seed(seed);
+        (0..64).map(|_| {
+            c.execute_insn().unwrap();
+            c.execute_insn().unwrap();
+            c.v[0]
+        }).collect::<Vec<u8>>()
+    };
+    assert_eq!(draws(1), draws(1));
+    assert_ne!(draws(1), draws(2));
+    // Not stuck on a value.
+    let first = draws(0);
+    assert!(first.iter().any(|&n| n != first[0]));
+}
+
 #[test]
 fn test_assign_i_plus_vx_to_i() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0xf31e
     // i += v[3]
@@ -478,14 +504,39 @@ fn test_assign_i_plus_vx_to_i() {
     c.mem[0] = 0xf3;
     c.mem[1] = 0x1e;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.i, 14);
     assert_eq!(c.pc, 2);
 }
 
+#[test]
+fn test_ireg_wraps_at_16_bits() {
+    let mut c = cpu_with_program("i += v0\nsave v0\ni += v0");
+    c.set_quirks(Quirks { load_store_increments_i: true, ..Quirks::default() });
+    c.set_fault_policy(FaultPolicy::Wrap);
+    c.i = 0xfff0;
+    c.v[0] = 0x0f;
+    c.execute_insn().unwrap();
+    assert_eq!(c.i, 0xffff);
+    c.execute_insn().unwrap();
+    assert_eq!(c.i, 0);
+    c.i = 0xfff8;
+    c.execute_insn().unwrap();
+    assert_eq!(c.i, 0x7);
+
+    // So it survives a save state.
+    c.i = 0xffff;
+    let st = c.save_state();
+    let mut buf = Vec::new();
+    st.write(&mut buf).unwrap();
+    let mut c2 = CPU::headless();
+    c2.load_state(&State::read(&mut &buf[..]).unwrap()).unwrap();
+    assert_eq!(c2.i, 0xffff);
+}
+
 #[test]
 fn test_store_bcd_of_vx_to_mem() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0xf133
     
@@ -494,7 +545,7 @@ fn test_store_bcd_of_vx_to_mem() {
     c.mem[0] = 0xf1;
     c.mem[1] = 0x33;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     assert_eq!(c.mem[6], 1);
     assert_eq!(c.mem[7], 2);
     assert_eq!(c.mem[8], 3);
@@ -503,7 +554,7 @@ fn test_store_bcd_of_vx_to_mem() {
 
 #[test]
 fn test_store_v0_to_vx_to_mem() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0xff55
     // Store v[0] to v[0xf] to mem,
@@ -516,7 +567,7 @@ fn test_store_v0_to_vx_to_mem() {
     c.mem[0] = 0xff;
     c.mem[1] = 0x55;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     for i in 0..0x10usize {
         assert_eq!(c.mem[i], c.v[i]);
     }
@@ -525,7 +576,7 @@ fn test_store_v0_to_vx_to_mem() {
 
 #[test]
 fn test_fill_v0_to_vx_from_mem() {
-    let mut c = CPU::new(None);
+    let mut c = CPU::headless();
     c.pc = 0;
     // Instruction: 0xff65
     // Store from c.mem[i], c.mem[i+1], ..., c.mem[i+0xf]
@@ -538,9 +589,930 @@ fn test_fill_v0_to_vx_from_mem() {
     c.mem[0] = 0xff;
     c.mem[1] = 0x65;
 
-    c.execute_insn();
+    c.execute_insn().unwrap();
     for i in 0..0x10usize {
         assert_eq!(c.v[i], i as u8);
     }
     assert_eq!(c.pc, 2);
-}
\ No newline at end of file
+}
+
+/// A headless CPU, and its keypad on which the test can
+/// press keys.
+fn cpu_with_keypad() -> (CPU, Rc<RefCell<KeyState>>) {
+    let k = Rc::new(RefCell::new(KeyState::new()));
+    (CPU::new(Box::new(headless::Framebuffer::new()), Box::new(k.clone())), k)
+}
+
+#[test]
+fn test_clear_screen() {
+    let mut c = CPU::headless();
+    c.pc = 0;
+    // Instruction: 0x00e0
+    // Clear the screen
+
+    c.display.draw_pixel(3, 4, 1);
+    c.mem[0] = 0x00;
+    c.mem[1] = 0xe0;
+
+    c.execute_insn().unwrap();
+    assert_eq!(c.display.get_pixel(3, 4), 0);
+    assert_eq!(c.pc, 2);
+}
+
+#[test]
+fn test1_draw_sprite() {
+    let mut c = CPU::headless();
+    c.pc = 0;
+    // Instruction: 0xd122
+    // Draw the 2 row sprite at mem[i] at (v[1], v[2])
+
+    c.v[1] = 10;
+    c.v[2] = 20;
+    c.i = 0x300;
+    c.mem[0x300] = 0b1000_0001;
+    c.mem[0x301] = 0b0100_0000;
+    c.mem[0] = 0xd1;
+    c.mem[1] = 0x22;
+
+    c.execute_insn().unwrap();
+    assert_eq!(c.display.get_pixel(10, 20), 1);
+    assert_eq!(c.display.get_pixel(11, 20), 0);
+    assert_eq!(c.display.get_pixel(17, 20), 1);
+    assert_eq!(c.display.get_pixel(11, 21), 1);
+    assert_eq!(c.v[0xf], 0);
+    assert_eq!(c.pc, 2);
+}
+
+#[test]
+fn test2_draw_sprite() {
+    let mut c = CPU::headless();
+    c.pc = 0;
+    // Instruction: 0xd121
+    // Drawing the same sprite twice erases it
+    // and sets v[f].
+
+    c.v[1] = 62; // Wraps to the left edge
+    c.v[2] = 0;
+    c.i = 0x300;
+    c.mem[0x300] = 0b1110_0000;
+    c.mem[0] = 0xd1;
+    c.mem[1] = 0x21;
+    c.mem[2] = 0xd1;
+    c.mem[3] = 0x21;
+
+    c.execute_insn().unwrap();
+    assert_eq!(c.display.get_pixel(63, 0), 1);
+    assert_eq!(c.display.get_pixel(0, 0), 1);
+    assert_eq!(c.v[0xf], 0);
+
+    c.execute_insn().unwrap();
+    assert_eq!(c.display.get_pixel(63, 0), 0);
+    assert_eq!(c.display.get_pixel(0, 0), 0);
+    assert_eq!(c.v[0xf], 1);
+    assert_eq!(c.pc, 4);
+}
+
+#[test]
+fn test1_get_key() {
+    let (mut c, k) = cpu_with_keypad();
+    c.pc = 0;
+    // Instruction: 0xf30a
+    // v[3] = key pressed and released
+
+    c.mem[0] = 0xf3;
+    c.mem[1] = 0x0a;
+
+    k.borrow_mut().press(0xb);
+    c.execute_insn().unwrap();
+    assert_eq!(c.pc, 0);
+    // Other keys pressed meanwhile are ignored.
+    k.borrow_mut().press(0x2);
+    c.execute_insn().unwrap();
+    assert_eq!(c.pc, 0);
+
+    k.borrow_mut().release(0xb);
+    c.execute_insn().unwrap();
+    assert_eq!(c.v[3], 0xb);
+    assert_eq!(c.pc, 2);
+}
+
+#[test]
+fn test2_get_key() {
+    let mut c = CPU::headless();
+    c.pc = 0;
+    // Instruction: 0xf30a
+    // No key is pressed, so the instruction
+    // is executed again.
+
+    c.mem[0] = 0xf3;
+    c.mem[1] = 0x0a;
+
+    c.execute_insn().unwrap();
+    assert_eq!(c.pc, 0);
+}
+
+#[test]
+fn test1_skip_if_key_eq_vx() {
+    let (mut c, k) = cpu_with_keypad();
+    c.pc = 0;
+    // Instruction: 0xe49e
+    // Skip next instruction if key v[4] is pressed
+
+    k.borrow_mut().press(0x7);
+    c.v[4] = 0x7;
+    c.mem[0] = 0xe4;
+    c.mem[1] = 0x9e;
+    c.mem[4] = 0xe4;
+    c.mem[5] = 0x9e;
+
+    c.execute_insn().unwrap();
+    assert_eq!(c.pc, 4);
+    // A key held down is seen by every instruction.
+    c.execute_insn().unwrap();
+    assert_eq!(c.pc, 8);
+}
+
+#[test]
+fn test2_skip_if_key_eq_vx() {
+    let (mut c, k) = cpu_with_keypad();
+    c.pc = 0;
+    // Instruction: 0xe49e
+    // Skip next instruction if key v[4] is pressed
+
+    k.borrow_mut().press(0x8);
+    c.v[4] = 0x7;
+    c.mem[0] = 0xe4;
+    c.mem[1] = 0x9e;
+
+    c.execute_insn().unwrap();
+    assert_eq!(c.pc, 2);
+}
+
+#[test]
+fn test1_skip_if_key_ne_vx() {
+    let (mut c, k) = cpu_with_keypad();
+    c.pc = 0;
+    // Instruction: 0xe4a1
+    // Skip next instruction if key v[4] is not pressed
+
+    k.borrow_mut().press(0x7);
+    c.v[4] = 0x7;
+    c.mem[0] = 0xe4;
+    c.mem[1] = 0xa1;
+
+    c.execute_insn().unwrap();
+    assert_eq!(c.pc, 2);
+}
+
+#[test]
+fn test2_skip_if_key_ne_vx() {
+    let mut c = CPU::headless();
+    c.pc = 0;
+    // Instruction: 0xe4a1
+    // Skip next instruction if key v[4] is not pressed
+
+    c.v[4] = 0x7;
+    c.mem[0] = 0xe4;
+    c.mem[1] = 0xa1;
+
+    c.execute_insn().unwrap();
+    assert_eq!(c.pc, 4);
+}
+
+#[test]
+fn test_run_rom_headless() {
+    // Run a whole game without a window.
+    let mut c = CPU::headless();
+    let rom = File::open(concat!(env!("CARGO_MANIFEST_DIR"), "/roms/PONG")).unwrap();
+    c.load_rom(rom, PC_START).unwrap();
+
+    for _ in 0..10000 {
+        c.execute_insn().unwrap();
+    }
+    let lit = (0..u32::from(SCREEN_HEIGHT))
+        .flat_map(|y| (0..u32::from(SCREEN_WIDTH)).map(move |x| (x, y)))
+        .filter(|&(x, y)| c.display.get_pixel(x, y) == 1)
+        .count();
+    assert!(lit > 0);
+}
+
+#[test]
+fn test_shr_vx_quirk_shift_uses_vy() {
+    let mut c = CPU::headless();
+    c.set_quirks(Quirks { shift_uses_vy: true, ..Quirks::default() });
+    c.pc = 0;
+    // Instruction: 0x8706
+    // v[7] = v[0] >> 1
+
+    c.v[7] = 0;
+    c.v[0] = 5;
+    c.mem[0] = 0x87;
+    c.mem[1] = 0x06;
+
+    c.execute_insn().unwrap();
+    assert_eq!(c.v[7], 2);
+    assert_eq!(c.v[0xf], 1);
+    assert_eq!(c.pc, 2);
+}
+
+#[test]
+fn test_shl_vx_quirk_shift_uses_vy() {
+    let mut c = CPU::headless();
+    c.set_quirks(Quirks { shift_uses_vy: true, ..Quirks::default() });
+    c.pc = 0;
+    // Instruction: 0x871e
+    // v[7] = v[1] << 1
+
+    c.v[7] = 0;
+    c.v[1] = 0x81;
+    c.mem[0] = 0x87;
+    c.mem[1] = 0x1e;
+
+    c.execute_insn().unwrap();
+    assert_eq!(c.v[7], 2);
+    assert_eq!(c.v[0xf], 1);
+    assert_eq!(c.pc, 2);
+}
+
+#[test]
+fn test_store_v0_to_vx_to_mem_quirk_increments_i() {
+    let mut c = CPU::headless();
+    c.set_quirks(Quirks { load_store_increments_i: true, ..Quirks::default() });
+    c.pc = 0;
+    // Instruction: 0xf255
+    // Store v[0] to v[2], then i += 3
+
+    c.i = 0x300;
+    c.mem[0] = 0xf2;
+    c.mem[1] = 0x55;
+
+    c.execute_insn().unwrap();
+    assert_eq!(c.i, 0x303);
+}
+
+#[test]
+fn test_fill_v0_to_vx_from_mem_quirk_increments_i() {
+    let mut c = CPU::headless();
+    c.set_quirks(Quirks { load_store_increments_i: true, ..Quirks::default() });
+    c.pc = 0;
+    // Instruction: 0xf365
+    // Fill v[0] to v[3], then i += 4
+
+    c.i = 0x300;
+    c.mem[0] = 0xf3;
+    c.mem[1] = 0x65;
+
+    c.execute_insn().unwrap();
+    assert_eq!(c.i, 0x304);
+}
+
+#[test]
+fn test_jmp_to_address_plus_v0_quirk_jump_uses_vx() {
+    let mut c = CPU::headless();
+    c.set_quirks(Quirks { jump_uses_vx: true, ..Quirks::default() });
+    c.pc = 0;
+    // Instruction: 0xb123
+    // pc = 0x123 + v[1]
+
+    c.v[0] = 0x3;
+    c.v[1] = 0x10;
+    c.mem[0] = 0xb1;
+    c.mem[1] = 0x23;
+
+    c.execute_insn().unwrap();
+    assert_eq!(c.pc, 0x133);
+}
+
+#[test]
+fn test_logic_ops_quirk_vf_reset() {
+    let mut c = CPU::headless();
+    c.set_quirks(Quirks { vf_reset: true, ..Quirks::default() });
+    c.pc = 0;
+    // Instruction: 0x85c1
+    // v[5] = v[5] | v[0xc], v[f] = 0
+
+    c.v[0xf] = 1;
+    c.mem[0] = 0x85;
+    c.mem[1] = 0xc1;
+
+    c.execute_insn().unwrap();
+    assert_eq!(c.v[0xf], 0);
+}
+
+#[test]
+fn test_draw_sprite_quirk_clip_sprites() {
+    let mut c = CPU::headless();
+    c.set_quirks(Quirks { clip_sprites: true, ..Quirks::default() });
+    c.pc = 0;
+    // Instruction: 0xd122
+    // Only the top left pixel of the sprite is on screen.
+
+    c.v[1] = 63;
+    c.v[2] = 31;
+    c.i = 0x300;
+    c.mem[0x300] = 0xff;
+    c.mem[0x301] = 0xff;
+    c.mem[0] = 0xd1;
+    c.mem[1] = 0x22;
+
+    c.execute_insn().unwrap();
+    assert_eq!(c.display.get_pixel(63, 31), 1);
+    assert_eq!(c.display.get_pixel(0, 31), 0);
+    assert_eq!(c.display.get_pixel(63, 0), 0);
+    assert_eq!(c.display.get_pixel(0, 0), 0);
+}
+
+#[test]
+fn test_quirks_from_str() {
+    assert_eq!("schip".parse::<Quirks>().unwrap(), Quirks::schip());
+    assert_eq!("default".parse::<Quirks>().unwrap(), Quirks::default());
+    assert!("cosmac".parse::<Quirks>().is_err());
+}
+
+#[test]
+fn test_hires_draw_sprite() {
+    let mut c = CPU::headless();
+    c.pc = 0;
+    // Instructions: 0x00ff, 0xd121
+    // Switch to 128x64, then draw a 1 row sprite at
+    // (v[1], v[2]), beyond the low resolution screen.
+
+    c.v[1] = 120;
+    c.v[2] = 60;
+    c.i = 0x300;
+    c.mem[0x300] = 0x80;
+    c.mem[0] = 0x00;
+    c.mem[1] = 0xff;
+    c.mem[2] = 0xd1;
+    c.mem[3] = 0x21;
+
+    c.execute_insn().unwrap();
+    c.execute_insn().unwrap();
+    assert_eq!(c.display.get_pixel(120, 60), 1);
+    assert_eq!(c.pc, 4);
+}
+
+#[test]
+fn test_lores() {
+    let mut c = CPU::headless();
+    c.pc = 0;
+    // Instructions: 0x00ff, 0x00fe
+    // Switch to 128x64 and back to 64x32.
+
+    c.mem[0] = 0x00;
+    c.mem[1] = 0xff;
+    c.mem[2] = 0x00;
+    c.mem[3] = 0xfe;
+
+    c.execute_insn().unwrap();
+    assert_eq!(c.screen_width(), 128);
+    c.execute_insn().unwrap();
+    assert_eq!(c.screen_width(), 64);
+    assert_eq!(c.screen_height(), 32);
+    assert_eq!(c.pc, 4);
+}
+
+#[test]
+fn test_draw_16x16_sprite() {
+    let mut c = CPU::headless();
+    c.pc = 0;
+    // Instruction: 0xd120
+    // Draw a 16x16 sprite at (v[1], v[2])
+
+    c.v[1] = 0;
+    c.v[2] = 0;
+    c.i = 0x300;
+    for n in 0..32 {
+        c.mem[0x300 + n] = 0xff;
+    }
+    c.mem[0] = 0xd1;
+    c.mem[1] = 0x20;
+
+    c.execute_insn().unwrap();
+    assert_eq!(c.display.get_pixel(15, 15), 1);
+    assert_eq!(c.display.get_pixel(16, 15), 0);
+    assert_eq!(c.display.get_pixel(15, 16), 0);
+    assert_eq!(c.pc, 2);
+}
+
+#[test]
+fn test_scroll_down() {
+    let mut c = CPU::headless();
+    c.pc = 0;
+    // Instruction: 0x00c3
+    // Scroll down 3 pixels
+
+    c.display.draw_pixel(5, 0, 1);
+    c.mem[0] = 0x00;
+    c.mem[1] = 0xc3;
+
+    c.execute_insn().unwrap();
+    assert_eq!(c.display.get_pixel(5, 0), 0);
+    assert_eq!(c.display.get_pixel(5, 3), 1);
+    assert_eq!(c.pc, 2);
+}
+
+#[test]
+fn test_scroll_right_and_left() {
+    let mut c = CPU::headless();
+    c.pc = 0;
+    // Instructions: 0x00fb, 0x00fc, 0x00fc
+    // Scroll right 4 pixels, then left 8 pixels.
+
+    c.display.draw_pixel(5, 1, 1);
+    c.mem[0] = 0x00;
+    c.mem[1] = 0xfb;
+    c.mem[2] = 0x00;
+    c.mem[3] = 0xfc;
+    c.mem[4] = 0x00;
+    c.mem[5] = 0xfc;
+
+    c.execute_insn().unwrap();
+    assert_eq!(c.display.get_pixel(9, 1), 1);
+    c.execute_insn().unwrap();
+    c.execute_insn().unwrap();
+    assert_eq!(c.display.get_pixel(1, 1), 1);
+    assert_eq!(c.display.get_pixel(5, 1), 0);
+    assert_eq!(c.pc, 6);
+}
+
+#[test]
+fn test_exit() {
+    let mut c = CPU::headless();
+    c.pc = 0;
+    // Instruction: 0x00fd
+    // Exit the interpreter
+
+    c.mem[0] = 0x00;
+    c.mem[1] = 0xfd;
+
+    c.execute_insn().unwrap();
+    assert!(c.exited());
+    c.execute_insn().unwrap();
+    assert_eq!(c.pc, 0);
+}
+
+#[test]
+fn test_set_ireg_to_big_sprite_address() {
+    let mut c = CPU::headless();
+    c.pc = 0;
+    // Instruction: 0xf430
+    // i = address of 8x10 character v[4]
+
+    c.v[4] = 0x2;
+    c.mem[0] = 0xf4;
+    c.mem[1] = 0x30;
+
+    c.execute_insn().unwrap();
+    assert_eq!(c.i, font::BIG_FONT_START + 20);
+    assert_eq!(c.mem[c.i + 2], 0x03);
+    assert_eq!(c.pc, 2);
+}
+
+#[test]
+fn test_store_and_fill_rpl() {
+    let mut c = CPU::headless();
+    c.pc = 0;
+    // Instructions: 0xf275, 0xff85
+    // Store v[0] to v[2] in the RPL flags, then
+    // fill v[0] to v[7] from them.
+
+    c.v[0] = 1;
+    c.v[1] = 2;
+    c.v[2] = 3;
+    c.v[3] = 4;
+    c.mem[0] = 0xf2;
+    c.mem[1] = 0x75;
+    c.mem[2] = 0xff;
+    c.mem[3] = 0x85;
+
+    c.execute_insn().unwrap();
+    c.v[0] = 0;
+    c.execute_insn().unwrap();
+    assert_eq!(&c.v[..4], &[1, 2, 3, 0]);
+    assert_eq!(c.pc, 4);
+}
+
+#[test]
+fn test_rpl_file_unreadable() {
+    let mut c = CPU::headless();
+    let dir = ::std::env::temp_dir();
+    assert!(c.set_rpl_file(dir.to_str().unwrap()).is_err());
+    assert!(c.rpl_file.is_none());
+    let missing = dir.join("chip8-no-such-rpl-file");
+    assert!(c.set_rpl_file(missing.to_str().unwrap()).is_ok());
+    assert_eq!(c.rpl, [0; NUM_RPL_FLAGS]);
+}
+
+#[test]
+fn test_save_and_load_state() {
+    let mut c = CPU::headless();
+    c.pc = 0;
+    // Instructions: 0x6105, 0xd121
+    // Draw a sprite, then restore the state
+    // into another CPU.
+
+    c.i = 0x300;
+    c.mem[0x300] = 0xf0;
+    c.mem[0] = 0x61;
+    c.mem[1] = 0x05;
+    c.mem[2] = 0xd1;
+    c.mem[3] = 0x21;
+    c.delay = 9;
+
+    c.execute_insn().unwrap();
+    c.execute_insn().unwrap();
+    let st = c.save_state();
+
+    let mut c2 = CPU::headless();
+    c2.load_state(&st).unwrap();
+    assert_eq!(c2.pc, 4);
+    assert_eq!(c2.v[1], 5);
+    assert_eq!(c2.i, 0x300);
+    assert_eq!(c2.delay, 9);
+    assert_eq!(c2.display.get_pixel(5, 0), 1);
+    assert_eq!(c2.display.get_pixel(9, 0), 0);
+    assert_eq!(c2.save_state(), st);
+}
+
+#[test]
+fn test_load_state_bad_size() {
+    let mut c = CPU::headless();
+    let mut st = c.save_state();
+    st.mem.pop();
+    assert!(c.load_state(&st).is_err());
+    assert_eq!(c.pc, PC_START);
+
+    let mut st = c.save_state();
+    st.pixels.pop();
+    assert!(c.load_state(&st).is_err());
+}
+
+/// A CPU with the program "src", written in the syntax of
+/// `asm::assemble`, loaded at PC_START.
+fn cpu_with_program(src: &str) -> CPU {
+    let mut c = CPU::headless();
+    let rom = asm::assemble(src, PC_START).unwrap();
+    c.mem[PC_START..PC_START + rom.len()].copy_from_slice(&rom);
+    c
+}
+
+#[test]
+fn test_program_loop() {
+    // Add 1 + 2 + ... + 10, and store the sum in memory.
+    let mut c = cpu_with_program("
+        v0 := 0
+        v1 := 10
+        : loop
+        v0 += v1
+        v1 += 0xff        # v1 -= 1
+        if v1 != 0 then
+        jump loop
+        i := result
+        save v0
+        exit
+        : result
+        0
+    ");
+    for _ in 0..100 {
+        c.execute_insn().unwrap();
+    }
+    assert!(c.exited);
+    assert_eq!(c.v[0], 55);
+    assert_eq!(c.mem[c.i], 55);
+}
+
+#[test]
+fn test_program_subroutine() {
+    let mut c = cpu_with_program("
+        :call double
+        :call double
+        exit
+        : double
+        v2 += v2
+        return
+    ");
+    c.v[2] = 3;
+    for _ in 0..10 {
+        c.execute_insn().unwrap();
+    }
+    assert!(c.exited);
+    assert_eq!(c.v[2], 12);
+    assert_eq!(c.sp, SP_BOTTOM);
+}
+
+#[test]
+fn test_invalid_insn() {
+    for &opcode in &[0x0123u16, 0x8008, 0xe1ff, 0xf0ff] {
+        let mut c = CPU::headless();
+        c.mem[PC_START] = (opcode >> 8) as u8;
+        c.mem[PC_START + 1] = opcode as u8;
+        assert_eq!(c.execute_insn(), Err(Chip8Error::InvalidOpcode { pc: PC_START, opcode }));
+        assert_eq!(c.pc, PC_START);
+    }
+}
+
+/// A CPU running "src" with a buzzer which records what it
+/// was asked to play.
+fn cpu_with_audio(src: &str) -> (CPU, Rc<RefCell<RecordingAudio>>) {
+    let mut c = cpu_with_program(src);
+    let a = Rc::new(RefCell::new(RecordingAudio::new()));
+    c.set_audio(Box::new(a.clone()));
+    a.borrow_mut().events.clear();
+    (c, a)
+}
+
+#[test]
+fn test_sound_timer() {
+    let (mut c, a) = cpu_with_audio("
+        v0 := 2
+        buzzer := v0
+    ");
+    c.execute_insn().unwrap();
+    c.execute_insn().unwrap();
+    assert!(a.borrow().playing());
+    c.vblank();
+    assert!(a.borrow().playing());
+    c.vblank();
+    assert!(!a.borrow().playing());
+    assert_eq!(a.borrow().events, [AudioEvent::Start, AudioEvent::Stop]);
+}
+
+#[test]
+fn test_audio_pattern() {
+    let (mut c, a) = cpu_with_audio("
+        i := pattern
+        audio
+        v1 := 112
+        pitch := v1
+        : pattern
+        0xff 0x00 0xff 0x00 0xff 0x00 0xff 0x00
+        0xf0 0x0f 0xf0 0x0f 0xf0 0x0f 0xf0 0x0f
+    ");
+    assert_eq!(c.waveform(), Waveform::Square);
+    c.execute_insn().unwrap();
+    c.execute_insn().unwrap();
+    let pattern = [0xff, 0x00, 0xff, 0x00, 0xff, 0x00, 0xff, 0x00,
+                   0xf0, 0x0f, 0xf0, 0x0f, 0xf0, 0x0f, 0xf0, 0x0f];
+    assert_eq!(c.waveform(), Waveform::Pattern(pattern, 4000.0));
+    assert_eq!(c.i, PC_START + 8);
+    c.execute_insn().unwrap();
+    c.execute_insn().unwrap();
+    assert_eq!(c.waveform(), Waveform::Pattern(pattern, 8000.0));
+    assert_eq!(a.borrow().events,
+               [AudioEvent::Waveform(Waveform::Pattern(pattern, 4000.0)),
+                AudioEvent::Waveform(Waveform::Pattern(pattern, 8000.0))]);
+}
+
+#[test]
+fn test_audio_save_state() {
+    let (mut c, a) = cpu_with_audio("
+        i := 0
+        audio
+        v0 := 10
+        buzzer := v0
+    ");
+    let st = c.save_state();
+    for _ in 0..4 {
+        c.execute_insn().unwrap();
+    }
+    let st2 = c.save_state();
+    assert_eq!(st2.pattern, &c.mem[..PATTERN_SIZE]);
+
+    c.load_state(&st).unwrap();
+    assert_eq!(c.waveform(), Waveform::Square);
+    assert!(!a.borrow().playing());
+    c.load_state(&st2).unwrap();
+    assert_eq!(c.pattern.map(|p| p.to_vec()), Some(st2.pattern));
+    assert!(a.borrow().playing());
+}
+
+/// A CPU running "src" with the fault policy "policy".
+fn cpu_with_policy(src: &str, policy: FaultPolicy) -> CPU {
+    let mut c = cpu_with_program(src);
+    c.set_fault_policy(policy);
+    c
+}
+
+#[test]
+fn test_fault_halt() {
+    let cases = [
+        ("i := 0xffe\nsave v2", Chip8Error::AddressOutOfRange { pc: PC_START + 2, addr: 0x1000 }),
+        ("i := 0xfff\nv0 := 0xff\ni += v0\nload v0",
+         Chip8Error::AddressOutOfRange { pc: PC_START + 6, addr: 0x10fe }),
+        ("i := 0xffe\nbcd v0", Chip8Error::AddressOutOfRange { pc: PC_START + 2, addr: 0x1000 }),
+        ("i := 0xff0\nsprite v0 v0 0", Chip8Error::AddressOutOfRange { pc: PC_START + 2, addr: 0x1000 }),
+        ("return", Chip8Error::StackUnderflow { pc: PC_START }),
+        (": loop\n:call loop", Chip8Error::StackOverflow { pc: PC_START }),
+        ("jump 0xffe", Chip8Error::InvalidOpcode { pc: 0xffe, opcode: 0 }),
+    ];
+    for &(src, err) in cases.iter() {
+        let mut c = cpu_with_policy(src, FaultPolicy::Halt);
+        let result = (0..100).map(|_| c.execute_insn()).find(|r| r.is_err());
+        assert_eq!(result, Some(Err(err)), "{}", src);
+        // The faulting instruction had no effect.
+        let st = c.save_state();
+        assert_eq!(c.execute_insn(), Err(err));
+        assert_eq!(c.save_state(), st);
+    }
+}
+
+#[test]
+fn test_fault_pc_out_of_range() {
+    let mut c = CPU::headless();
+    c.pc = 0xfff;
+    assert_eq!(c.execute_insn(), Err(Chip8Error::AddressOutOfRange { pc: 0xfff, addr: 0x1000 }));
+    c.pc = 0x10fe;
+    assert_eq!(c.execute_insn(), Err(Chip8Error::AddressOutOfRange { pc: 0x10fe, addr: 0x10fe }));
+
+    // Wrap around to 0x0fe, where "v1 := 5" is.
+    c.set_fault_policy(FaultPolicy::Wrap);
+    c.mem[0xfe] = 0x61;
+    c.mem[0xff] = 0x05;
+    c.execute_insn().unwrap();
+    assert_eq!((c.pc, c.v[1]), (0x100, 5));
+}
+
+#[test]
+fn test_fault_wrap() {
+    let mut c = cpu_with_policy("i := 0xffe\nv0 := 1\nv1 := 2\nv2 := 3\nsave v2",
+                                FaultPolicy::Wrap);
+    for _ in 0..5 {
+        c.execute_insn().unwrap();
+    }
+    assert_eq!((c.mem[0xffe], c.mem[0xfff], c.mem[0]), (1, 2, 3));
+
+    // The 25th call overwrites the oldest entry.
+    let mut c = cpu_with_policy(": loop\n:call loop", FaultPolicy::Wrap);
+    for _ in 0..STACK_SIZE + 1 {
+        c.execute_insn().unwrap();
+    }
+    assert_eq!(c.sp, SP_BOTTOM + 2);
+    assert_eq!(c.stack(), [PC_START + 2]);
+
+    // A return with the stack empty uses the top entry.
+    let mut c = cpu_with_policy("return", FaultPolicy::Wrap);
+    c.mem[SP_TOP] = 0x03;
+    c.mem[SP_TOP + 1] = 0x45;
+    c.execute_insn().unwrap();
+    assert_eq!((c.pc, c.sp), (0x345, SP_TOP - 2));
+}
+
+#[test]
+fn test_fault_ignore() {
+    let mut c = cpu_with_policy("return\ni := 0xfff\nload v3\n0x01 0x23\nv0 := 7",
+                                FaultPolicy::Ignore);
+    for _ in 0..5 {
+        c.execute_insn().unwrap();
+    }
+    assert_eq!((c.pc, c.sp, c.v[0]), (PC_START + 10, SP_BOTTOM, 7));
+    assert_eq!(c.v[1..4], [0, 0, 0]);
+}
+
+#[test]
+fn test_load_rom_too_large() {
+    let pong = fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/roms/PONG")).unwrap();
+    let size = pong.len();
+    let offset = MEM_SIZE - 10;
+
+    let mut c = CPU::headless();
+    assert_eq!(c.load_rom(&pong[..], offset), Err(Chip8Error::RomTooLarge { size, offset }));
+    assert!(c.mem[offset..].iter().all(|&b| b == 0));
+
+    c.set_fault_policy(FaultPolicy::Ignore);
+    c.load_rom(&pong[..], offset).unwrap();
+    assert_eq!(c.mem[offset..], pong[..10]);
+    assert_eq!(c.mem[..font::FONT.len()], font::FONT[..]);
+
+    let mut c = CPU::headless();
+    c.set_fault_policy(FaultPolicy::Wrap);
+    c.load_rom(&pong[..], offset).unwrap();
+    assert_eq!(c.mem[offset..], pong[..10]);
+    assert_eq!(c.mem[..size - 10], pong[10..]);
+}
+
+/// A ROM which can not be read.
+struct BrokenRom;
+
+impl Read for BrokenRom {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "broken"))
+    }
+}
+
+#[test]
+fn test_load_rom_io_error() {
+    let mut c = CPU::headless();
+    assert_eq!(c.load_rom(BrokenRom, 0), Err(Chip8Error::Io(io::ErrorKind::PermissionDenied)));
+}
+
+#[test]
+fn test_builtin_fonts() {
+    let c = CPU::headless();
+    let font = fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/font/FONTS.chip8")).unwrap();
+    assert_eq!(c.mem[font::FONT_START..font::FONT_START + font.len()], font[..]);
+    assert_eq!(c.mem[font::BIG_FONT_START..font::BIG_FONT_START + font::BIG_FONT.len()],
+               font::BIG_FONT[..]);
+}
+
+#[test]
+fn test_assign_long_address_to_ireg() {
+    let mut c = cpu_with_program("i := long 0x1234\nv0 := 1\nif v0 != 1 then\ni := long 0x0345\nv1 := 2");
+    c.execute_insn().unwrap();
+    assert_eq!((c.i, c.pc), (0x1234, PC_START + 4));
+    c.execute_insn().unwrap();
+    // The skip goes over the 4 bytes of "f000 nnnn".
+    c.execute_insn().unwrap();
+    assert_eq!(c.pc, PC_START + 12);
+    c.execute_insn().unwrap();
+    assert_eq!((c.i, c.v[1]), (0x1234, 2));
+}
+
+#[test]
+fn test_store_and_fill_vx_to_vy() {
+    let mut c = cpu_with_program("i := 0x300\nsave v2 - v4\nload v7 - v5");
+    c.v[2..5].copy_from_slice(&[1, 2, 3]);
+    for _ in 0..3 {
+        c.execute_insn().unwrap();
+    }
+    assert_eq!(c.mem[0x300..0x303], [1, 2, 3]);
+    // In reverse order, and "i" is not changed.
+    assert_eq!(c.v[5..8], [3, 2, 1]);
+    assert_eq!(c.i, 0x300);
+}
+
+#[test]
+fn test_draw_sprite_planes() {
+    let mut c = cpu_with_program("plane 3\ni := 0x300\nsprite v0 v0 1\nplane 2\nsprite v0 v0 1");
+    c.mem[0x300] = 0xc0;
+    c.mem[0x301] = 0x60;
+    for _ in 0..3 {
+        c.execute_insn().unwrap();
+    }
+    // The first plane gets the first row, the second one the
+    // row after it.
+    assert_eq!((0..3).map(|x| c.display.get_pixel(x, 0)).collect::<Vec<_>>(), [1, 3, 2]);
+    assert_eq!(c.v[0xf], 0);
+    c.execute_insn().unwrap();
+    c.execute_insn().unwrap();
+    assert_eq!((0..3).map(|x| c.display.get_pixel(x, 0)).collect::<Vec<_>>(), [3, 1, 2]);
+    assert_eq!(c.v[0xf], 1);
+    assert_eq!(c.planes(), 2);
+}
+
+#[test]
+fn test_clear_and_scroll_planes() {
+    let mut c = cpu_with_program("plane 1\nclear\nscroll-down 1\nplane 0\nclear");
+    c.display.draw_pixel(5, 0, 3);
+    c.display.draw_pixel(6, 0, 2);
+    c.execute_insn().unwrap();
+    c.execute_insn().unwrap();
+    assert_eq!((c.display.get_pixel(5, 0), c.display.get_pixel(6, 0)), (2, 2));
+    c.display.draw_pixel(7, 0, 1);
+    c.execute_insn().unwrap();
+    assert_eq!((c.display.get_pixel(7, 0), c.display.get_pixel(7, 1)), (0, 1));
+    assert_eq!(c.display.get_pixel(5, 0), 2);
+    // No plane is selected: nothing is cleared.
+    c.execute_insn().unwrap();
+    c.execute_insn().unwrap();
+    assert_eq!(c.display.get_pixel(5, 0), 2);
+}
+
+#[test]
+fn test_scroll_up_planes() {
+    let mut c = cpu_with_program("plane 2\nscroll-up 2\nplane 3\nscroll-up 1");
+    c.display.draw_pixel(5, 3, 3);
+    c.display.draw_pixel(6, 3, 1);
+    c.execute_insn().unwrap();
+    c.execute_insn().unwrap();
+    // Only the second plane moved up.
+    assert_eq!((c.display.get_pixel(5, 1), c.display.get_pixel(5, 3)), (2, 1));
+    assert_eq!((c.display.get_pixel(6, 1), c.display.get_pixel(6, 3)), (0, 1));
+    c.execute_insn().unwrap();
+    c.execute_insn().unwrap();
+    assert_eq!((c.display.get_pixel(5, 0), c.display.get_pixel(5, 2)), (2, 1));
+    assert_eq!(c.display.get_pixel(6, 2), 1);
+    assert_eq!(c.pc, PC_START + 8);
+}
+
+#[test]
+fn test_large_memory() {
+    let mut c = cpu_with_program("i := long 0xfffe\nv0 := 1\nv1 := 2\nv2 := 3\nsave v2");
+    c.set_quirks("xochip".parse().unwrap());
+    assert_eq!(c.mem.len(), LARGE_MEM_SIZE);
+    assert_eq!(c.mem[PC_START..PC_START + 2], [0xf0, 0x00]);
+    c.set_fault_policy(FaultPolicy::Wrap);
+    for _ in 0..5 {
+        c.execute_insn().unwrap();
+    }
+    assert_eq!((c.mem[0xfffe], c.mem[0xffff], c.mem[0]), (1, 2, 3));
+
+    // Without the quirk, "i" is past the end of memory.
+    c.set_quirks(Quirks::default());
+    assert_eq!(c.mem.len(), MEM_SIZE);
+    c.pc = PC_START;
+    c.execute_insn().unwrap();
+    c.pc = PC_START + 12;
+    c.set_fault_policy(FaultPolicy::Halt);
+    assert!(c.execute_insn().is_err());
+}
@@ -0,0 +1,453 @@
+// debugger.rs
+
+// An interactive debugger. The main loop hands each
+// instruction over to `Debugger::step`, which executes it
+// and decides whether to stop. While stopped, commands are
+// read by `Debugger::repl`, or passed to `Debugger::command`
+// directly from tests.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+
+use cheat::{Predicate, Search};
+use cpu::CPU;
+use disasm;
+use error::Chip8Error;
+
+static HELP: &str = "\
+commands:
+  s, step [n]         execute n instructions (default 1)
+  c, continue         run until a breakpoint or watchpoint is hit
+  b, break ADDR       set a breakpoint on PC == ADDR
+  d, delete ADDR      delete the breakpoint at ADDR
+  w, watch WHAT       stop when WHAT changes: vX, i or mem ADDR
+  u, unwatch WHAT     delete a watchpoint
+  r, regs             show the registers, timers and stack
+  x ADDR [LEN]        hexdump LEN bytes of memory (default 16)
+  l, list [ADDR]      disassemble around ADDR (default PC)
+  search [HOW]        search memory for a value: start over, or keep the
+                      addresses whose value is HOW since the last search:
+                      eq N, changed, unchanged, inc or dec
+  freeze [ADDR N]     write N at ADDR every frame, or list frozen addresses
+  unfreeze ADDR       stop writing to ADDR every frame
+  poke ADDR N...      write bytes to memory from ADDR on
+  q, quit             quit the emulator
+  h, help             show this message
+addresses and values are hex, with or without 0x, and counts (n, LEN) decimal";
+
+/// The most candidates of a memory search listed.
+const MAX_LISTED_CANDIDATES: usize = 16;
+
+/// Something whose value is watched.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Watchpoint {
+    /// The memory location at this address.
+    Mem(usize),
+    /// The general purpose register v[x].
+    V(usize),
+    /// The address register.
+    I,
+}
+
+impl Watchpoint {
+    /// The value watched. A memory location beyond the end of
+    /// memory, which may have shrunk, reads as 0.
+    fn value(&self, cpu: &CPU) -> usize {
+        match *self {
+            Watchpoint::Mem(addr) => cpu.mem().get(addr).map_or(0, |&b| usize::from(b)),
+            Watchpoint::V(x) => usize::from(cpu.v()[x]),
+            Watchpoint::I => cpu.i(),
+        }
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Watchpoint::Mem(addr) => write!(f, "mem 0x{:03x}", addr),
+            Watchpoint::V(x) => write!(f, "v{:x}", x),
+            Watchpoint::I => write!(f, "i"),
+        }
+    }
+}
+
+/// Why execution stopped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    /// The requested number of instructions were executed.
+    Step,
+    /// PC reached a breakpoint.
+    Breakpoint(usize),
+    /// A watched value changed from the first to the
+    /// second value.
+    Watchpoint(Watchpoint, usize, usize),
+    /// The program executed the "exit" instruction.
+    Exited,
+    /// The instruction at PC faulted.
+    Error(Chip8Error),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StopReason::Step => write!(f, "stepped"),
+            StopReason::Breakpoint(addr) => write!(f, "breakpoint at 0x{:03x}", addr),
+            StopReason::Watchpoint(w, old, new) =>
+                write!(f, "watchpoint: {} changed from 0x{:02x} to 0x{:02x}", w, old, new),
+            StopReason::Exited => write!(f, "program exited"),
+            StopReason::Error(e) => write!(f, "error: {}", e),
+        }
+    }
+}
+
+pub struct Debugger {
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeSet<Watchpoint>,
+    /// True while execution is stopped.
+    paused: bool,
+    /// Number of instructions left to execute before
+    /// stopping, when single-stepping.
+    steps_left: Option<u64>,
+    /// True once the user has asked to quit.
+    quit: bool,
+    /// The memory search going on, if any.
+    search: Option<Search>,
+}
+
+impl Default for Debugger {
+    fn default() -> Debugger {
+        Debugger::new()
+    }
+}
+
+/// Parse a hex address, with or without a "0x" prefix.
+fn parse_addr(s: &str) -> Option<usize> {
+    let s = s.trim_start_matches("0x");
+    usize::from_str_radix(s, 16).ok()
+}
+
+/// Parse a hex byte.
+fn parse_byte(s: &str) -> Option<u8> {
+    u8::from_str_radix(s.trim_start_matches("0x"), 16).ok()
+}
+
+fn parse_predicate(args: &[&str]) -> Option<Predicate> {
+    match args {
+        ["eq", val] => parse_byte(val).map(Predicate::Equal),
+        ["changed"] => Some(Predicate::Changed),
+        ["unchanged"] => Some(Predicate::Unchanged),
+        ["inc"] | ["increased"] => Some(Predicate::Increased),
+        ["dec"] | ["decreased"] => Some(Predicate::Decreased),
+        _ => None,
+    }
+}
+
+fn parse_watchpoint(args: &[&str]) -> Option<Watchpoint> {
+    match args {
+        ["i"] => Some(Watchpoint::I),
+        ["mem", addr] => parse_addr(addr).map(Watchpoint::Mem),
+        [reg] if reg.len() == 2 && reg.starts_with('v') =>
+            usize::from_str_radix(&reg[1..], 16).ok().map(Watchpoint::V),
+        _ => None,
+    }
+}
+
+impl Debugger {
+    /// A debugger which is stopped, so that breakpoints can be
+    /// set before the first instruction is executed.
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+            paused: true,
+            steps_left: None,
+            quit: false,
+            search: None,
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, addr: usize) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: usize) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn add_watchpoint(&mut self, w: Watchpoint) {
+        self.watchpoints.insert(w);
+    }
+
+    pub fn remove_watchpoint(&mut self, w: Watchpoint) -> bool {
+        self.watchpoints.remove(&w)
+    }
+
+    /// True while execution is stopped.
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// True once the user has asked to quit.
+    pub fn quit_requested(&self) -> bool {
+        self.quit
+    }
+
+    /// Resume execution until a breakpoint or watchpoint is hit.
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.steps_left = None;
+    }
+
+    /// Resume execution for `n` instructions.
+    pub fn resume_steps(&mut self, n: u64) {
+        self.paused = n == 0;
+        self.steps_left = Some(n);
+    }
+
+    /// Execute one instruction. Return the reason for stopping
+    /// after it, if execution should stop; the debugger is then
+    /// paused.
+    pub fn step(&mut self, cpu: &mut CPU) -> Option<StopReason> {
+        let before: Vec<(Watchpoint, usize)> =
+            self.watchpoints.iter().map(|w| (*w, w.value(cpu))).collect();
+        if let Err(e) = cpu.execute_insn() {
+            self.paused = true;
+            self.steps_left = None;
+            return Some(StopReason::Error(e));
+        }
+
+        let mut reason = None;
+        if let Some(n) = self.steps_left {
+            if n <= 1 {
+                reason = Some(StopReason::Step);
+            }
+            self.steps_left = Some(n.saturating_sub(1));
+        }
+        if self.breakpoints.contains(&cpu.pc()) {
+            reason = Some(StopReason::Breakpoint(cpu.pc()));
+        }
+        for (w, old) in before {
+            let new = w.value(cpu);
+            if new != old {
+                reason = Some(StopReason::Watchpoint(w, old, new));
+                break;
+            }
+        }
+        if cpu.exited() {
+            reason = Some(StopReason::Exited);
+        }
+        if reason.is_some() {
+            self.paused = true;
+            self.steps_left = None;
+        }
+        reason
+    }
+
+    /// Registers, timers and the stack, one per line.
+    pub fn dump_registers(cpu: &CPU) -> String {
+        let mut s = String::new();
+        for (x, val) in cpu.v().iter().enumerate() {
+            s.push_str(&format!("v{:x}=0x{:02x}{}", x, val, if x % 8 == 7 { "\n" } else { " " }));
+        }
+        s.push_str(&format!("pc=0x{:03x} i=0x{:03x} sp=0x{:03x} delay={} sound={}\n",
+                            cpu.pc(), cpu.i(), cpu.sp(), cpu.delay(), cpu.sound()));
+        let stack: Vec<String> = cpu.stack().iter().map(|a| format!("0x{:03x}", a)).collect();
+        s.push_str(&format!("stack=[{}]", stack.join(" ")));
+        s
+    }
+
+    /// A hexdump of `len` bytes of memory starting at `addr`,
+    /// 16 bytes per line.
+    pub fn hexdump(cpu: &CPU, addr: usize, len: usize) -> String {
+        let mem = cpu.mem();
+        let end = addr.saturating_add(len).min(mem.len());
+        let mut lines = Vec::new();
+        let mut a = addr;
+        while a < end {
+            let row_end = (a + 16).min(end);
+            let bytes: Vec<String> = mem[a..row_end].iter().map(|b| format!("{:02x}", b)).collect();
+            lines.push(format!("{:03x}: {}", a, bytes.join(" ")));
+            a = row_end;
+        }
+        lines.join("\n")
+    }
+
+    /// Disassemble `before` instructions before `addr` and
+    /// `after` instructions after it. The instruction at PC
+    /// is marked with "=>" and breakpoints with "*".
+    pub fn disassemble_around(&self, cpu: &CPU, addr: usize, before: usize, after: usize) -> String {
+        let mem = cpu.mem();
+        let start = addr.saturating_sub(2 * before);
+        let end = (addr + 2 * (after + 1)).min(mem.len() - 1);
+        let mut lines = Vec::new();
+        let mut a = start;
+        while a < end {
+            let opcode = (u16::from(mem[a]) << 8) | u16::from(mem[a + 1]);
+            let marker = if a == cpu.pc() { "=>" } else { "  " };
+            let bp = if self.breakpoints.contains(&a) { "*" } else { " " };
+            lines.push(format!("{}{}{:03x}: {:04x}  {}", marker, bp, a, opcode,
+                               disasm::disassemble_at(mem, a)));
+            a += if opcode == 0xf000 { 4 } else { 2 };
+        }
+        lines.join("\n")
+    }
+
+    /// Start a memory search if `args` is empty, or narrow the
+    /// search going on, and list the candidates left.
+    fn search(&mut self, cpu: &CPU, args: &[&str]) -> String {
+        match (parse_predicate(args), self.search.as_mut()) {
+            _ if args.is_empty() => self.search = Some(Search::new(cpu.mem())),
+            (Some(pred @ Predicate::Equal(_)), None) => {
+                let mut search = Search::new(cpu.mem());
+                search.narrow(cpu.mem(), pred);
+                self.search = Some(search);
+            },
+            (Some(pred), Some(search)) => search.narrow(cpu.mem(), pred),
+            (Some(_), None) => return "no search going on, type 'search' to start one".to_string(),
+            (None, _) => return "usage: search [eq N | changed | unchanged | inc | dec]".to_string(),
+        }
+        let candidates = self.search.as_ref().map_or(&[][..], |s| s.candidates());
+        let mut lines = vec![format!("{} candidate{}", candidates.len(),
+                                     if candidates.len() == 1 { "" } else { "s" })];
+        if candidates.len() <= MAX_LISTED_CANDIDATES {
+            lines.extend(candidates.iter().map(|&(addr, val)| format!("{:03x}: {:02x}", addr, val)));
+        }
+        lines.join("\n")
+    }
+
+    /// Execute one debugger command and return its output.
+    pub fn command(&mut self, cpu: &mut CPU, line: &str) -> String {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            return String::new();
+        }
+        let args = &words[1..];
+        match words[0] {
+            "s" | "step" => {
+                match args.first().map(|n| n.parse::<u64>()) {
+                    None => self.resume_steps(1),
+                    Some(Ok(n)) => self.resume_steps(n),
+                    Some(Err(_)) => return "bad step count".to_string(),
+                }
+                String::new()
+            },
+            "c" | "continue" => {
+                self.resume();
+                String::new()
+            },
+            "b" | "break" => match args.first().and_then(|a| parse_addr(a)) {
+                Some(addr) => {
+                    self.add_breakpoint(addr);
+                    format!("breakpoint at 0x{:03x}", addr)
+                },
+                None => "usage: break ADDR".to_string(),
+            },
+            "d" | "delete" => match args.first().and_then(|a| parse_addr(a)) {
+                Some(addr) if self.remove_breakpoint(addr) =>
+                    format!("deleted breakpoint at 0x{:03x}", addr),
+                Some(addr) => format!("no breakpoint at 0x{:03x}", addr),
+                None => "usage: delete ADDR".to_string(),
+            },
+            "w" | "watch" => match parse_watchpoint(args) {
+                Some(Watchpoint::Mem(addr)) if addr >= cpu.mem().len() =>
+                    "usage: watch vX | i | mem ADDR".to_string(),
+                Some(w) => {
+                    self.add_watchpoint(w);
+                    format!("watching {}", w)
+                },
+                None => "usage: watch vX | i | mem ADDR".to_string(),
+            },
+            "u" | "unwatch" => match parse_watchpoint(args) {
+                Some(w) if self.remove_watchpoint(w) => format!("deleted watchpoint on {}", w),
+                Some(w) => format!("no watchpoint on {}", w),
+                None => "usage: unwatch vX | i | mem ADDR".to_string(),
+            },
+            "r" | "regs" => Debugger::dump_registers(cpu),
+            "x" => {
+                let addr = args.first().and_then(|a| parse_addr(a));
+                let len = args.get(1).map_or(Some(16), |l| l.parse::<usize>().ok());
+                match (addr, len) {
+                    (Some(addr), Some(len)) if addr < cpu.mem().len() =>
+                        Debugger::hexdump(cpu, addr, len),
+                    _ => "usage: x ADDR [LEN]".to_string(),
+                }
+            },
+            "l" | "list" => match args.first().map(|a| parse_addr(a)) {
+                None => self.disassemble_around(cpu, cpu.pc(), 4, 4),
+                Some(Some(addr)) if addr < cpu.mem().len() =>
+                    self.disassemble_around(cpu, addr, 4, 4),
+                Some(_) => "usage: list [ADDR]".to_string(),
+            },
+            "search" => self.search(cpu, args),
+            "freeze" => match *args {
+                [] if cpu.frozen().is_empty() => "no frozen addresses".to_string(),
+                [] => {
+                    let frozen: Vec<String> = cpu.frozen().iter()
+                        .map(|(addr, val)| format!("0x{:03x} = 0x{:02x}", addr, val))
+                        .collect();
+                    frozen.join("\n")
+                },
+                [addr, val] => match (parse_addr(addr), parse_byte(val)) {
+                    (Some(addr), Some(val)) if addr < cpu.mem().len() => {
+                        cpu.freeze(addr, val);
+                        format!("froze 0x{:03x} at 0x{:02x}", addr, val)
+                    },
+                    _ => "usage: freeze [ADDR N]".to_string(),
+                },
+                _ => "usage: freeze [ADDR N]".to_string(),
+            },
+            "unfreeze" => match args.first().and_then(|a| parse_addr(a)) {
+                Some(addr) if cpu.unfreeze(addr) => format!("unfroze 0x{:03x}", addr),
+                Some(addr) => format!("0x{:03x} is not frozen", addr),
+                None => "usage: unfreeze ADDR".to_string(),
+            },
+            "poke" => {
+                let addr = args.first().and_then(|a| parse_addr(a));
+                let bytes: Option<Vec<u8>> = args.iter().skip(1).map(|b| parse_byte(b)).collect();
+                match (addr, bytes) {
+                    (Some(addr), Some(ref bytes)) if !bytes.is_empty() &&
+                        addr.checked_add(bytes.len()).is_some_and(|end| end <= cpu.mem().len()) => {
+                        cpu.poke(addr, bytes);
+                        Debugger::hexdump(cpu, addr, bytes.len())
+                    },
+                    _ => "usage: poke ADDR N...".to_string(),
+                }
+            },
+            "q" | "quit" => {
+                self.quit = true;
+                self.paused = false;
+                String::new()
+            },
+            "h" | "help" => HELP.to_string(),
+            _ => format!("unknown command '{}', type 'help' for help", words[0]),
+        }
+    }
+
+    /// Read and execute commands while the debugger is paused.
+    /// End of input is treated as "quit".
+    pub fn repl<R: BufRead, W: Write>(&mut self, cpu: &mut CPU, input: &mut R,
+                                      output: &mut W) -> io::Result<()> {
+        writeln!(output, "{}", self.disassemble_around(cpu, cpu.pc(), 0, 0))?;
+        while self.paused {
+            write!(output, "(chip8) ")?;
+            output.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                self.quit = true;
+                self.paused = false;
+                break;
+            }
+            let out = self.command(cpu, &line);
+            if !out.is_empty() {
+                writeln!(output, "{}", out)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[path="./debugger_test.rs"]
+mod debugger_test;
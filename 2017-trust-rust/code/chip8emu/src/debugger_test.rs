@@ -0,0 +1,275 @@
+// debugger_test.rs
+
+// Tests run the first instructions of PONG, which are:
+//
+// 200: 6a02  va := 0x02
+// 202: 6b0c  vb := 0x0c
+// 204: 6c3f  vc := 0x3f
+// 206: 6d0c  vd := 0x0c
+// 208: a2ea  i := 0x2ea
+// 20a: dab6  sprite va vb 6
+// 20c: dcd6  sprite vc vd 6
+// 20e: 6e00  ve := 0x00
+// 210: 22d4  :call 0x2d4
+
+use super::*;
+use cpu::PC_START;
+
+fn pong() -> CPU {
+    let mut c = CPU::headless();
+    c.load_rom(&include_bytes!("../roms/PONG")[..], PC_START).unwrap();
+    c
+}
+
+/// Step until the debugger stops, at most `limit` times.
+fn run(d: &mut Debugger, c: &mut CPU, limit: usize) -> Option<StopReason> {
+    for _ in 0..limit {
+        if let Some(reason) = d.step(c) {
+            return Some(reason);
+        }
+    }
+    None
+}
+
+#[test]
+fn test_starts_paused() {
+    let d = Debugger::new();
+    assert!(d.paused());
+    assert!(!d.quit_requested());
+}
+
+#[test]
+fn test_single_step() {
+    let mut c = pong();
+    let mut d = Debugger::new();
+    assert_eq!(d.command(&mut c, "step"), "");
+    assert!(!d.paused());
+    assert_eq!(d.step(&mut c), Some(StopReason::Step));
+    assert!(d.paused());
+    assert_eq!(c.pc(), 0x202);
+    assert_eq!(c.v()[0xa], 2);
+}
+
+#[test]
+fn test_step_n() {
+    let mut c = pong();
+    let mut d = Debugger::new();
+    d.command(&mut c, "s 3");
+    assert_eq!(d.step(&mut c), None);
+    assert_eq!(d.step(&mut c), None);
+    assert_eq!(d.step(&mut c), Some(StopReason::Step));
+    assert_eq!(c.pc(), 0x206);
+    assert!(d.paused());
+}
+
+#[test]
+fn test_breakpoint() {
+    let mut c = pong();
+    let mut d = Debugger::new();
+    assert_eq!(d.command(&mut c, "break 0x20e"), "breakpoint at 0x20e");
+    d.command(&mut c, "continue");
+    assert_eq!(run(&mut d, &mut c, 100), Some(StopReason::Breakpoint(0x20e)));
+    assert_eq!(c.pc(), 0x20e);
+    assert!(d.paused());
+
+    assert_eq!(d.command(&mut c, "delete 20e"), "deleted breakpoint at 0x20e");
+    assert_eq!(d.command(&mut c, "delete 20e"), "no breakpoint at 0x20e");
+}
+
+#[test]
+fn test_watch_register() {
+    let mut c = pong();
+    let mut d = Debugger::new();
+    assert_eq!(d.command(&mut c, "watch vc"), "watching vc");
+    d.command(&mut c, "c");
+    assert_eq!(run(&mut d, &mut c, 100),
+               Some(StopReason::Watchpoint(Watchpoint::V(0xc), 0, 0x3f)));
+    assert_eq!(c.pc(), 0x206);
+}
+
+#[test]
+fn test_watch_i() {
+    let mut c = pong();
+    let mut d = Debugger::new();
+    d.command(&mut c, "w i");
+    d.command(&mut c, "c");
+    assert_eq!(run(&mut d, &mut c, 100),
+               Some(StopReason::Watchpoint(Watchpoint::I, 0, 0x2ea)));
+}
+
+#[test]
+fn test_watch_memory() {
+    let mut c = pong();
+    let mut d = Debugger::new();
+    // The call at 0x210 pushes the return address 0x212
+    // on the stack, which starts at 0xea0.
+    d.command(&mut c, "watch mem ea1");
+    d.command(&mut c, "c");
+    assert_eq!(run(&mut d, &mut c, 100),
+               Some(StopReason::Watchpoint(Watchpoint::Mem(0xea1), 0, 0x12)));
+    assert_eq!(c.pc(), 0x2d4);
+    assert_eq!(c.stack(), vec![0x212]);
+
+    assert_eq!(d.command(&mut c, "unwatch mem ea1"), "deleted watchpoint on mem 0xea1");
+    assert_eq!(d.command(&mut c, "unwatch mem ea1"), "no watchpoint on mem 0xea1");
+}
+
+#[test]
+fn test_watch_memory_shrinks() {
+    let mut c = pong();
+    c.set_quirks("xochip".parse().unwrap());
+    let mut d = Debugger::new();
+    assert_eq!(d.command(&mut c, "watch mem 5000"), "watching mem 0x5000");
+    // Back to 4 KiB: the location watched is gone.
+    c.set_quirks(Default::default());
+    d.command(&mut c, "s 2");
+    assert_eq!(run(&mut d, &mut c, 10), Some(StopReason::Step));
+}
+
+#[test]
+fn test_regs() {
+    let mut c = pong();
+    let mut d = Debugger::new();
+    d.command(&mut c, "s 2");
+    run(&mut d, &mut c, 2);
+    let out = d.command(&mut c, "regs");
+    assert!(out.contains("va=0x02 vb=0x0c"));
+    assert!(out.contains("pc=0x204 i=0x000"));
+    assert!(out.contains("stack=[]"));
+}
+
+#[test]
+fn test_hexdump() {
+    let mut c = pong();
+    let mut d = Debugger::new();
+    assert_eq!(d.command(&mut c, "x 200 4"), "200: 6a 02 6b 0c");
+    let out = d.command(&mut c, "x 0x200 20");
+    assert_eq!(out.lines().count(), 2);
+    assert!(out.lines().nth(1).unwrap().starts_with("210: 22 d4"));
+    assert_eq!(d.command(&mut c, "x"), "usage: x ADDR [LEN]");
+    assert_eq!(d.command(&mut c, "x 200 0x10"), "usage: x ADDR [LEN]");
+    assert_eq!(d.command(&mut c, "x ffe 18446744073709551615"), "ffe: 00 00");
+}
+
+#[test]
+fn test_list() {
+    let mut c = pong();
+    let mut d = Debugger::new();
+    d.command(&mut c, "b 204");
+    let out = d.command(&mut c, "list");
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 9);
+    assert_eq!(lines[4], "=> 200: 6a02  va := 0x02");
+    assert_eq!(lines[6], "  *204: 6c3f  vc := 0x3f");
+
+    let out = d.command(&mut c, "l 208");
+    assert_eq!(out.lines().count(), 9);
+    assert!(out.contains("   208: a2ea  i := 0x2ea"));
+}
+
+#[test]
+fn test_search() {
+    let mut c = pong();
+    let mut d = Debugger::new();
+    assert_eq!(d.command(&mut c, "search dec"), "no search going on, type 'search' to start one");
+    assert_eq!(d.command(&mut c, "search"), "4096 candidates");
+    assert_eq!(d.command(&mut c, "poke 300 5 0x07"), "300: 05 07");
+    assert_eq!(d.command(&mut c, "search changed"), "2 candidates\n300: 05\n301: 07");
+    c.poke(0x300, &[0x2b]);
+    assert_eq!(d.command(&mut c, "search inc"), "1 candidate\n300: 2b");
+    assert_eq!(d.command(&mut c, "search eq 5"), "0 candidates");
+
+    // "eq" starts a search if there is none.
+    let mut d = Debugger::new();
+    assert_eq!(d.command(&mut c, "search eq 2b"), "1 candidate\n300: 2b");
+}
+
+#[test]
+fn test_freeze() {
+    let mut c = pong();
+    let mut d = Debugger::new();
+    assert_eq!(d.command(&mut c, "freeze"), "no frozen addresses");
+    assert_eq!(d.command(&mut c, "freeze 300 9"), "froze 0x300 at 0x09");
+    assert_eq!(d.command(&mut c, "freeze"), "0x300 = 0x09");
+    assert_eq!(c.mem()[0x300], 9);
+    c.poke(0x300, &[1]);
+    c.vblank();
+    assert_eq!(c.mem()[0x300], 9);
+    assert_eq!(d.command(&mut c, "unfreeze 0x300"), "unfroze 0x300");
+    assert_eq!(d.command(&mut c, "unfreeze 0x300"), "0x300 is not frozen");
+}
+
+#[test]
+fn test_exit_stops() {
+    let mut c = CPU::headless();
+    // "exit" is the first instruction.
+    c.load_state(&{
+        let mut st = c.save_state();
+        st.mem[PC_START] = 0x00;
+        st.mem[PC_START + 1] = 0xfd;
+        st
+    }).unwrap();
+    let mut d = Debugger::new();
+    d.command(&mut c, "c");
+    assert_eq!(d.step(&mut c), Some(StopReason::Exited));
+}
+
+#[test]
+fn test_invalid_insn_stops() {
+    let mut c = CPU::headless();
+    let mut d = Debugger::new();
+    d.command(&mut c, "c");
+    // Memory at PC is all zeros, which is not an instruction.
+    let reason = d.step(&mut c);
+    assert_eq!(reason, Some(StopReason::Error(Chip8Error::InvalidOpcode { pc: PC_START, opcode: 0 })));
+    assert_eq!(reason.unwrap().to_string(), "error: invalid opcode 0x0000 at 0x200");
+    assert!(d.paused());
+}
+
+#[test]
+fn test_bad_commands() {
+    let mut c = pong();
+    let mut d = Debugger::new();
+    assert_eq!(d.command(&mut c, ""), "");
+    assert_eq!(d.command(&mut c, "break"), "usage: break ADDR");
+    assert_eq!(d.command(&mut c, "break xyz"), "usage: break ADDR");
+    assert_eq!(d.command(&mut c, "watch vz"), "usage: watch vX | i | mem ADDR");
+    assert_eq!(d.command(&mut c, "watch mem 1000"), "usage: watch vX | i | mem ADDR");
+    assert_eq!(d.command(&mut c, "step two"), "bad step count");
+    assert_eq!(d.command(&mut c, "search up"), "usage: search [eq N | changed | unchanged | inc | dec]");
+    assert_eq!(d.command(&mut c, "freeze 300"), "usage: freeze [ADDR N]");
+    assert_eq!(d.command(&mut c, "freeze 300 100"), "usage: freeze [ADDR N]");
+    assert_eq!(d.command(&mut c, "unfreeze"), "usage: unfreeze ADDR");
+    assert_eq!(d.command(&mut c, "poke 300"), "usage: poke ADDR N...");
+    assert_eq!(d.command(&mut c, "poke fff 1 2"), "usage: poke ADDR N...");
+    assert_eq!(d.command(&mut c, "poke ffffffffffffffff 1"), "usage: poke ADDR N...");
+    assert!(d.command(&mut c, "frobnicate").starts_with("unknown command"));
+    assert!(d.paused());
+}
+
+#[test]
+fn test_repl() {
+    let mut c = pong();
+    let mut d = Debugger::new();
+    let mut input = "b 20a\nbogus\ncontinue\nregs\n".as_bytes();
+    let mut output = Vec::new();
+    d.repl(&mut c, &mut input, &mut output).unwrap();
+    let out = String::from_utf8(output).unwrap();
+    assert!(out.starts_with("=> 200: 6a02  va := 0x02\n(chip8) "));
+    assert!(out.contains("breakpoint at 0x20a"));
+    assert!(out.contains("unknown command 'bogus'"));
+    // "continue" ends the REPL; "regs" is left unread.
+    assert!(!out.contains("pc="));
+    assert!(!d.paused());
+    assert!(!d.quit_requested());
+}
+
+#[test]
+fn test_repl_end_of_input_quits() {
+    let mut c = pong();
+    let mut d = Debugger::new();
+    let mut input = "".as_bytes();
+    let mut output = Vec::new();
+    d.repl(&mut c, &mut input, &mut output).unwrap();
+    assert!(d.quit_requested());
+}
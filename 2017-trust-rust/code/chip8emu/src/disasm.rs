@@ -0,0 +1,182 @@
+// disasm.rs
+
+// References:
+// (1) <http://johnearnest.github.io/Octo/docs/Manual.html>
+// (2) <http://devernay.free.fr/hacks/chip8/C8TECH10.HTM>
+
+use std::collections::BTreeSet;
+
+use insn::{self, Instruction};
+
+/// Return the Octo mnemonic of the instruction "opcode".
+///
+/// Octo has no skip instructions; instead, "if ... then"
+/// executes the next instruction only if the condition holds.
+/// So "3xnn" (skip if v[x] == nn) becomes "if vx != nn then".
+///
+/// Opcodes which are not valid instructions are shown as
+/// two data bytes. The address of "f000 nnnn" is in the next
+/// 2 bytes, so it is shown as "i := long" alone; see
+/// `disassemble_at`.
+pub fn disassemble_insn(opcode: u16) -> String {
+    disassemble_with_labels(opcode, &BTreeSet::new())
+}
+
+/// The 2 bytes at `addr` in `mem`, wrapping around.
+fn opcode_at(mem: &[u8], addr: usize) -> u16 {
+    (u16::from(mem[addr % mem.len()]) << 8) | u16::from(mem[(addr + 1) % mem.len()])
+}
+
+/// Return the Octo mnemonic of the instruction at `addr` in
+/// `mem`, with the address of "i := long" read from memory.
+pub fn disassemble_at(mem: &[u8], addr: usize) -> String {
+    match opcode_at(mem, addr) {
+        0xf000 => long_i(usize::from(opcode_at(mem, addr + 2))),
+        opcode => disassemble_insn(opcode),
+    }
+}
+
+/// The mnemonic of "f000 nnnn".
+fn long_i(nnnn: usize) -> String {
+    format!("i := long 0x{:04x}", nnnn)
+}
+
+/// Name of the label at address "addr".
+fn label_name(addr: usize) -> String {
+    format!("L{:03x}", addr)
+}
+
+/// An address operand: the label at that address if there is
+/// one, otherwise the address itself.
+fn addr_operand(addr: usize, labels: &BTreeSet<usize>) -> String {
+    if labels.contains(&addr) {
+        label_name(addr)
+    } else {
+        format!("0x{:03x}", addr)
+    }
+}
+
+fn disassemble_with_labels(opcode: u16, labels: &BTreeSet<usize>) -> String {
+    use insn::Instruction::*;
+
+    let insn = match insn::decode(opcode) {
+        Ok(insn) => insn,
+        Err(_) => return data(opcode),
+    };
+    match insn {
+        ScrollDown(n) => format!("scroll-down {}", n),
+        ScrollUp(n) => format!("scroll-up {}", n),
+        Clear => "clear".to_string(),
+        Return => "return".to_string(),
+        ScrollRight => "scroll-right".to_string(),
+        ScrollLeft => "scroll-left".to_string(),
+        Exit => "exit".to_string(),
+        Lores => "lores".to_string(),
+        Hires => "hires".to_string(),
+        Jump(nnn) => format!("jump {}", addr_operand(nnn, labels)),
+        Call(nnn) => format!(":call {}", addr_operand(nnn, labels)),
+        SkipIfEqConst(x, nn) => format!("if v{:x} != 0x{:02x} then", x, nn),
+        SkipIfNeConst(x, nn) => format!("if v{:x} == 0x{:02x} then", x, nn),
+        SkipIfEq(x, y) => format!("if v{:x} != v{:x} then", x, y),
+        StoreRange(x, y) => format!("save v{:x} - v{:x}", x, y),
+        LoadRange(x, y) => format!("load v{:x} - v{:x}", x, y),
+        SetConst(x, nn) => format!("v{:x} := 0x{:02x}", x, nn),
+        AddConst(x, nn) => format!("v{:x} += 0x{:02x}", x, nn),
+        Assign(x, y) => format!("v{:x} := v{:x}", x, y),
+        Or(x, y) => format!("v{:x} |= v{:x}", x, y),
+        And(x, y) => format!("v{:x} &= v{:x}", x, y),
+        Xor(x, y) => format!("v{:x} ^= v{:x}", x, y),
+        Add(x, y) => format!("v{:x} += v{:x}", x, y),
+        Sub(x, y) => format!("v{:x} -= v{:x}", x, y),
+        ShiftRight(x, y) => format!("v{:x} >>= v{:x}", x, y),
+        SubReverse(x, y) => format!("v{:x} =- v{:x}", x, y),
+        ShiftLeft(x, y) => format!("v{:x} <<= v{:x}", x, y),
+        SkipIfNe(x, y) => format!("if v{:x} == v{:x} then", x, y),
+        SetI(nnn) => format!("i := 0x{:03x}", nnn),
+        JumpOffset(nnn) => format!("jump0 {}", addr_operand(nnn, labels)),
+        Random(x, nn) => format!("v{:x} := random 0x{:02x}", x, nn),
+        Draw(x, y, n) => format!("sprite v{:x} v{:x} {}", x, y, n),
+        SkipIfKey(x) => format!("if v{:x} -key then", x),
+        SkipIfNotKey(x) => format!("if v{:x} key then", x),
+        LongI => "i := long".to_string(),
+        Planes(n) => format!("plane {}", n),
+        Audio => "audio".to_string(),
+        GetDelay(x) => format!("v{:x} := delay", x),
+        WaitKey(x) => format!("v{:x} := key", x),
+        SetDelay(x) => format!("delay := v{:x}", x),
+        SetSound(x) => format!("buzzer := v{:x}", x),
+        AddI(x) => format!("i += v{:x}", x),
+        Font(x) => format!("i := hex v{:x}", x),
+        BigFont(x) => format!("i := bighex v{:x}", x),
+        Bcd(x) => format!("bcd v{:x}", x),
+        Pitch(x) => format!("pitch := v{:x}", x),
+        Store(x) => format!("save v{:x}", x),
+        Load(x) => format!("load v{:x}", x),
+        StoreFlags(x) => format!("saveflags v{:x}", x),
+        LoadFlags(x) => format!("loadflags v{:x}", x),
+    }
+}
+
+/// Show an opcode as two data bytes.
+fn data(opcode: u16) -> String {
+    format!("0x{:02x} 0x{:02x}", opcode >> 8, opcode & 0xff)
+}
+
+/// Addresses of the jump and call targets in "rom", loaded at
+/// "origin", which can be given a label. Only targets which
+/// start a line of the disassembly qualify; other targets are
+/// left as numbers.
+fn find_labels(rom: &[u8], origin: usize) -> BTreeSet<usize> {
+    rom.chunks(2)
+        .filter(|c| c.len() == 2)
+        .filter_map(|c| {
+            match insn::decode((u16::from(c[0]) << 8) | u16::from(c[1])) {
+                Ok(Instruction::Jump(addr)) |
+                Ok(Instruction::Call(addr)) |
+                Ok(Instruction::JumpOffset(addr)) => Some(addr),
+                _ => None,
+            }
+        })
+        .filter(|&addr| addr >= origin && (addr - origin) & 1 == 0 && addr - origin < rom.len())
+        .collect()
+}
+
+/// Disassemble a whole ROM loaded at address "origin".
+///
+/// Every two bytes become one line, so data mixed with code is
+/// shown as instructions or data bytes too; a trailing odd byte
+/// is a line of its own. "f000 nnnn" takes four bytes, unless
+/// its address is missing or labelled, in which case "f000" is
+/// shown as data. Jump and call targets get a label, "Lnnn"
+/// where "nnn" is the address. `asm::assemble` turns the
+/// result back into exactly the same bytes.
+pub fn disassemble(rom: &[u8], origin: usize) -> String {
+    let labels = find_labels(rom, origin);
+    let mut out = String::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let addr = origin + offset;
+        if labels.contains(&addr) {
+            out.push_str(&format!(": {}\n", label_name(addr)));
+        }
+        let line = if offset + 2 > rom.len() {
+            format!("0x{:02x}", rom[offset])
+        } else {
+            match opcode_at(rom, offset) {
+                0xf000 if offset + 4 <= rom.len() && !labels.contains(&(addr + 2)) => {
+                    offset += 2;
+                    long_i(usize::from(opcode_at(rom, offset)))
+                },
+                0xf000 => data(0xf000),
+                opcode => disassemble_with_labels(opcode, &labels),
+            }
+        };
+        out.push_str(&format!("    {}\n", line));
+        offset += 2;
+    }
+    out
+}
+
+#[cfg(test)]
+#[path="./disasm_test.rs"]
+mod disasm_test;
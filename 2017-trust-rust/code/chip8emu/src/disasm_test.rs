@@ -0,0 +1,69 @@
+use super::*;
+
+#[test]
+fn test_disassemble_insn() {
+    assert_eq!(disassemble_insn(0x00e0), "clear");
+    assert_eq!(disassemble_insn(0x00c3), "scroll-down 3");
+    assert_eq!(disassemble_insn(0x00d4), "scroll-up 4");
+    assert_eq!(disassemble_insn(0x1234), "jump 0x234");
+    assert_eq!(disassemble_insn(0x3a12), "if va != 0x12 then");
+    assert_eq!(disassemble_insn(0x5ab0), "if va != vb then");
+    assert_eq!(disassemble_insn(0x8ab7), "va =- vb");
+    assert_eq!(disassemble_insn(0xc10f), "v1 := random 0x0f");
+    assert_eq!(disassemble_insn(0xd125), "sprite v1 v2 5");
+    assert_eq!(disassemble_insn(0xe39e), "if v3 -key then");
+    assert_eq!(disassemble_insn(0xf430), "i := bighex v4");
+    assert_eq!(disassemble_insn(0xf002), "audio");
+    assert_eq!(disassemble_insn(0xf53a), "pitch := v5");
+    assert_eq!(disassemble_insn(0x5122), "save v1 - v2");
+    assert_eq!(disassemble_insn(0x5a33), "load va - v3");
+    assert_eq!(disassemble_insn(0xf201), "plane 2");
+    assert_eq!(disassemble_insn(0xf000), "i := long");
+    assert_eq!(disassemble_at(&[0xf0, 0x00, 0x12, 0x34], 0), "i := long 0x1234");
+    assert_eq!(disassemble_at(&[0x00, 0xe0], 0), "clear");
+}
+
+#[test]
+fn test_disassemble_invalid() {
+    assert_eq!(disassemble_insn(0x0123), "0x01 0x23");
+    assert_eq!(disassemble_insn(0x5121), "0x51 0x21");
+    assert_eq!(disassemble_insn(0x812f), "0x81 0x2f");
+    assert_eq!(disassemble_insn(0xe1ff), "0xe1 0xff");
+}
+
+#[test]
+fn test_disassemble_labels() {
+    let rom = [0x22, 0x06, // :call 0x206
+               0x12, 0x02, // jump 0x202
+               0x13, 0x00, // jump 0x300, outside the ROM
+               0x12, 0x09, // jump 0x209, not the start of a line
+               0x00, 0xee];
+    assert_eq!(disassemble(&rom, 0x200), concat!(
+        "    :call L206\n",
+        ": L202\n",
+        "    jump L202\n",
+        "    jump 0x300\n",
+        ": L206\n",
+        "    jump 0x209\n",
+        "    return\n"));
+}
+
+#[test]
+fn test_disassemble_odd_length() {
+    assert_eq!(disassemble(&[0x00, 0xe0, 0xab], 0x200), "    clear\n    0xab\n");
+}
+
+#[test]
+fn test_disassemble_long() {
+    let rom = [0xf0, 0x00, 0xab, 0xcd, // i := long 0xabcd
+               0x12, 0x08,             // jump 0x208, into the next one
+               0xf0, 0x00, 0x00, 0xe0,
+               0xf0, 0x00];            // no address
+    assert_eq!(disassemble(&rom, 0x200), concat!(
+        "    i := long 0xabcd\n",
+        "    jump L208\n",
+        "    0xf0 0x00\n",
+        ": L208\n",
+        "    clear\n",
+        "    0xf0 0x00\n"));
+}
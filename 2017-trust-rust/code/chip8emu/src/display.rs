@@ -0,0 +1,124 @@
+// display.rs
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Default screen height in pixels
+pub const SCREEN_HEIGHT:u16 = 32;
+
+/// Default screen width in pixels
+pub const SCREEN_WIDTH:u16 = 64;
+
+/// Screen height in pixels in the SUPER-CHIP high
+/// resolution mode
+pub const HIRES_SCREEN_HEIGHT:u16 = 64;
+
+/// Screen width in pixels in the SUPER-CHIP high
+/// resolution mode
+pub const HIRES_SCREEN_WIDTH:u16 = 128;
+
+/// The display attached to the CPU.
+///
+/// Pixels are either ON (1) or OFF (0). With the XO-CHIP
+/// bit planes a pixel's value has one bit per plane, so it
+/// is 0 to 3. The CPU does the Xor logic of sprite drawing
+/// itself, so a display only has to remember and show the
+/// value of each pixel.
+///
+/// A display starts out with a resolution of SCREEN_WIDTH
+/// x SCREEN_HEIGHT pixels.
+pub trait Display {
+    /// Turn every pixel OFF.
+    fn clear(&mut self);
+
+    /// Switch to a resolution of `width` x `height` pixels.
+    /// Every pixel is turned OFF.
+    fn set_resolution(&mut self, width: u32, height: u32);
+
+    /// Set the pixel at (x, y) to `val`.
+    fn draw_pixel(&mut self, x: u32, y: u32, val: u8);
+
+    /// Return the value of the pixel at (x, y).
+    fn get_pixel(&self, x: u32, y: u32) -> u8;
+
+    /// Make the pixels drawn so far visible. Called once
+    /// per "dxyn" instruction.
+    fn present(&mut self) {}
+
+    /// Called at the start of every 60 Hz frame, for
+    /// displays whose picture changes over time.
+    fn vblank(&mut self) {}
+}
+
+/// Number of keys on the hex keypad.
+pub const NUM_KEYS: usize = 16;
+
+/// The hex keypad attached to the CPU.
+///
+/// CHIP-8 uses keys from 0,1,...9 and a, b, ... f.
+/// These are assigned codes from 0, 1, ... 0xf.
+pub trait Keypad {
+    /// Return true if the key with CHIP-8 value `key` is
+    /// held down.
+    fn is_pressed(&self, key: u8) -> bool;
+}
+
+/// A shared keypad, so that the main loop can still get at
+/// a keypad after handing it over to the CPU.
+impl<K: Keypad + ?Sized> Keypad for Rc<RefCell<K>> {
+    fn is_pressed(&self, key: u8) -> bool {
+        self.borrow().is_pressed(key)
+    }
+}
+
+/// Which keys of the keypad are held down. Frontends update
+/// it from their key press and release events.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct KeyState {
+    pressed: [bool; NUM_KEYS],
+}
+
+impl KeyState {
+    /// A keypad on which no key is pressed.
+    pub fn new() -> KeyState {
+        KeyState { pressed: [false; NUM_KEYS] }
+    }
+
+    pub fn press(&mut self, key: u8) {
+        self.pressed[usize::from(key) % NUM_KEYS] = true;
+    }
+
+    pub fn release(&mut self, key: u8) {
+        self.pressed[usize::from(key) % NUM_KEYS] = false;
+    }
+
+    /// Release every key, for example when the window loses
+    /// the keyboard focus and the release events would be
+    /// missed.
+    pub fn release_all(&mut self) {
+        self.pressed = [false; NUM_KEYS];
+    }
+
+    /// The keys held down as a bitmask: bit n is set if key
+    /// n is pressed.
+    pub fn bits(&self) -> u16 {
+        self.pressed.iter().enumerate()
+            .filter(|&(_, &p)| p)
+            .fold(0, |bits, (key, _)| bits | 1 << key)
+    }
+
+    /// The keypad described by a bitmask from `bits`.
+    pub fn from_bits(bits: u16) -> KeyState {
+        let mut keys = KeyState::new();
+        for key in 0..NUM_KEYS {
+            keys.pressed[key] = bits & (1 << key) != 0;
+        }
+        keys
+    }
+}
+
+impl Keypad for KeyState {
+    fn is_pressed(&self, key: u8) -> bool {
+        self.pressed[usize::from(key) % NUM_KEYS]
+    }
+}
@@ -0,0 +1,101 @@
+// error.rs
+
+// Faults of the CHIP-8 machine, and what to do about them.
+// A ROM can make the CPU read or write beyond the end of
+// memory, or overflow the stack; such faults are reported
+// as a Chip8Error rather than crashing the emulator.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chip8Error {
+    /// The opcode at address `pc` is not a valid instruction.
+    InvalidOpcode { pc: usize, opcode: u16 },
+    /// The instruction at address `pc` accessed address
+    /// `addr`, which is beyond the end of memory.
+    AddressOutOfRange { pc: usize, addr: usize },
+    /// The instruction at address `pc` called a subroutine
+    /// with the stack full.
+    StackOverflow { pc: usize },
+    /// The instruction at address `pc` returned from a
+    /// subroutine with the stack empty.
+    StackUnderflow { pc: usize },
+    /// A ROM of `size` bytes does not fit in memory when
+    /// loaded at address `offset`.
+    RomTooLarge { size: usize, offset: usize },
+    /// A ROM could not be read.
+    Io(io::ErrorKind),
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Chip8Error::InvalidOpcode { pc, opcode } =>
+                write!(f, "invalid opcode 0x{:04x} at 0x{:03x}", opcode, pc),
+            Chip8Error::AddressOutOfRange { pc, addr } =>
+                write!(f, "address 0x{:x} out of range at 0x{:03x}", addr, pc),
+            Chip8Error::StackOverflow { pc } => write!(f, "stack overflow at 0x{:03x}", pc),
+            Chip8Error::StackUnderflow { pc } => write!(f, "stack underflow at 0x{:03x}", pc),
+            Chip8Error::RomTooLarge { size, offset } =>
+                write!(f, "ROM of {} bytes does not fit in memory at 0x{:03x}", size, offset),
+            Chip8Error::Io(kind) => write!(f, "{}", io::Error::from(kind)),
+        }
+    }
+}
+
+impl Error for Chip8Error {
+    fn description(&self) -> &str {
+        "CHIP-8 fault"
+    }
+}
+
+impl From<io::Error> for Chip8Error {
+    fn from(e: io::Error) -> Chip8Error {
+        Chip8Error::Io(e.kind())
+    }
+}
+
+/// What the CPU does about a fault.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FaultPolicy {
+    #[default]
+    /// Return the fault as an error. The faulting instruction
+    /// has no effect and the PC is left on it, so the program
+    /// stops there.
+    Halt,
+    /// Wrap around: addresses beyond the end of memory wrap
+    /// to 0, a call with the stack full overwrites its oldest
+    /// entry and a return with the stack empty uses its top
+    /// entry. A ROM too large to fit wraps around to address
+    /// 0. Invalid opcodes are skipped, as with Ignore.
+    Wrap,
+    /// Skip the faulting instruction and carry on with the
+    /// next one. A ROM too large to fit is truncated.
+    Ignore,
+}
+
+/// Error returned when a fault policy name is not known.
+#[derive(Debug)]
+pub struct UnknownPolicy(String);
+
+impl fmt::Display for UnknownPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown fault policy '{}', expected one of: halt, wrap, ignore", self.0)
+    }
+}
+
+impl FromStr for FaultPolicy {
+    type Err = UnknownPolicy;
+
+    fn from_str(s: &str) -> Result<FaultPolicy, UnknownPolicy> {
+        match s {
+            "halt" => Ok(FaultPolicy::Halt),
+            "wrap" => Ok(FaultPolicy::Wrap),
+            "ignore" => Ok(FaultPolicy::Ignore),
+            _ => Err(UnknownPolicy(s.to_string())),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+// frontend.rs
+
+// What the emulator shows the game in, and the controls all
+// frontends have in common.
+
+use std::fmt;
+use std::str::FromStr;
+
+use display::KeyState;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Frontend {
+    /// A window, with sound.
+    #[default]
+    Sdl,
+    /// The terminal, for example over SSH.
+    Tui,
+}
+
+/// Error returned when a frontend name is not known.
+#[derive(Debug)]
+pub struct UnknownFrontend(String);
+
+impl fmt::Display for UnknownFrontend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown frontend '{}', expected one of: sdl, tui", self.0)
+    }
+}
+
+impl FromStr for Frontend {
+    type Err = UnknownFrontend;
+
+    fn from_str(s: &str) -> Result<Frontend, UnknownFrontend> {
+        match s {
+            "sdl" => Ok(Frontend::Sdl),
+            "tui" => Ok(Frontend::Tui),
+            _ => Err(UnknownFrontend(s.to_string())),
+        }
+    }
+}
+
+/// Keys used to control the emulator itself, rather
+/// than the game.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Hotkey {
+    /// F5: save the state of the machine to a file.
+    SaveState,
+    /// F9: load the state saved with F5.
+    LoadState,
+    /// Backspace: go back in time a little.
+    Rewind,
+    /// F12: save a screenshot.
+    Screenshot,
+    /// Closing the window, or Esc or Ctrl-C in the terminal:
+    /// quit the emulator.
+    Quit,
+}
+
+/// The keys of a frontend, which drive the CHIP-8 keypad and
+/// the hotkeys.
+pub trait Controls {
+    /// Process the pending input, which updates the pressed
+    /// keys, and return the first hotkey pressed, if any.
+    /// Called once per frame.
+    fn poll_hotkey(&mut self) -> Option<Hotkey>;
+
+    /// The CHIP-8 keys held down.
+    fn keys(&self) -> KeyState;
+}
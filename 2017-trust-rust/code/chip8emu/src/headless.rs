@@ -0,0 +1,54 @@
+// headless.rs
+
+// A display which does not need a window. Used by the unit
+// tests and for running ROMs in CI.
+
+use display::{Display, SCREEN_WIDTH, SCREEN_HEIGHT};
+
+/// An in-memory framebuffer. mem[i] is the value of the
+/// corresponding pixel: 0 if it is OFF, otherwise its bit
+/// planes.
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    mem: Vec<u8>,
+}
+
+impl Framebuffer {
+    pub fn new() -> Framebuffer {
+        let (width, height) = (u32::from(SCREEN_WIDTH), u32::from(SCREEN_HEIGHT));
+        Framebuffer {
+            width,
+            height,
+            mem: vec![0; (width * height) as usize],
+        }
+    }
+}
+
+impl Default for Framebuffer {
+    fn default() -> Framebuffer {
+        Framebuffer::new()
+    }
+}
+
+impl Display for Framebuffer {
+    fn clear(&mut self) {
+        for p in self.mem.iter_mut() {
+            *p = 0;
+        }
+    }
+
+    fn set_resolution(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.mem = vec![0; (width * height) as usize];
+    }
+
+    fn draw_pixel(&mut self, x: u32, y: u32, val: u8) {
+        self.mem[(y * self.width + x) as usize] = val;
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> u8 {
+        self.mem[(y * self.width + x) as usize]
+    }
+}
@@ -0,0 +1,308 @@
+// insn.rs
+
+// Decoding of CHIP-8, SUPER-CHIP and XO-CHIP opcodes.
+//
+// References:
+// (1) <http://devernay.free.fr/hacks/chip8/C8TECH10.HTM>
+// (2) <https://github.com/Chromatophore/HP48-Superchip>
+// (3) <http://johnearnest.github.io/Octo/docs/XO-ChipSpecification.html>
+
+use std::error::Error;
+use std::fmt;
+
+/// A decoded instruction. Register operands are register
+/// numbers (0 to 0xf), address operands are 12 bit addresses.
+/// Instructions are 2 bytes long, except "f000 nnnn", whose
+/// 16 bit address is in the 2 bytes which follow it.
+///
+/// The opcode of each instruction is given in its doc
+/// comment; "x" and "y" are registers, "n", "nn" and "nnn"
+/// are 4, 8 and 12 bit constants.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// "00cn": scroll the screen down n pixels (SUPER-CHIP).
+    ScrollDown(u8),
+    /// "00dn": scroll the screen up n pixels (XO-CHIP).
+    ScrollUp(u8),
+    /// "00e0": clear the screen.
+    Clear,
+    /// "00ee": return from a subroutine.
+    Return,
+    /// "00fb": scroll the screen right 4 pixels (SUPER-CHIP).
+    ScrollRight,
+    /// "00fc": scroll the screen left 4 pixels (SUPER-CHIP).
+    ScrollLeft,
+    /// "00fd": exit the interpreter (SUPER-CHIP).
+    Exit,
+    /// "00fe": switch to 64x32 resolution (SUPER-CHIP).
+    Lores,
+    /// "00ff": switch to 128x64 resolution (SUPER-CHIP).
+    Hires,
+    /// "1nnn": jump to nnn.
+    Jump(usize),
+    /// "2nnn": call the subroutine at nnn.
+    Call(usize),
+    /// "3xnn": skip the next instruction if v[x] == nn.
+    SkipIfEqConst(usize, u8),
+    /// "4xnn": skip the next instruction if v[x] != nn.
+    SkipIfNeConst(usize, u8),
+    /// "5xy0": skip the next instruction if v[x] == v[y].
+    SkipIfEq(usize, usize),
+    /// "5xy2": store v[x] to v[y] at i, in that order even if
+    /// x > y (XO-CHIP).
+    StoreRange(usize, usize),
+    /// "5xy3": load v[x] to v[y] from i (XO-CHIP).
+    LoadRange(usize, usize),
+    /// "6xnn": v[x] = nn.
+    SetConst(usize, u8),
+    /// "7xnn": v[x] += nn, without carry.
+    AddConst(usize, u8),
+    /// "8xy0": v[x] = v[y].
+    Assign(usize, usize),
+    /// "8xy1": v[x] |= v[y].
+    Or(usize, usize),
+    /// "8xy2": v[x] &= v[y].
+    And(usize, usize),
+    /// "8xy3": v[x] ^= v[y].
+    Xor(usize, usize),
+    /// "8xy4": v[x] += v[y], with carry in v[f].
+    Add(usize, usize),
+    /// "8xy5": v[x] -= v[y], with NOT borrow in v[f].
+    Sub(usize, usize),
+    /// "8xy6": shift right.
+    ShiftRight(usize, usize),
+    /// "8xy7": v[x] = v[y] - v[x], with NOT borrow in v[f].
+    SubReverse(usize, usize),
+    /// "8xye": shift left.
+    ShiftLeft(usize, usize),
+    /// "9xy0": skip the next instruction if v[x] != v[y].
+    SkipIfNe(usize, usize),
+    /// "annn": i = nnn.
+    SetI(usize),
+    /// "bnnn": jump to nnn + v[0].
+    JumpOffset(usize),
+    /// "cxnn": v[x] = random byte & nn.
+    Random(usize, u8),
+    /// "dxyn": draw a sprite n rows high at (v[x], v[y]).
+    Draw(usize, usize, u8),
+    /// "ex9e": skip the next instruction if key v[x] is pressed.
+    SkipIfKey(usize),
+    /// "exa1": skip the next instruction if key v[x] is not
+    /// pressed.
+    SkipIfNotKey(usize),
+    /// "f000 nnnn": i = nnnn, a 16 bit address (XO-CHIP).
+    LongI,
+    /// "fn01": draw on the bit planes in the mask n
+    /// (XO-CHIP).
+    Planes(u8),
+    /// "f002": load the 16 byte audio pattern at i
+    /// (XO-CHIP).
+    Audio,
+    /// "fx07": v[x] = delay timer.
+    GetDelay(usize),
+    /// "fx0a": wait for a key press and store it in v[x].
+    WaitKey(usize),
+    /// "fx15": delay timer = v[x].
+    SetDelay(usize),
+    /// "fx18": sound timer = v[x].
+    SetSound(usize),
+    /// "fx1e": i += v[x].
+    AddI(usize),
+    /// "fx29": i = address of the font character v[x].
+    Font(usize),
+    /// "fx30": i = address of the big font character v[x]
+    /// (SUPER-CHIP).
+    BigFont(usize),
+    /// "fx33": store the BCD digits of v[x] at i.
+    Bcd(usize),
+    /// "fx3a": set the audio pattern playback pitch to v[x]
+    /// (XO-CHIP).
+    Pitch(usize),
+    /// "fx55": store v[0] to v[x] at i.
+    Store(usize),
+    /// "fx65": load v[0] to v[x] from i.
+    Load(usize),
+    /// "fx75": store v[0] to v[x] in the RPL user flags
+    /// (SUPER-CHIP).
+    StoreFlags(usize),
+    /// "fx85": load v[0] to v[x] from the RPL user flags
+    /// (SUPER-CHIP).
+    LoadFlags(usize),
+}
+
+/// Error returned when an opcode is not a valid instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid opcode 0x{:04x}", self.opcode)
+    }
+}
+
+impl Error for DecodeError {
+    fn description(&self) -> &str {
+        "invalid opcode"
+    }
+}
+
+/// Decode the 2 byte instruction "opcode". For "f000 nnnn"
+/// this is the first 2 bytes.
+pub fn decode(opcode: u16) -> Result<Instruction, DecodeError> {
+    use self::Instruction::*;
+
+    let x = usize::from((opcode >> 8) & 0xf);
+    let y = usize::from((opcode >> 4) & 0xf);
+    let n = (opcode & 0xf) as u8;
+    let nn = (opcode & 0xff) as u8;
+    let nnn = usize::from(opcode & 0xfff);
+
+    let insn = match opcode >> 12 {
+        0x0 => match nnn {
+            0x0c0 ..= 0x0cf => ScrollDown(n),
+            0x0d0 ..= 0x0df => ScrollUp(n),
+            0x0e0 => Clear,
+            0x0ee => Return,
+            0x0fb => ScrollRight,
+            0x0fc => ScrollLeft,
+            0x0fd => Exit,
+            0x0fe => Lores,
+            0x0ff => Hires,
+            _ => return Err(DecodeError { opcode }),
+        },
+        0x1 => Jump(nnn),
+        0x2 => Call(nnn),
+        0x3 => SkipIfEqConst(x, nn),
+        0x4 => SkipIfNeConst(x, nn),
+        0x5 => match n {
+            0x0 => SkipIfEq(x, y),
+            0x2 => StoreRange(x, y),
+            0x3 => LoadRange(x, y),
+            _ => return Err(DecodeError { opcode }),
+        },
+        0x6 => SetConst(x, nn),
+        0x7 => AddConst(x, nn),
+        0x8 => match n {
+            0x0 => Assign(x, y),
+            0x1 => Or(x, y),
+            0x2 => And(x, y),
+            0x3 => Xor(x, y),
+            0x4 => Add(x, y),
+            0x5 => Sub(x, y),
+            0x6 => ShiftRight(x, y),
+            0x7 => SubReverse(x, y),
+            0xe => ShiftLeft(x, y),
+            _ => return Err(DecodeError { opcode }),
+        },
+        0x9 if n == 0 => SkipIfNe(x, y),
+        0xa => SetI(nnn),
+        0xb => JumpOffset(nnn),
+        0xc => Random(x, nn),
+        0xd => Draw(x, y, n),
+        0xe => match nn {
+            0x9e => SkipIfKey(x),
+            0xa1 => SkipIfNotKey(x),
+            _ => return Err(DecodeError { opcode }),
+        },
+        0xf => match nn {
+            0x00 if x == 0 => LongI,
+            0x01 => Planes(x as u8),
+            0x02 if x == 0 => Audio,
+            0x07 => GetDelay(x),
+            0x0a => WaitKey(x),
+            0x15 => SetDelay(x),
+            0x18 => SetSound(x),
+            0x1e => AddI(x),
+            0x29 => Font(x),
+            0x30 => BigFont(x),
+            0x33 => Bcd(x),
+            0x3a => Pitch(x),
+            0x55 => Store(x),
+            0x65 => Load(x),
+            0x75 => StoreFlags(x),
+            0x85 => LoadFlags(x),
+            _ => return Err(DecodeError { opcode }),
+        },
+        _ => return Err(DecodeError { opcode }),
+    };
+    Ok(insn)
+}
+
+impl Instruction {
+    /// Size of the instruction in bytes.
+    pub fn size(&self) -> usize {
+        match *self {
+            Instruction::LongI => 4,
+            _ => 2,
+        }
+    }
+
+    /// The opcode of the instruction; the inverse of `decode`.
+    /// For "f000 nnnn" this is the first 2 bytes.
+    pub fn encode(&self) -> u16 {
+        use self::Instruction::*;
+
+        let xy = |base: u16, x: usize, y: usize| base | ((x as u16) << 8) | ((y as u16) << 4);
+        let xnn = |base: u16, x: usize, nn: u8| base | ((x as u16) << 8) | u16::from(nn);
+        let fx = |nn: u16, x: usize| 0xf000 | ((x as u16) << 8) | nn;
+
+        match *self {
+            ScrollDown(n) => 0x00c0 | u16::from(n),
+            ScrollUp(n) => 0x00d0 | u16::from(n),
+            Clear => 0x00e0,
+            Return => 0x00ee,
+            ScrollRight => 0x00fb,
+            ScrollLeft => 0x00fc,
+            Exit => 0x00fd,
+            Lores => 0x00fe,
+            Hires => 0x00ff,
+            Jump(nnn) => 0x1000 | nnn as u16,
+            Call(nnn) => 0x2000 | nnn as u16,
+            SkipIfEqConst(x, nn) => xnn(0x3000, x, nn),
+            SkipIfNeConst(x, nn) => xnn(0x4000, x, nn),
+            SkipIfEq(x, y) => xy(0x5000, x, y),
+            StoreRange(x, y) => xy(0x5002, x, y),
+            LoadRange(x, y) => xy(0x5003, x, y),
+            SetConst(x, nn) => xnn(0x6000, x, nn),
+            AddConst(x, nn) => xnn(0x7000, x, nn),
+            Assign(x, y) => xy(0x8000, x, y),
+            Or(x, y) => xy(0x8001, x, y),
+            And(x, y) => xy(0x8002, x, y),
+            Xor(x, y) => xy(0x8003, x, y),
+            Add(x, y) => xy(0x8004, x, y),
+            Sub(x, y) => xy(0x8005, x, y),
+            ShiftRight(x, y) => xy(0x8006, x, y),
+            SubReverse(x, y) => xy(0x8007, x, y),
+            ShiftLeft(x, y) => xy(0x800e, x, y),
+            SkipIfNe(x, y) => xy(0x9000, x, y),
+            SetI(nnn) => 0xa000 | nnn as u16,
+            JumpOffset(nnn) => 0xb000 | nnn as u16,
+            Random(x, nn) => xnn(0xc000, x, nn),
+            Draw(x, y, n) => xy(0xd000, x, y) | u16::from(n),
+            SkipIfKey(x) => xnn(0xe000, x, 0x9e),
+            SkipIfNotKey(x) => xnn(0xe000, x, 0xa1),
+            LongI => 0xf000,
+            Planes(n) => fx(0x01, usize::from(n)),
+            Audio => 0xf002,
+            GetDelay(x) => fx(0x07, x),
+            WaitKey(x) => fx(0x0a, x),
+            SetDelay(x) => fx(0x15, x),
+            SetSound(x) => fx(0x18, x),
+            AddI(x) => fx(0x1e, x),
+            Font(x) => fx(0x29, x),
+            BigFont(x) => fx(0x30, x),
+            Bcd(x) => fx(0x33, x),
+            Pitch(x) => fx(0x3a, x),
+            Store(x) => fx(0x55, x),
+            Load(x) => fx(0x65, x),
+            StoreFlags(x) => fx(0x75, x),
+            LoadFlags(x) => fx(0x85, x),
+        }
+    }
+}
+
+#[cfg(test)]
+#[path="./insn_test.rs"]
+mod insn_test;
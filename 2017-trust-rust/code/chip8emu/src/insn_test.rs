@@ -0,0 +1,56 @@
+use super::*;
+use super::Instruction::*;
+
+#[test]
+fn test_decode() {
+    assert_eq!(decode(0x00c5), Ok(ScrollDown(5)));
+    assert_eq!(decode(0x00d2), Ok(ScrollUp(2)));
+    assert_eq!(decode(0x00ee), Ok(Return));
+    assert_eq!(decode(0x1234), Ok(Jump(0x234)));
+    assert_eq!(decode(0x3a12), Ok(SkipIfEqConst(0xa, 0x12)));
+    assert_eq!(decode(0x5ab0), Ok(SkipIfEq(0xa, 0xb)));
+    assert_eq!(decode(0x5ab2), Ok(StoreRange(0xa, 0xb)));
+    assert_eq!(decode(0x5ba3), Ok(LoadRange(0xb, 0xa)));
+    assert_eq!(decode(0x8ab6), Ok(ShiftRight(0xa, 0xb)));
+    assert_eq!(decode(0x8abe), Ok(ShiftLeft(0xa, 0xb)));
+    assert_eq!(decode(0xb300), Ok(JumpOffset(0x300)));
+    assert_eq!(decode(0xd12f), Ok(Draw(1, 2, 0xf)));
+    assert_eq!(decode(0xe5a1), Ok(SkipIfNotKey(5)));
+    assert_eq!(decode(0xf000), Ok(LongI));
+    assert_eq!(decode(0xf301), Ok(Planes(3)));
+    assert_eq!(decode(0xf002), Ok(Audio));
+    assert_eq!(decode(0xf40a), Ok(WaitKey(4)));
+    assert_eq!(decode(0xf23a), Ok(Pitch(2)));
+    assert_eq!(decode(0xf785), Ok(LoadFlags(7)));
+}
+
+#[test]
+fn test_decode_invalid() {
+    for &opcode in &[0x0000, 0x00e1, 0x0123, 0x5121, 0x8008, 0x9ab1, 0xe19f, 0xf100, 0xf102, 0xffff] {
+        assert_eq!(decode(opcode), Err(DecodeError { opcode }));
+    }
+    assert_eq!(DecodeError { opcode: 0x8008 }.to_string(), "invalid opcode 0x8008");
+}
+
+#[test]
+fn test_size() {
+    assert_eq!(LongI.size(), 4);
+    assert_eq!(Planes(1).size(), 2);
+    assert_eq!(Jump(0x200).size(), 2);
+}
+
+#[test]
+fn test_encode_decode() {
+    let mut valid = 0;
+    for opcode in 0..=0xffffu32 {
+        let opcode = opcode as u16;
+        if let Ok(insn) = decode(opcode) {
+            assert_eq!(insn.encode(), opcode, "{:?}", insn);
+            valid += 1;
+        }
+    }
+    // 0x0 group: 2 * 16 + 7, 0x5: 3 * 256, 0x9: 256, 0x8: 9 * 256,
+    // 0xe: 2 * 16, 0xf: 14 * 16 + 2, the other 10 groups: 4096
+    // each.
+    assert_eq!(valid, 39 + 4 * 256 + 9 * 256 + 2 * 16 + 14 * 16 + 2 + 10 * 4096);
+}
@@ -0,0 +1,139 @@
+// jit.rs
+
+// A translation cache for the CPU. Rather than reading and
+// decoding each instruction every time it is executed, runs of
+// straight-line code are decoded once into blocks of
+// instructions. A block is kept until the memory it was
+// decoded from is written to, by "fx33", "fx55", "5xy2", a call
+// pushing onto the stack, or loading a ROM or a state.
+
+use insn::{self, Instruction};
+
+/// The most instructions in a block.
+pub const MAX_BLOCK_INSNS: usize = 32;
+
+/// Straight-line code, decoded from memory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    /// Address of the first instruction.
+    pub start: usize,
+    pub insns: Vec<Instruction>,
+}
+
+impl Block {
+    /// Address of the byte after the block.
+    pub fn end(&self) -> usize {
+        self.start + 2 * self.insns.len()
+    }
+}
+
+/// Return true if control may go somewhere else than the next
+/// instruction after "insn", which then ends a block. "fx0a"
+/// stays on itself until a key is pressed. "f000 nnnn" ends a
+/// block too, as its address is read from memory when it is
+/// executed, and is followed by the next instruction 4 bytes on.
+fn ends_block(insn: Instruction) -> bool {
+    use insn::Instruction::*;
+
+    matches!(insn, Jump(_) | JumpOffset(_) | Call(_) | Return | Exit | LongI |
+             SkipIfEqConst(..) | SkipIfNeConst(..) | SkipIfEq(..) | SkipIfNe(..) |
+             SkipIfKey(_) | SkipIfNotKey(_) | WaitKey(_))
+}
+
+/// Decode the block which starts at `pc` in `mem`. It stops
+/// before an invalid opcode or the end of memory, so there is
+/// no block if the instruction at `pc` is not valid.
+pub fn compile(mem: &[u8], pc: usize) -> Option<Block> {
+    let mut insns = Vec::new();
+    let mut addr = pc;
+    while insns.len() < MAX_BLOCK_INSNS && addr + 1 < mem.len() {
+        let insn = match insn::decode((u16::from(mem[addr]) << 8) | u16::from(mem[addr + 1])) {
+            Ok(insn) => insn,
+            Err(_) => break,
+        };
+        insns.push(insn);
+        if ends_block(insn) {
+            break;
+        }
+        addr += 2;
+    }
+    if insns.is_empty() {
+        None
+    } else {
+        Some(Block { start: pc, insns })
+    }
+}
+
+/// The blocks decoded so far, by start address.
+pub struct BlockCache {
+    blocks: Vec<Option<Block>>,
+    /// Number of blocks each byte of memory is part of.
+    covered: Vec<u8>,
+    /// The start of the block being run, and the index of its
+    /// next instruction.
+    current: Option<(usize, usize)>,
+}
+
+impl BlockCache {
+    /// An empty cache for a memory of `mem_size` bytes.
+    pub fn new(mem_size: usize) -> BlockCache {
+        BlockCache { blocks: vec![None; mem_size], covered: vec![0; mem_size], current: None }
+    }
+
+    /// The instruction at `pc`, decoded from `mem` unless it is
+    /// cached. None if it is not a valid instruction, or not
+    /// all in memory.
+    pub fn next(&mut self, mem: &[u8], pc: usize) -> Option<Instruction> {
+        if pc + 1 >= mem.len() {
+            return None;
+        }
+        if let Some((start, ref mut index)) = self.current {
+            if let Some(ref block) = self.blocks[start] {
+                if *index < block.insns.len() && start + 2 * *index == pc {
+                    *index += 1;
+                    return Some(block.insns[*index - 1]);
+                }
+            }
+        }
+        if self.blocks[pc].is_none() {
+            let block = compile(mem, pc)?;
+            for c in &mut self.covered[block.start..block.end()] {
+                *c += 1;
+            }
+            self.blocks[pc] = Some(block);
+        }
+        self.current = Some((pc, 1));
+        self.blocks[pc].as_ref().map(|b| b.insns[0])
+    }
+
+    /// Forget the blocks decoded from any of the `len` bytes
+    /// from `addr` on, which were written to.
+    pub fn invalidate(&mut self, addr: usize, len: usize) {
+        let end = addr + len;
+        if self.covered[addr..end].iter().all(|&c| c == 0) {
+            return;
+        }
+        for start in addr.saturating_sub(2 * MAX_BLOCK_INSNS - 1)..end {
+            let overlaps = match self.blocks[start] {
+                Some(ref block) => block.end() > addr,
+                None => false,
+            };
+            if overlaps {
+                let block = self.blocks[start].take().unwrap();
+                for c in &mut self.covered[block.start..block.end()] {
+                    *c -= 1;
+                }
+            }
+        }
+        self.current = None;
+    }
+
+    /// Number of blocks in the cache.
+    pub fn num_blocks(&self) -> usize {
+        self.blocks.iter().filter(|b| b.is_some()).count()
+    }
+}
+
+#[cfg(test)]
+#[path="./jit_test.rs"]
+mod jit_test;
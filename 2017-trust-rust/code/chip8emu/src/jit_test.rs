@@ -0,0 +1,129 @@
+use super::*;
+
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+
+use cpu::{CPU, PC_START};
+use display::KeyState;
+use headless::Framebuffer;
+use quirks::{self, Quirks};
+use rom;
+use scheduler::DEFAULT_INSNS_PER_FRAME;
+
+#[test]
+fn test_compile() {
+    use insn::Instruction::*;
+
+    let mem = [0x60, 0x01,  // v0 := 0x01
+               0x70, 0x01,  // v0 += 0x01
+               0x30, 0x05,  // if v0 != 0x05 then
+               0x12, 0x02,  // jump 0x202
+               0x00, 0xe0];
+    assert_eq!(compile(&mem, 0), Some(Block {
+        start: 0,
+        insns: vec![SetConst(0, 1), AddConst(0, 1), SkipIfEqConst(0, 5)],
+    }));
+    assert_eq!(compile(&mem, 6).unwrap().insns, vec![Jump(0x202)]);
+    // Only a byte is left.
+    assert_eq!(compile(&mem[..9], 8), None);
+
+    // The block stops before the invalid opcode.
+    let mem = [0x00, 0xe0, 0x01, 0x23];
+    assert_eq!(compile(&mem, 0).unwrap().insns, vec![Clear]);
+    assert_eq!(compile(&mem, 2), None);
+
+    let mem: Vec<u8> = (0..MAX_BLOCK_INSNS + 1).flat_map(|_| vec![0x00, 0xe0]).collect();
+    assert_eq!(compile(&mem, 0).unwrap().insns.len(), MAX_BLOCK_INSNS);
+}
+
+#[test]
+fn test_invalidate() {
+    let mem = [0x00, 0xe0,  // 0: clear
+               0x00, 0xe0,  // 2: clear
+               0x12, 0x00,  // 4: jump 0x200
+               0x00, 0xe0,  // 6: clear, not in a block
+               0x00, 0xe0,  // 8: clear
+               0x00, 0xe0]; // a: clear
+    let mut cache = BlockCache::new(mem.len());
+    assert_eq!(cache.next(&mem, 0), Some(Instruction::Clear));
+    assert_eq!(cache.next(&mem, 8), Some(Instruction::Clear));
+    assert_eq!(cache.num_blocks(), 2);
+    cache.invalidate(6, 2);
+    assert_eq!(cache.num_blocks(), 2);
+    cache.invalidate(11, 1);
+    assert_eq!(cache.num_blocks(), 1);
+    cache.invalidate(4, 1);
+    assert_eq!(cache.num_blocks(), 0);
+    // Beyond the end of memory.
+    assert_eq!(cache.next(&mem, mem.len() - 1), None);
+    assert_eq!(cache.next(&mem, mem.len() + 6), None);
+}
+
+/// Run "rom" with the interpreter and with the translation
+/// cache side by side for "ninsns" instructions, and check
+/// that the registers and memory are the same after every
+/// instruction, and the whole state after every frame. Keys
+/// are pressed and released at pseudo-random.
+fn run_side_by_side(name: &str, rom: &[u8], quirks: Quirks, ninsns: u32) {
+    let keypad = Rc::new(RefCell::new(KeyState::new()));
+    let mut cpus: Vec<CPU> = (0..2).map(|_| {
+        let mut c = CPU::new(Box::new(Framebuffer::new()), Box::new(keypad.clone()));
+        c.set_quirks(quirks);
+        c.set_seed(1);
+        c.load_rom(rom, PC_START).unwrap();
+        c
+    }).collect();
+    let mut keys: u32 = 0x1234_5678;
+    for n in 0..ninsns {
+        if n % DEFAULT_INSNS_PER_FRAME == 0 {
+            keys ^= keys << 13;
+            keys ^= keys >> 17;
+            keys ^= keys << 5;
+            *keypad.borrow_mut() = KeyState::from_bits(keys as u16 & keys.rotate_left(7) as u16);
+            assert!(cpus[0].save_state() == cpus[1].save_state(),
+                    "{}: state differs before instruction {}", name, n);
+            for c in &mut cpus {
+                c.vblank();
+            }
+        }
+        let interpreted = cpus[0].execute_insn();
+        let cached = cpus[1].execute_cached();
+        assert_eq!(interpreted, cached, "{}: instruction {}", name, n);
+        let regs = |c: &CPU| (c.pc(), c.i(), c.sp(), c.v().to_vec(), c.delay(), c.sound());
+        assert_eq!(regs(&cpus[0]), regs(&cpus[1]), "{}: instruction {}", name, n);
+        assert!(cpus[0].mem() == cpus[1].mem(), "{}: memory differs after instruction {}", name, n);
+        if interpreted.is_err() || cpus[0].exited() {
+            break;
+        }
+    }
+}
+
+#[test]
+fn test_bundled_roms_side_by_side() {
+    for (name, _) in rom::list_roms(rom::BUNDLED_ROMS_DIR).unwrap() {
+        let data = fs::read(format!("{}/{}", rom::BUNDLED_ROMS_DIR, name)).unwrap();
+        for preset in &quirks::PRESET_NAMES {
+            run_side_by_side(&format!("{} ({})", name, preset), &data, preset.parse().unwrap(), 5000);
+        }
+    }
+}
+
+#[test]
+fn test_self_modifying_side_by_side() {
+    // "save v0" rewrites the "v1 := 0x00" which follows it to
+    // "v1 := 0x42", in the block being run.
+    let rom = [0x60, 0x42,  // 200: v0 := 0x42
+               0xa2, 0x07,  // 202: i := 0x207
+               0xf0, 0x55,  // 204: save v0
+               0x61, 0x00,  // 206: v1 := 0x00, rewritten
+               0x12, 0x00]; // 208: jump 0x200
+    run_side_by_side("self-modifying", &rom, Quirks::default(), 1000);
+
+    let mut c = CPU::headless();
+    c.load_rom(&rom[..], PC_START).unwrap();
+    for _ in 0..4 {
+        c.execute_cached().unwrap();
+    }
+    assert_eq!(c.v()[1], 0x42);
+}
@@ -0,0 +1,156 @@
+// keymap.rs
+
+// Key bindings: which keys of the host keyboard act as which
+// keys of the CHIP-8 hex keypad.
+//
+// The standard layout maps the left side of a QWERTY
+// keyboard onto the keypad of the COSMAC VIP:
+//
+//   1 2 3 4        1 2 3 C
+//   Q W E R   ->   4 5 6 D
+//   A S D F        7 8 9 E
+//   Z X C V        A 0 B F
+//
+// Other bindings can be read from a file like this one:
+//
+//   # Used for every ROM. Replaces the standard layout.
+//   [default]
+//   1 = 1
+//   Up = 5
+//   Keypad 8 = 5
+//
+//   # Only used for the ROM file named PONG, on top of
+//   # the [default] bindings.
+//   [PONG]
+//   W = 1
+//   S = 4
+//
+// Host keys are given by their SDL names, which are not case
+// sensitive; CHIP-8 keys are hex digits. Lines before the
+// first section belong to [default].
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+/// The standard layout, as (host key name, CHIP-8 key).
+const STANDARD_LAYOUT: [(&str, u8); 16] = [
+    ("1", 0x1), ("2", 0x2), ("3", 0x3), ("4", 0xc),
+    ("q", 0x4), ("w", 0x5), ("e", 0x6), ("r", 0xd),
+    ("a", 0x7), ("s", 0x8), ("d", 0x9), ("f", 0xe),
+    ("z", 0xa), ("x", 0x0), ("c", 0xb), ("v", 0xf),
+];
+
+/// Name of the section used for every ROM.
+const DEFAULT_SECTION: &str = "default";
+
+/// Error returned when a key bindings file can not be parsed.
+#[derive(Debug, PartialEq)]
+pub struct KeymapError {
+    /// Line number, starting at 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for KeymapError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+/// A set of key bindings. Several host keys may be bound to
+/// the same CHIP-8 key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keymap {
+    /// CHIP-8 key bound to each host key, by lower case name.
+    bindings: BTreeMap<String, u8>,
+}
+
+impl Keymap {
+    /// The standard layout.
+    pub fn new() -> Keymap {
+        let mut keymap = Keymap { bindings: BTreeMap::new() };
+        for &(name, key) in STANDARD_LAYOUT.iter() {
+            keymap.bind(name, key);
+        }
+        keymap
+    }
+
+    /// Make the host key named `name` act as CHIP-8 key `key`,
+    /// in place of the key it was bound to before, if any.
+    pub fn bind(&mut self, name: &str, key: u8) {
+        self.bindings.insert(name.to_lowercase(), key);
+    }
+
+    /// The CHIP-8 key bound to the host key named `name`.
+    pub fn key(&self, name: &str) -> Option<u8> {
+        self.bindings.get(&name.to_lowercase()).cloned()
+    }
+
+    /// All bindings, as (host key name, CHIP-8 key), ordered
+    /// by name. Names are in lower case.
+    pub fn bindings(&self) -> &BTreeMap<String, u8> {
+        &self.bindings
+    }
+
+    /// Parse a key bindings file, keeping the bindings for
+    /// the ROM file named `rom`.
+    pub fn parse(src: &str, rom: &str) -> Result<Keymap, KeymapError> {
+        let mut default = None;
+        let mut overrides = Vec::new();
+        let mut section = DEFAULT_SECTION.to_string();
+        for (index, line) in src.lines().enumerate() {
+            let err = |message: String| KeymapError { line: index + 1, message };
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('[') {
+                if !line.ends_with(']') || line.len() < 3 {
+                    return Err(err(format!("bad section header '{}'", line)));
+                }
+                section = line[1..line.len() - 1].trim().to_string();
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let (name, key) = match (parts.next(), parts.next()) {
+                (Some(name), Some(key)) if !name.trim().is_empty() => (name.trim(), key.trim()),
+                _ => return Err(err(format!("expected 'host key = CHIP-8 key', found '{}'", line))),
+            };
+            let key = match u8::from_str_radix(key, 16) {
+                Ok(k) if k < 16 => k,
+                _ => return Err(err(format!("bad CHIP-8 key '{}'", key))),
+            };
+            if section == DEFAULT_SECTION {
+                default.get_or_insert_with(Vec::new).push((name, key));
+            } else if section == rom {
+                overrides.push((name, key));
+            }
+        }
+
+        let mut keymap = match default {
+            Some(_) => Keymap { bindings: BTreeMap::new() },
+            None => Keymap::new(),
+        };
+        for &(name, key) in default.iter().flat_map(|d| d.iter()).chain(overrides.iter()) {
+            keymap.bind(name, key);
+        }
+        Ok(keymap)
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        Keymap::new()
+    }
+}
+
+#[cfg(test)]
+#[path="./keymap_test.rs"]
+mod keymap_test;
@@ -0,0 +1,63 @@
+use super::*;
+
+#[test]
+fn test_standard_layout() {
+    let k = Keymap::new();
+    assert_eq!(k.bindings().len(), 16);
+    assert_eq!(k.key("1"), Some(0x1));
+    assert_eq!(k.key("4"), Some(0xc));
+    assert_eq!(k.key("Q"), Some(0x4));
+    assert_eq!(k.key("x"), Some(0x0));
+    assert_eq!(k.key("V"), Some(0xf));
+    assert_eq!(k.key("0"), None);
+}
+
+#[test]
+fn test_parse() {
+    let src = "
+        # Arrows for everything
+        [default]
+        Up = 5
+        Down = 8  # comment
+        Keypad 5 = 0
+
+        [PONG]
+        w = 1
+        up = 2
+
+        [BRIX]
+        Left = 4
+    ";
+    let k = Keymap::parse(src, "BRIX").unwrap();
+    let bindings: Vec<(&str, u8)> =
+        k.bindings().iter().map(|(n, &v)| (n.as_str(), v)).collect();
+    assert_eq!(bindings, [("down", 8), ("keypad 5", 0), ("left", 4), ("up", 5)]);
+
+    // The ROM section overrides the default one.
+    let k = Keymap::parse(src, "PONG").unwrap();
+    assert_eq!(k.key("Up"), Some(2));
+    assert_eq!(k.key("W"), Some(1));
+    assert_eq!(k.key("Left"), None);
+}
+
+#[test]
+fn test_parse_without_default() {
+    // Without a [default] section the standard layout is kept.
+    let k = Keymap::parse("[PONG]\nUp = 1\n", "PONG").unwrap();
+    assert_eq!(k.key("Up"), Some(1));
+    assert_eq!(k.key("Q"), Some(4));
+    assert_eq!(Keymap::parse("", "PONG").unwrap(), Keymap::new());
+}
+
+#[test]
+fn test_parse_errors() {
+    let err = |src: &str| Keymap::parse(src, "PONG").unwrap_err();
+    assert_eq!(err("[default]\nUp = 10"),
+               KeymapError { line: 2, message: "bad CHIP-8 key '10'".to_string() });
+    assert_eq!(err("Up 1").line, 1);
+    assert_eq!(err("= 1").line, 1);
+    assert_eq!(err("\n[PONG").line, 2);
+    assert_eq!(err("[]").line, 1);
+    // Other ROMs' sections are checked too.
+    assert_eq!(err("[BRIX]\nUp = g").line, 2);
+}
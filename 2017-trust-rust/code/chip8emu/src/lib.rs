@@ -0,0 +1,71 @@
+// lib.rs
+
+//! The chip8 library: a CHIP-8, SUPER-CHIP and XO-CHIP
+//! machine, and the tools around it. It does not need SDL;
+//! the player, the tools in src/bin, the web page version in
+//! wasm/ and the benchmarks are all built on it.
+//!
+//! A [`machine::Machine`] is what a frontend drives. It runs
+//! its program by instruction or by 60 Hz frame, and tells
+//! what happened as [`machine::Event`]s:
+//!
+//! ```
+//! use chip8::asm;
+//! use chip8::machine::{Event, Machine};
+//!
+//! let rom = asm::assemble("v0 := key\ni := hex v0\nsprite v1 v1 5\nexit", 0x200).unwrap();
+//! let mut m = Machine::headless();
+//! m.load_rom(&rom[..]).unwrap();
+//! // The program waits for a key to be pressed and released,
+//! // then draws its digit.
+//! m.press(7);
+//! m.run_frame().unwrap();
+//! m.release(7);
+//! let events = m.run_frame().unwrap();
+//! assert_eq!(events, vec![Event::ScreenChanged, Event::Exited]);
+//! assert_eq!(m.cpu().v()[0], 7);
+//! let (width, height, pixels) = m.framebuffer();
+//! assert_eq!((width, height), (64, 32));
+//! assert_eq!(pixels[..4], [1, 1, 1, 1]);
+//! ```
+//!
+//! A frontend shows the screen on a [`display::Display`] of its
+//! own with `Machine::new`, and plays the buzzer on an
+//! [`audio::Audio`]. [`cpu::CPU`] is the machine's processor,
+//! for quirks, save states, cheats, the debugger and the
+//! profiler.
+
+extern crate gif;
+#[cfg(test)]
+extern crate proptest;
+extern crate png;
+extern crate rand;
+extern crate zip;
+
+pub mod analyze;
+pub mod asm;
+pub mod audio;
+pub mod capture;
+pub mod cheat;
+pub mod clock;
+pub mod cpu;
+pub mod debugger;
+pub mod disasm;
+pub mod display;
+pub mod error;
+pub mod font;
+pub mod headless;
+pub mod insn;
+pub mod jit;
+pub mod keymap;
+pub mod machine;
+pub mod movie;
+pub mod palette;
+pub mod profile;
+pub mod quirks;
+pub mod render;
+pub mod rom;
+pub mod scheduler;
+pub mod state;
+pub mod terminal;
+pub mod trace;
@@ -0,0 +1,259 @@
+// machine.rs
+
+// A whole CHIP-8 machine: the CPU with its display, keypad and
+// buzzer, run in 60 Hz frames. This is what frontends drive;
+// the CPU is still there for what a machine does not cover,
+// like quirks, save states or the debugger.
+
+use std::cell::{Cell, RefCell};
+use std::io::Read;
+use std::rc::Rc;
+
+use audio::Audio;
+use clock::{Clock, VirtualClock};
+use cpu::{CPU, PC_START};
+use display::{Display, KeyState};
+use error::Chip8Error;
+use headless::Framebuffer;
+use scheduler::{Scheduler, DEFAULT_INSNS_PER_FRAME};
+
+/// Something which happened while the machine ran, that a
+/// frontend may want to act on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    /// A pixel was turned on or off, or the screen was
+    /// cleared.
+    ScreenChanged,
+    /// The sound timer was set, so the buzzer sounds.
+    SoundStarted,
+    /// The sound timer ran out.
+    SoundStopped,
+    /// The program ran "00fd".
+    Exited,
+}
+
+/// A display which notes when it is drawn to.
+struct WatchedDisplay {
+    display: Box<dyn Display>,
+    changed: Rc<Cell<bool>>,
+}
+
+impl Display for WatchedDisplay {
+    fn clear(&mut self) {
+        self.changed.set(true);
+        self.display.clear()
+    }
+
+    fn set_resolution(&mut self, width: u32, height: u32) {
+        self.changed.set(true);
+        self.display.set_resolution(width, height)
+    }
+
+    fn draw_pixel(&mut self, x: u32, y: u32, val: u8) {
+        if self.display.get_pixel(x, y) != val {
+            self.changed.set(true);
+        }
+        self.display.draw_pixel(x, y, val)
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> u8 {
+        self.display.get_pixel(x, y)
+    }
+
+    fn present(&mut self) {
+        self.display.present()
+    }
+
+    fn vblank(&mut self) {
+        self.display.vblank()
+    }
+}
+
+/// What a machine remembers to tell which events happened.
+struct Watch {
+    screen_changed: Rc<Cell<bool>>,
+    sound_playing: bool,
+    exited: bool,
+}
+
+impl Watch {
+    /// Add to `events` those which happened since the last
+    /// update.
+    fn update(&mut self, cpu: &CPU, events: &mut Vec<Event>) {
+        if self.screen_changed.replace(false) {
+            events.push(Event::ScreenChanged);
+        }
+        let playing = cpu.sound() > 0;
+        if playing != self.sound_playing {
+            self.sound_playing = playing;
+            events.push(if playing { Event::SoundStarted } else { Event::SoundStopped });
+        }
+        // A state loaded since may have undone the exit.
+        if cpu.exited() != self.exited {
+            self.exited = cpu.exited();
+            if self.exited {
+                events.push(Event::Exited);
+            }
+        }
+    }
+}
+
+/// A CHIP-8 machine. Its frames are timed by a clock of type
+/// `C`: the host's clock to run at the speed of the original,
+/// or a virtual clock to run as fast as frames are asked for.
+pub struct Machine<C: Clock> {
+    cpu: CPU,
+    keypad: Rc<RefCell<KeyState>>,
+    scheduler: Scheduler<C>,
+    /// Run the code through the translation cache.
+    jit: bool,
+    watch: Watch,
+}
+
+impl Machine<VirtualClock> {
+    /// A machine with an in-memory framebuffer, which makes
+    /// no sound and never sleeps, for tools and tests.
+    pub fn headless() -> Machine<VirtualClock> {
+        Machine::new(Box::new(Framebuffer::new()), VirtualClock::new())
+    }
+}
+
+impl<C: Clock> Machine<C> {
+    /// A machine showing its screen on `display`, with frames
+    /// timed by `clock`. It runs DEFAULT_INSNS_PER_FRAME
+    /// instructions per frame, and no key is pressed.
+    pub fn new(display: Box<dyn Display>, clock: C) -> Machine<C> {
+        let screen_changed = Rc::new(Cell::new(false));
+        let display = WatchedDisplay { display, changed: screen_changed.clone() };
+        let keypad = Rc::new(RefCell::new(KeyState::new()));
+        Machine {
+            cpu: CPU::new(Box::new(display), Box::new(keypad.clone())),
+            keypad,
+            scheduler: Scheduler::new(clock, DEFAULT_INSNS_PER_FRAME),
+            jit: false,
+            watch: Watch { screen_changed, sound_playing: false, exited: false },
+        }
+    }
+
+    /// Load `rom` at PC_START, where programs start.
+    pub fn load_rom<R: Read>(&mut self, rom: R) -> Result<(), Chip8Error> {
+        self.cpu.load_rom(rom, PC_START)
+    }
+
+    /// Make the buzzer sound on `audio`.
+    pub fn set_audio(&mut self, audio: Box<dyn Audio>) {
+        self.cpu.set_audio(audio);
+    }
+
+    pub fn set_insns_per_frame(&mut self, insns_per_frame: u32) {
+        self.scheduler.set_insns_per_frame(insns_per_frame);
+    }
+
+    /// Run the code through the translation cache (true) or
+    /// the interpreter (false). Both give the same results.
+    pub fn set_jit(&mut self, jit: bool) {
+        self.jit = jit;
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    /// The CPU, for settings and tools a machine does not
+    /// cover. Loading a state through it does not produce
+    /// events until the next step.
+    pub fn cpu_mut(&mut self) -> &mut CPU {
+        &mut self.cpu
+    }
+
+    pub fn clock(&self) -> &C {
+        self.scheduler.clock()
+    }
+
+    /// Execute one instruction, without starting a frame.
+    /// Return the events it caused.
+    pub fn step(&mut self) -> Result<Vec<Event>, Chip8Error> {
+        let result = if self.jit { self.cpu.execute_cached() } else { self.cpu.execute_insn() };
+        let mut events = Vec::new();
+        self.watch.update(&self.cpu, &mut events);
+        result.map(|()| events)
+    }
+
+    /// Run one 60 Hz frame, and wait for its time slot to end.
+    /// Return the events of the frame. The frame ends early if
+    /// the program exits or an instruction faults.
+    pub fn run_frame(&mut self) -> Result<Vec<Event>, Chip8Error> {
+        let jit = self.jit;
+        let mut fault = None;
+        let events = self.run_frame_with(|cpu| {
+            let result = if jit { cpu.execute_cached() } else { cpu.execute_insn() };
+            match result {
+                Ok(()) => true,
+                Err(e) => { fault = Some(e); false },
+            }
+        });
+        match fault {
+            Some(e) => Err(e),
+            None => Ok(events.unwrap_or_default()),
+        }
+    }
+
+    /// Run one frame, executing its instructions by calling
+    /// `exec`, which returns false to stop the machine, for
+    /// example to run them under the debugger. Return the
+    /// events of the frame, or None if `exec` stopped the
+    /// machine.
+    pub fn run_frame_with<F>(&mut self, mut exec: F) -> Option<Vec<Event>>
+        where F: FnMut(&mut CPU) -> bool {
+        let mut events = Vec::new();
+        let mut stopped = false;
+        let watch = &mut self.watch;
+        self.scheduler.run_frame(&mut self.cpu, |cpu| {
+            stopped = !exec(cpu);
+            watch.update(cpu, &mut events);
+            !stopped
+        });
+        if stopped {
+            return None;
+        }
+        // A frame without instructions still has its vertical
+        // blank, at which the sound timer may run out.
+        self.watch.update(&self.cpu, &mut events);
+        Some(events)
+    }
+
+    /// Return true if the program has exited.
+    pub fn exited(&self) -> bool {
+        self.cpu.exited()
+    }
+
+    /// The width and height of the screen, and the value of
+    /// each pixel, row by row.
+    pub fn framebuffer(&self) -> (u32, u32, Vec<u8>) {
+        self.cpu.framebuffer()
+    }
+
+    /// The keys held down.
+    pub fn keys(&self) -> KeyState {
+        *self.keypad.borrow()
+    }
+
+    /// Hold down `keys`, and only them.
+    pub fn set_keys(&mut self, keys: KeyState) {
+        *self.keypad.borrow_mut() = keys;
+    }
+
+    /// Press the CHIP-8 key `key` (0 to 0xf).
+    pub fn press(&mut self, key: u8) {
+        self.keypad.borrow_mut().press(key);
+    }
+
+    /// Release the CHIP-8 key `key`.
+    pub fn release(&mut self, key: u8) {
+        self.keypad.borrow_mut().release(key);
+    }
+}
+
+#[cfg(test)]
+#[path="./machine_test.rs"]
+mod machine_test;
@@ -0,0 +1,59 @@
+use super::*;
+
+use asm;
+use display::KeyState;
+use std::time::Duration;
+
+/// A headless machine running the program "src".
+fn machine_with_program(src: &str) -> Machine<VirtualClock> {
+    let mut m = Machine::headless();
+    m.load_rom(&asm::assemble(src, PC_START).unwrap()[..]).unwrap();
+    m
+}
+
+#[test]
+fn test_step_events() {
+    let mut m = machine_with_program("i := dot\nsprite v0 v0 1\nv1 := 2\nbuzzer := v1\nexit\n: dot\n0x80");
+    assert_eq!(m.step(), Ok(vec![]));
+    assert_eq!(m.step(), Ok(vec![Event::ScreenChanged]));
+    assert_eq!(m.framebuffer().2[0], 1);
+    m.step().unwrap();
+    assert_eq!(m.step(), Ok(vec![Event::SoundStarted]));
+    assert_eq!(m.step(), Ok(vec![Event::Exited]));
+    assert!(m.exited());
+}
+
+#[test]
+fn test_frame_events() {
+    let mut m = machine_with_program("v1 := 1\nbuzzer := v1\n: loop\njump loop");
+    m.set_insns_per_frame(10);
+    assert_eq!(m.run_frame(), Ok(vec![Event::SoundStarted]));
+    // The timer runs out at the vertical blank.
+    assert_eq!(m.run_frame(), Ok(vec![Event::SoundStopped]));
+    assert_eq!(m.run_frame(), Ok(vec![]));
+    assert_eq!(m.clock().now(), Duration::new(0, 50_000_000));
+
+    // The frame ends at the fault.
+    let mut m = machine_with_program("clear\n0x01 0x23");
+    assert_eq!(m.run_frame(), Err(Chip8Error::InvalidOpcode { pc: PC_START + 2, opcode: 0x0123 }));
+
+    // Or when it is told to stop.
+    let mut m = machine_with_program("clear\n: loop\njump loop");
+    let mut count = 0;
+    assert_eq!(m.run_frame_with(|cpu| { count += 1; count < 3 && cpu.execute_insn().is_ok() }), None);
+    assert_eq!(m.cpu().pc(), PC_START + 2);
+}
+
+#[test]
+fn test_keys() {
+    let mut m = machine_with_program("v0 := key\nexit");
+    m.set_jit(true);
+    m.press(5);
+    assert_eq!(m.keys(), KeyState::from_bits(1 << 5));
+    m.run_frame().unwrap();
+    m.release(5);
+    assert_eq!(m.run_frame(), Ok(vec![Event::Exited]));
+    assert_eq!(m.cpu().v()[0], 5);
+    m.set_keys(KeyState::from_bits(0xffff));
+    assert_eq!(m.keys().bits(), 0xffff);
+}
@@ -1,9 +1,13 @@
 
 
-mod cpu;
+mod frontend;
 mod screen;
-mod chip8;
+mod speaker;
+mod tui;
+mod player;
 
+extern crate chip8;
+extern crate crossterm;
 extern crate rand;
 extern crate sdl2;
 extern crate structopt;
@@ -11,33 +15,241 @@ extern crate structopt;
 #[macro_use]
 extern crate structopt_derive;
 
-#[macro_use]
-extern crate lazy_static;
-#[macro_use]
-extern crate maplit;
+use std::fs::File;
+use std::io::prelude::*;
+use std::process;
 
+use chip8::{audio, capture, cheat, clock, cpu, debugger, display, error, font, keymap, machine,
+            movie, palette, quirks, render, rom, scheduler, state, terminal, trace};
+use frontend::Frontend;
+use cheat::Cheat;
+use keymap::Keymap;
+use movie::Movie;
+use rom::RomSource;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
 struct Opt {
-    #[structopt(long = "fontfile", help = "Name of the file containing fonts")]
-    font_file: String,
-    #[structopt(long = "gamefile", help = "Name of the file containing game code")]
-    game_file: String,
+    #[structopt(long = "fontfile", help = "Name of a file with a 4x5 font to use instead of the built-in one")]
+    font_file: Option<String>,
+    #[structopt(long = "gamefile", help = "The ROM: a file name, - for stdin, a zip file holding one ROM, or zipfile:ROM")]
+    game_file: Option<String>,
+    #[structopt(long = "list", help = "List the ROMs which come with the emulator, and exit")]
+    list: bool,
+    #[structopt(long = "frontend", help = "Where to show the game: sdl, in a window, or tui, in the terminal. Default is sdl")]
+    frontend: Option<Frontend>,
     #[structopt(long = "scale", help = "The scale factor of the Window. Default is 5")]
     scale_factor: Option<u32>,
+    #[structopt(long = "headless", help = "Run without a window, for the number of instructions given")]
+    headless: Option<u64>,
+    #[structopt(long = "quirks", help = "Interpreter behaviour: default, chip8, schip or xochip. Default is default")]
+    quirks: Option<quirks::Quirks>,
+    #[structopt(long = "on-fault", help = "What to do when the program accesses memory beyond its end, overflows the stack or executes an invalid opcode: halt, wrap or ignore. Default is halt")]
+    fault_policy: Option<error::FaultPolicy>,
+    #[structopt(long = "ipf", help = "Instructions executed per 60 Hz frame. Default is 15")]
+    insns_per_frame: Option<u32>,
+    #[structopt(long = "vblank-wait", help = "Make sprite drawing wait for the next frame, whatever the quirks")]
+    vblank_wait: bool,
+    #[structopt(long = "jit", help = "Run the code through a translation cache, which decodes each block of straight-line code only once")]
+    jit: bool,
+    #[structopt(long = "frequency", help = "Frequency of the buzzer in Hz. Default is 440")]
+    frequency: Option<u32>,
+    #[structopt(long = "volume", help = "Volume of the buzzer in percent, 0 for no sound. Default is 25")]
+    volume: Option<u32>,
+    #[structopt(long = "keymap", help = "File with key bindings, for every ROM or per ROM. Default is the 1234/QWER/ASDF/ZXCV layout")]
+    keymap_file: Option<String>,
+    #[structopt(long = "rplfile", help = "File to save SUPER-CHIP RPL flags to. Default is the game file name (the ROM name for stdin and zip files) with .rpl appended")]
+    rpl_file: Option<String>,
+    #[structopt(long = "statefile", help = "File used by the save (F5) and load (F9) state keys. Default is the game file name with .state appended")]
+    state_file: Option<String>,
+    #[structopt(long = "debug", help = "Start in the debugger. Type 'help' at its prompt for the commands")]
+    debug: bool,
+    #[structopt(long = "seed", help = "Seed of the random number generator, to make a run reproducible. Default is a random seed")]
+    seed: Option<u64>,
+    #[structopt(long = "record", help = "Record the keypad input of every frame to a movie file, which --play can play back")]
+    record_file: Option<String>,
+    #[structopt(long = "play", help = "Play back a movie recorded with --record, with its seed and instructions per frame. With --headless, stop at its end")]
+    play_file: Option<String>,
+    #[structopt(long = "palette", help = "Colours of the pixels: default, amber, green, lcd, octo, or a list of colours like 000000,fafafa; XO-CHIP uses four")]
+    palette: Option<palette::Palette>,
+    #[structopt(long = "filter", help = "Pixel art filter used to scale up the picture: none, scale2x or scale3x. Default is none")]
+    filter: Option<render::Filter>,
+    #[structopt(long = "phosphor", help = "Percent of their brightness pixels turned off keep from one frame to the next, to reduce flicker. Default is 0")]
+    persistence: Option<u32>,
+    #[structopt(long = "screenshot", help = "PNG file the screenshot key (F12) saves to; with --headless, a screenshot of the end of the run is saved to it. Default is the game file name with .png appended")]
+    screenshot_file: Option<String>,
+    #[structopt(long = "gif", help = "Record the run to an animated GIF file")]
+    gif_file: Option<String>,
+    #[structopt(long = "profile", help = "Count the instructions executed by address, opcode, frame and subroutine, and print the hottest ones at exit")]
+    profile: bool,
+    #[structopt(long = "profile-out", help = "Write the profile to a file at exit: JSON if its name ends in .json, folded stacks for flamegraph.pl otherwise")]
+    profile_file: Option<String>,
+    #[structopt(long = "trace", help = "Write a line for each instruction executed to a file: the cycle, the registers before it and its mnemonic, to compare with other emulators using trace-diff")]
+    trace_file: Option<String>,
+    #[structopt(long = "trace-range", help = "Only trace the instructions at addresses in a range, like 0x200-0x2ff")]
+    trace_range: Option<trace::AddrRange>,
+    #[structopt(long = "trace-ops", help = "Only trace the instructions in some classes, like dxyn,8: opcode patterns as in the profile, or hex digits for all the opcodes starting with them")]
+    trace_ops: Option<trace::OpClasses>,
+    #[structopt(long = "cheats", help = "File of cheats to enable: patches of the ROM, applied once it is loaded, and addresses frozen to a value. The debugger can search memory for more")]
+    cheats_file: Option<String>,
+}
+
+fn exit_with_error(msg: &str) -> ! {
+    eprintln!("chip8_emu: {}", msg);
+    process::exit(1);
+}
+
+/// Read the key bindings for the ROM named `rom_name` from
+/// the key bindings file `filename`.
+fn load_keymap(filename: &str, rom_name: &str) -> Result<Keymap, String> {
+    let mut src = String::new();
+    File::open(filename)
+        .and_then(|mut f| f.read_to_string(&mut src))
+        .map_err(|e| format!("{}: {}", filename, e))?;
+    Keymap::parse(&src, rom_name).map_err(|e| format!("{}: {}", filename, e))
 }
 
+/// Read the cheats in the cheat file `filename`.
+fn load_cheats(filename: &str) -> Result<Vec<Cheat>, String> {
+    let mut src = String::new();
+    File::open(filename)
+        .and_then(|mut f| f.read_to_string(&mut src))
+        .map_err(|e| format!("{}: {}", filename, e))?;
+    cheat::parse(&src).map_err(|e| format!("{}: {}", filename, e))
+}
+
+/// Read the movie file `filename` to play it back with
+/// `rom`.
+fn load_movie(filename: &str, rom: &[u8]) -> Result<Movie, String> {
+    let movie = File::open(filename)
+        .and_then(|mut f| Movie::read(&mut f))
+        .map_err(|e| format!("{}: {}", filename, e))?;
+    if !movie.matches_rom(rom) {
+        eprintln!("warning: {} was recorded with another ROM", filename);
+    }
+    Ok(movie)
+}
 
+/// Print the ROMs in the roms/ directory of the emulator.
+fn list_roms() {
+    let roms = rom::list_roms(rom::BUNDLED_ROMS_DIR)
+        .unwrap_or_else(|e| exit_with_error(&format!("{}: {}", rom::BUNDLED_ROMS_DIR, e)));
+    println!("ROMs in {}:", rom::BUNDLED_ROMS_DIR);
+    for (name, size) in roms {
+        println!("  {:<12} {:>5} bytes", name, size);
+    }
+}
 
 fn main() {
     let opt = Opt::from_args();
+    if opt.list {
+        list_roms();
+        return;
+    }
+    let game_file = match opt.game_file {
+        Some(ref f) => f.clone(),
+        None => exit_with_error("no ROM given, use --gamefile, or --list to see the bundled ROMs"),
+    };
+    let source = RomSource::parse(&game_file);
+    let rom = source.read().unwrap_or_else(|e| exit_with_error(&format!("{}: {}", game_file, e)));
+    let font = opt.font_file.as_ref().map(|f| {
+        let mut font = Vec::new();
+        File::open(f).and_then(|mut r| r.read_to_end(&mut font))
+            .unwrap_or_else(|e| exit_with_error(&format!("{}: {}", f, e)));
+        font
+    });
+    // Files kept for a ROM are named after it.
+    let save_name = match source {
+        RomSource::File(ref path) => path.clone(),
+        _ => source.rom_name(),
+    };
     let mut scale_factor: u32 = screen::DEFAULT_SCALE_FACTOR; 
 
     if let Some(s) = opt.scale_factor {
         scale_factor = s;
     }
 
-    chip8::chip8_run(&opt.font_file, &opt.game_file, scale_factor);
+    let mut quirks = opt.quirks.unwrap_or_default();
+    if opt.vblank_wait {
+        quirks.display_wait = true;
+    }
+    let mut insns_per_frame = opt.insns_per_frame.unwrap_or(scheduler::DEFAULT_INSNS_PER_FRAME);
+    let mut seed = opt.seed.unwrap_or_else(rand::random);
+    if opt.record_file.is_some() && (opt.play_file.is_some() || opt.headless.is_some()) {
+        exit_with_error("--record cannot be used with --play or --headless");
+    }
+    let movie = opt.play_file.as_ref().map(|f| {
+        load_movie(f, &rom).unwrap_or_else(|e| exit_with_error(&e))
+    });
+    if let Some(ref m) = movie {
+        seed = m.seed;
+        insns_per_frame = m.insns_per_frame;
+    }
+    let rpl_file = match opt.rpl_file {
+        Some(ref f) => f.clone(),
+        None => format!("{}.rpl", save_name),
+    };
+    let state_file = match opt.state_file {
+        Some(ref f) => f.clone(),
+        None => format!("{}.state", save_name),
+    };
+
+    // Headless runs only take a screenshot when asked to.
+    let screenshot_file = match opt.screenshot_file {
+        Some(ref f) => Some(f.clone()),
+        None if opt.headless.is_some() => None,
+        None => Some(format!("{}.png", save_name)),
+    };
+
+    let keymap = match opt.keymap_file {
+        Some(ref f) => load_keymap(f, &source.rom_name()).unwrap_or_else(|e| exit_with_error(&e)),
+        None => Keymap::new(),
+    };
+
+    let cheats = match opt.cheats_file {
+        Some(ref f) => load_cheats(f).unwrap_or_else(|e| exit_with_error(&e)),
+        None => Vec::new(),
+    };
+
+    let config = player::Config {
+        font,
+        rom,
+        scale_factor,
+        quirks,
+        fault_policy: opt.fault_policy.unwrap_or_default(),
+        insns_per_frame,
+        jit: opt.jit,
+        frequency: opt.frequency.unwrap_or(audio::DEFAULT_FREQUENCY),
+        volume: opt.volume.unwrap_or(audio::DEFAULT_VOLUME),
+        keymap,
+        rpl_file,
+        state_file,
+        debug: opt.debug,
+        seed,
+        record_file: opt.record_file.clone(),
+        movie,
+        palette: opt.palette.clone().unwrap_or_default(),
+        filter: opt.filter.unwrap_or_default(),
+        persistence: opt.persistence.unwrap_or(0),
+        screenshot_file,
+        gif_file: opt.gif_file.clone(),
+        profile: opt.profile,
+        profile_file: opt.profile_file.clone(),
+        trace_file: opt.trace_file.clone(),
+        trace_filter: trace::TraceFilter { range: opt.trace_range, ops: opt.trace_ops.clone() },
+        cheats,
+    };
+
+    if let Some(n) = opt.headless {
+        player::chip8_run_headless(&config, n);
+        return;
+    }
+
+    match opt.frontend.unwrap_or_default() {
+        Frontend::Sdl => player::chip8_run(&config),
+        // The debugger reads its commands from the terminal.
+        Frontend::Tui if opt.debug => exit_with_error("--debug cannot be used with --frontend tui"),
+        Frontend::Tui => player::chip8_run_tui(&config),
+    }
 
 }
@@ -0,0 +1,158 @@
+// movie.rs
+
+// Input movies: the keypad state of every frame of a run, so
+// that the run can be played back exactly. Together with the
+// seed of the random number generator and the number of
+// instructions per frame, this is all the emulator needs to
+// reproduce a run; the quirks and fault policy are not
+// recorded and must be given again when playing it back.
+//
+// A movie file is a header followed by one big-endian u16
+// per frame, the bitmask of the keys held down during that
+// frame (see KeyState::bits). Frames are appended as they are
+// recorded, so the file is usable even if the emulator is
+// killed in the middle of a run.
+
+use std::io;
+use std::io::prelude::*;
+
+use display::KeyState;
+use state::{self, invalid_data};
+
+/// Every movie file starts with these bytes.
+const MAGIC: &[u8; 4] = b"C8MV";
+
+/// Version of the movie file format.
+pub const MOVIE_VERSION: u8 = 1;
+
+/// A recorded run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Movie {
+    /// Seed of the random number generator.
+    pub seed: u64,
+    pub insns_per_frame: u32,
+    /// Hash of the ROM the movie was recorded with, from
+    /// `rom_hash`.
+    pub rom_hash: u32,
+    /// The keys held down in each frame.
+    pub frames: Vec<KeyState>,
+}
+
+/// A hash of `rom` (32 bit FNV-1a), to tell whether a movie
+/// was recorded with it.
+pub fn rom_hash(rom: &[u8]) -> u32 {
+    rom.iter().fold(0x811c_9dc5, |h, &b| (h ^ u32::from(b)).wrapping_mul(0x0100_0193))
+}
+
+impl Movie {
+    /// A movie of no frames yet, of a run of `rom`.
+    pub fn new(seed: u64, insns_per_frame: u32, rom: &[u8]) -> Movie {
+        Movie { seed, insns_per_frame, rom_hash: rom_hash(rom), frames: Vec::new() }
+    }
+
+    /// Return true if the movie was recorded with `rom`.
+    pub fn matches_rom(&self, rom: &[u8]) -> bool {
+        self.rom_hash == rom_hash(rom)
+    }
+
+    fn write_header<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&[MOVIE_VERSION])?;
+        state::write_u32(w, (self.seed >> 32) as usize)?;
+        state::write_u32(w, (self.seed & 0xffff_ffff) as usize)?;
+        state::write_u32(w, self.insns_per_frame as usize)?;
+        state::write_u32(w, self.rom_hash as usize)
+    }
+
+    /// Write the movie in the movie file format.
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.write_header(w)?;
+        for keys in &self.frames {
+            state::write_u16(w, usize::from(keys.bits()))?;
+        }
+        Ok(())
+    }
+
+    /// Read a movie written by `Movie::write` or a Recorder.
+    pub fn read<R: Read>(r: &mut R) -> io::Result<Movie> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a CHIP-8 movie"));
+        }
+        if state::read_u8(r)? != MOVIE_VERSION {
+            return Err(invalid_data("unsupported movie version"));
+        }
+        let seed = (state::read_u32(r)? as u64) << 32 | state::read_u32(r)? as u64;
+        let insns_per_frame = state::read_u32(r)? as u32;
+        let rom_hash = state::read_u32(r)? as u32;
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)?;
+        if buf.len() & 1 != 0 {
+            return Err(invalid_data("truncated movie frame"));
+        }
+        let frames = buf.chunks(2)
+            .map(|b| KeyState::from_bits(u16::from(b[0]) << 8 | u16::from(b[1])))
+            .collect();
+        Ok(Movie { seed, insns_per_frame, rom_hash, frames })
+    }
+}
+
+/// Writes a movie frame by frame, as the game is played.
+pub struct Recorder<W: Write> {
+    w: W,
+    frames: usize,
+}
+
+impl<W: Write> Recorder<W> {
+    /// Start recording a run with the settings of `movie`
+    /// to `w`. The frames of `movie` are not written.
+    pub fn new(mut w: W, movie: &Movie) -> io::Result<Recorder<W>> {
+        movie.write_header(&mut w)?;
+        w.flush()?;
+        Ok(Recorder { w, frames: 0 })
+    }
+
+    /// Record the keys held down in the next frame.
+    pub fn record(&mut self, keys: &KeyState) -> io::Result<()> {
+        state::write_u16(&mut self.w, usize::from(keys.bits()))?;
+        self.frames += 1;
+        self.w.flush()
+    }
+
+    /// Number of frames recorded so far.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+}
+
+/// Plays a movie back frame by frame.
+pub struct Player {
+    movie: Movie,
+    frame: usize,
+}
+
+impl Player {
+    pub fn new(movie: Movie) -> Player {
+        Player { movie, frame: 0 }
+    }
+
+    /// The keys held down in the next frame, or None at the
+    /// end of the movie.
+    pub fn next_frame(&mut self) -> Option<KeyState> {
+        let keys = self.movie.frames.get(self.frame).cloned();
+        if keys.is_some() {
+            self.frame += 1;
+        }
+        keys
+    }
+
+    /// Number of frames played so far.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+}
+
+#[cfg(test)]
+#[path="./movie_test.rs"]
+mod movie_test;
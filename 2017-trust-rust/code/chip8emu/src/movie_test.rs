@@ -0,0 +1,89 @@
+use super::*;
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::Cursor;
+use std::rc::Rc;
+
+use clock::VirtualClock;
+use cpu::{CPU, PC_START};
+use headless::Framebuffer;
+use rom::BUNDLED_ROMS_DIR;
+use scheduler::Scheduler;
+
+fn keys(pressed: &[u8]) -> KeyState {
+    let mut k = KeyState::new();
+    for &key in pressed {
+        k.press(key);
+    }
+    k
+}
+
+#[test]
+fn test_key_bits() {
+    assert_eq!(KeyState::new().bits(), 0);
+    assert_eq!(keys(&[0, 5, 0xf]).bits(), 0x8021);
+    assert_eq!(KeyState::from_bits(0x8021), keys(&[0, 5, 0xf]));
+}
+
+#[test]
+fn test_write_read() {
+    let mut movie = Movie::new(0x0123_4567_89ab_cdef, 15, b"\x12\x00");
+    movie.frames = vec![keys(&[]), keys(&[4]), keys(&[4, 6])];
+    let mut buf = Vec::new();
+    movie.write(&mut buf).unwrap();
+    assert_eq!(buf.len(), 21 + 2 * 3);
+    assert_eq!(Movie::read(&mut Cursor::new(&buf)).unwrap(), movie);
+    assert!(movie.matches_rom(b"\x12\x00"));
+    assert!(!movie.matches_rom(b"\x12\x02"));
+
+    buf.pop();
+    assert_eq!(Movie::read(&mut Cursor::new(&buf)).unwrap_err().to_string(),
+               "truncated movie frame");
+    buf[0] = b'X';
+    assert_eq!(Movie::read(&mut Cursor::new(&buf)).unwrap_err().to_string(),
+               "not a CHIP-8 movie");
+}
+
+#[test]
+fn test_record_and_play() {
+    let mut r = Recorder::new(Vec::new(), &Movie::new(42, 10, b"")).unwrap();
+    r.record(&keys(&[1])).unwrap();
+    r.record(&keys(&[])).unwrap();
+    assert_eq!(r.frames(), 2);
+
+    let movie = Movie::read(&mut Cursor::new(r.w)).unwrap();
+    assert_eq!((movie.seed, movie.insns_per_frame), (42, 10));
+    let mut p = Player::new(movie);
+    assert_eq!(p.next_frame(), Some(keys(&[1])));
+    assert_eq!(p.next_frame(), Some(keys(&[])));
+    assert_eq!(p.next_frame(), None);
+    assert_eq!(p.frame(), 2);
+}
+
+/// Play `movie` on BRIX, which uses random numbers, and
+/// return the final state of the machine.
+fn play_brix(movie: &Movie) -> ::state::State {
+    let keypad = Rc::new(RefCell::new(KeyState::new()));
+    let mut c = CPU::new(Box::new(Framebuffer::new()), Box::new(keypad.clone()));
+    c.load_rom(File::open(format!("{}/BRIX", BUNDLED_ROMS_DIR)).unwrap(), PC_START).unwrap();
+    c.set_seed(movie.seed);
+    let mut scheduler = Scheduler::new(VirtualClock::new(), movie.insns_per_frame);
+    let mut player = Player::new(movie.clone());
+    while let Some(keys) = player.next_frame() {
+        *keypad.borrow_mut() = keys;
+        assert!(scheduler.run_frame(&mut c, |c| c.execute_insn().is_ok()));
+    }
+    c.save_state()
+}
+
+#[test]
+fn test_playback_is_deterministic() {
+    let mut movie = Movie::new(7, 15, b"");
+    for n in 0..600 {
+        // Move the paddle left and right.
+        movie.frames.push(keys(if n % 120 < 60 { &[4] } else { &[6] }));
+    }
+    let st = play_brix(&movie);
+    assert_eq!(play_brix(&movie), st);
+    assert!(st.pixels.iter().any(|&p| p != 0));
+}
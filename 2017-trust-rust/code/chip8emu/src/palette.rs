@@ -0,0 +1,106 @@
+// palette.rs
+
+// The colours the pixels of the CHIP-8 display are shown in.
+// A palette is either one of the named palettes below, or a
+// list of colours in hex, like "000000,fafafa". Pixel value
+// n is shown in colour n. With the two XO-CHIP bit planes a
+// pixel is 0 to 3: 1 is the first plane, 2 the second and 3
+// both, so the named palettes have four colours.
+
+use std::cmp;
+use std::fmt;
+use std::str::FromStr;
+
+/// A colour: red, green and blue.
+pub type Rgb = [u8; 3];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    colors: Vec<Rgb>,
+}
+
+/// The named palettes, as (name, colours).
+const PALETTES: &[(&str, &[Rgb])] = &[
+    ("default", &[[0x00, 0x00, 0x00], [0xfa, 0xfa, 0xfa], [0xaa, 0xaa, 0xaa], [0x55, 0x55, 0x55]]),
+    ("amber", &[[0x1a, 0x10, 0x00], [0xff, 0xb0, 0x00], [0xa0, 0x6e, 0x00], [0x55, 0x3a, 0x00]]),
+    ("green", &[[0x00, 0x1a, 0x00], [0x33, 0xff, 0x33], [0x1f, 0x99, 0x1f], [0x0f, 0x4d, 0x0f]]),
+    ("lcd", &[[0x9b, 0xbc, 0x0f], [0x0f, 0x38, 0x0f], [0x8b, 0xac, 0x0f], [0x30, 0x62, 0x30]]),
+    ("octo", &[[0x99, 0x66, 0x00], [0xff, 0xcc, 0x00], [0xff, 0x66, 0x00], [0x66, 0x22, 0x00]]),
+];
+
+impl Palette {
+    /// A palette of `colors`; there must be at least two.
+    pub fn new(colors: Vec<Rgb>) -> Palette {
+        assert!(colors.len() >= 2, "a palette needs at least two colours");
+        Palette { colors }
+    }
+
+    /// The colour of pixel value `val`. Values beyond the
+    /// end of the palette get its last colour.
+    pub fn color(&self, val: u8) -> Rgb {
+        let last = self.colors.len() - 1;
+        self.colors[cmp::min(usize::from(val), last)]
+    }
+
+    /// The colour of pixels which are OFF.
+    pub fn background(&self) -> Rgb {
+        self.colors[0]
+    }
+
+    pub fn colors(&self) -> &[Rgb] {
+        &self.colors
+    }
+
+    /// Names of the named palettes.
+    pub fn names() -> Vec<&'static str> {
+        PALETTES.iter().map(|&(name, _)| name).collect()
+    }
+}
+
+impl Default for Palette {
+    /// White on black.
+    fn default() -> Palette {
+        Palette::new(PALETTES[0].1.to_vec())
+    }
+}
+
+/// Error returned when a palette is neither a known name nor
+/// a list of colours.
+#[derive(Debug, PartialEq)]
+pub struct BadPalette(String);
+
+impl fmt::Display for BadPalette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bad palette '{}', expected one of: {}, or colours like 000000,fafafa",
+               self.0, Palette::names().join(", "))
+    }
+}
+
+/// Parse a colour in hex, like "fafafa" or "#fafafa".
+fn parse_color(s: &str) -> Option<Rgb> {
+    let s = s.trim().trim_start_matches('#');
+    if s.len() != 6 || !s.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let byte = |i| u8::from_str_radix(&s[i..i + 2], 16).ok();
+    Some([byte(0)?, byte(2)?, byte(4)?])
+}
+
+impl FromStr for Palette {
+    type Err = BadPalette;
+
+    fn from_str(s: &str) -> Result<Palette, BadPalette> {
+        if let Some(&(_, colors)) = PALETTES.iter().find(|&&(name, _)| name == s) {
+            return Ok(Palette::new(colors.to_vec()));
+        }
+        let colors: Option<Vec<Rgb>> = s.split(',').map(parse_color).collect();
+        match colors {
+            Some(ref c) if c.len() >= 2 => Ok(Palette::new(c.clone())),
+            _ => Err(BadPalette(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+#[path="./palette_test.rs"]
+mod palette_test;
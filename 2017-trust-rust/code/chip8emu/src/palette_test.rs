@@ -0,0 +1,29 @@
+use super::*;
+
+#[test]
+fn test_named() {
+    let p: Palette = "default".parse().unwrap();
+    assert_eq!(p, Palette::default());
+    assert_eq!(p.color(0), [0, 0, 0]);
+    assert_eq!(p.color(1), [250, 250, 250]);
+    assert_eq!(p.color(2), [170, 170, 170]);
+    assert_eq!(p.color(3), [85, 85, 85]);
+    // Past the end of the palette.
+    assert_eq!(p.color(7), [85, 85, 85]);
+    for name in Palette::names() {
+        assert_eq!(name.parse::<Palette>().unwrap().colors().len(), 4);
+    }
+}
+
+#[test]
+fn test_colors() {
+    let p: Palette = "#102030, ff8000,0000FF".parse().unwrap();
+    assert_eq!(p.colors(), &[[0x10, 0x20, 0x30], [0xff, 0x80, 0x00], [0, 0, 0xff]]);
+    assert_eq!(p.background(), [0x10, 0x20, 0x30]);
+
+    assert!("ffffff".parse::<Palette>().is_err());
+    assert!("ffffff,fffff".parse::<Palette>().is_err());
+    assert!("ffffff,gggggg".parse::<Palette>().is_err());
+    let err = "pink".parse::<Palette>().unwrap_err();
+    assert!(err.to_string().starts_with("bad palette 'pink', expected one of: default, amber"));
+}
@@ -0,0 +1,484 @@
+// player.rs
+
+// The player: runs a game on a machine from the chip8 library,
+// in a window or in the terminal, with the hotkeys, movies,
+// captures and debugger of the command line options.
+
+use frontend::{Controls, Hotkey};
+use screen;
+use tui;
+use display::{KeyState, SCREEN_WIDTH, SCREEN_HEIGHT, HIRES_SCREEN_WIDTH, HIRES_SCREEN_HEIGHT};
+use clock::{Clock, MonotonicClock};
+use capture::{self, GifRecorder};
+use cheat::Cheat;
+use cpu;
+use font;
+use debugger::Debugger;
+use error::FaultPolicy;
+use keymap::Keymap;
+use machine::{Event, Machine};
+use movie::{Movie, Player, Recorder};
+use palette::Palette;
+use quirks::Quirks;
+use render::{Filter, Renderer};
+use state::{State, RewindBuffer};
+use trace::{TraceFilter, Tracer};
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::prelude::*;
+use std::process;
+
+/// A state is saved for rewinding every REWIND_INTERVAL
+/// frames.
+const REWIND_INTERVAL: u32 = 15;
+
+/// Number of states kept for rewinding. Together with
+/// REWIND_INTERVAL this allows going back a few seconds.
+const REWIND_CAPACITY: usize = 40;
+
+fn save_state_to_file(c: &cpu::CPU, filename: &str) -> io::Result<()> {
+    let mut f = File::create(filename)?;
+    c.save_state().write(&mut f)
+}
+
+fn load_state_from_file(c: &mut cpu::CPU, filename: &str) -> io::Result<()> {
+    let mut f = File::open(filename)?;
+    let st = State::read(&mut f)?;
+    c.load_state(&st)
+}
+
+/// Act on a hotkey pressed by the user.
+fn handle_hotkey(c: &mut cpu::CPU, h: Hotkey, state_file: &str, rewind: &mut RewindBuffer,
+                 capture: &mut Capture) {
+    match h {
+        Hotkey::SaveState => {
+            if let Err(e) = save_state_to_file(c, state_file) {
+                eprintln!("warning: failed to save state to {}: {}", state_file, e);
+            }
+        },
+        Hotkey::LoadState => {
+            if let Err(e) = load_state_from_file(c, state_file) {
+                eprintln!("warning: failed to load state from {}: {}", state_file, e);
+            }
+        },
+        Hotkey::Rewind => {
+            if let Some(st) = rewind.pop() {
+                c.load_state(&st).expect("rewind: bad state");
+            }
+        },
+        Hotkey::Screenshot => capture.screenshot(c),
+        Hotkey::Quit => {},
+    }
+}
+
+/// Execute one instruction, under the debugger if there is one,
+/// and trace it. While the debugger is paused, commands are
+/// read from stdin. Without a debugger, the instruction is
+/// taken from the translation cache if `jit` is true. Return
+/// false if the user asked to quit, or if the instruction
+/// faulted and there is no debugger.
+fn execute_insn(c: &mut cpu::CPU, debugger: &mut Option<Debugger>, trace: &mut Trace,
+                jit: bool) -> bool {
+    let d = match *debugger {
+        Some(ref mut d) => d,
+        None => {
+            trace.record(c);
+            let result = if jit { c.execute_cached() } else { c.execute_insn() };
+            if let Err(e) = result {
+                eprintln!("error: {}", e);
+                return false;
+            }
+            return true;
+        },
+    };
+    if d.paused() {
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        d.repl(c, &mut stdin.lock(), &mut stdout.lock()).expect("debugger: I/O error");
+        if d.quit_requested() {
+            return false;
+        }
+    }
+    trace.record(c);
+    if let Some(reason) = d.step(c) {
+        println!("{}", reason);
+    }
+    let _ = io::stdout().flush();
+    true
+}
+
+/// Settings of an emulator run, from the command line.
+pub struct Config {
+    /// A font to load instead of the built-in one.
+    pub font: Option<Vec<u8>>,
+    pub rom: Vec<u8>,
+    pub scale_factor: u32,
+    pub quirks: Quirks,
+    pub fault_policy: FaultPolicy,
+    /// Instructions executed per 60 Hz frame.
+    pub insns_per_frame: u32,
+    /// Run the code through the translation cache.
+    pub jit: bool,
+    /// Frequency of the buzzer in Hz.
+    pub frequency: u32,
+    /// Volume of the buzzer in percent.
+    pub volume: u32,
+    pub keymap: Keymap,
+    pub rpl_file: String,
+    /// File used by the save and load state hotkeys.
+    pub state_file: String,
+    /// Start in the debugger.
+    pub debug: bool,
+    /// Seed of the random number generator.
+    pub seed: u64,
+    /// File to record the keypad input of the run to.
+    pub record_file: Option<String>,
+    /// A movie to take the keypad input from, instead of
+    /// the keyboard.
+    pub movie: Option<Movie>,
+    pub palette: Palette,
+    pub filter: Filter,
+    /// Percent of their brightness pixels turned off keep
+    /// from one frame to the next.
+    pub persistence: u32,
+    /// File screenshots are saved to.
+    pub screenshot_file: Option<String>,
+    /// File to record the run to as an animated GIF.
+    pub gif_file: Option<String>,
+    /// Print a profile of the run when it ends.
+    pub profile: bool,
+    /// File to write the profile to when the run ends: JSON if
+    /// its name ends in .json, folded stacks otherwise.
+    pub profile_file: Option<String>,
+    /// File to write a trace of the instructions executed to.
+    pub trace_file: Option<String>,
+    /// Which instructions go into the trace.
+    pub trace_filter: TraceFilter,
+    /// Cheats enabled once the game is loaded.
+    pub cheats: Vec<Cheat>,
+}
+
+fn renderer(config: &Config) -> Renderer {
+    Renderer::new(config.palette.clone(), config.filter, config.persistence)
+}
+
+/// Screenshots and GIF capture. They are rendered from the
+/// pixels of the CPU's display, apart from the window, so
+/// that they work without one too.
+struct Capture {
+    renderer: Renderer,
+    screenshot_file: Option<String>,
+    gif: Option<GifRecorder<BufWriter<File>>>,
+}
+
+impl Capture {
+    fn new(config: &Config) -> Capture {
+        let renderer = renderer(config);
+        let gif = config.gif_file.as_ref().map(|filename| {
+            // Large enough for the SUPER-CHIP screen; the
+            // 64x32 screen is scaled up to fill it.
+            let (width, height) = renderer.size(u32::from(HIRES_SCREEN_WIDTH),
+                                                u32::from(HIRES_SCREEN_HEIGHT));
+            File::create(filename)
+                .and_then(|f| GifRecorder::new(BufWriter::new(f), width, height))
+                .unwrap_or_else(|e| {
+                    eprintln!("chip8_emu: {}: {}", filename, e);
+                    process::exit(1);
+                })
+        });
+        Capture { renderer, screenshot_file: config.screenshot_file.clone(), gif }
+    }
+
+    /// Capture the frame which has just been run.
+    fn frame(&mut self, c: &cpu::CPU) {
+        if self.gif.is_none() && !self.renderer.fades() {
+            return;
+        }
+        let (width, height, pixels) = c.framebuffer();
+        let img = self.renderer.render(&pixels, width, height);
+        self.renderer.next_frame();
+        if let Some(Err(e)) = self.gif.as_mut().map(|g| g.frame(&img)) {
+            eprintln!("warning: GIF capture stopped: {}", e);
+            self.gif = None;
+        }
+    }
+
+    fn screenshot(&mut self, c: &cpu::CPU) {
+        let filename = match self.screenshot_file {
+            Some(ref f) => f,
+            None => return,
+        };
+        let (width, height, pixels) = c.framebuffer();
+        let img = self.renderer.render(&pixels, width, height);
+        match File::create(filename).and_then(|f| capture::write_png(&img, BufWriter::new(f))) {
+            Ok(()) => println!("screenshot saved to {}", filename),
+            Err(e) => eprintln!("warning: failed to save screenshot to {}: {}", filename, e),
+        }
+    }
+
+    /// End the GIF, if one is being recorded.
+    fn finish(self) {
+        if let Some(Err(e)) = self.gif.map(|g| g.finish()) {
+            eprintln!("warning: failed to write GIF: {}", e);
+        }
+    }
+}
+
+/// The trace of a run, if there is a trace file.
+struct Trace(Option<Tracer<BufWriter<File>>>);
+
+impl Trace {
+    fn new(config: &Config) -> Trace {
+        Trace(config.trace_file.as_ref().map(|filename| {
+            File::create(filename)
+                .and_then(|f| Tracer::new(BufWriter::new(f), config.trace_filter.clone()))
+                .unwrap_or_else(|e| {
+                    eprintln!("chip8_emu: {}: {}", filename, e);
+                    process::exit(1);
+                })
+        }))
+    }
+
+    /// Trace the instruction "c" is about to execute.
+    fn record(&mut self, c: &cpu::CPU) {
+        if let Some(Err(e)) = self.0.as_mut().map(|t| t.record(c)) {
+            eprintln!("warning: trace stopped: {}", e);
+            self.0 = None;
+        }
+    }
+
+    /// Write out the end of the trace.
+    fn finish(self) {
+        if let Some(Err(e)) = self.0.map(|mut t| t.flush()) {
+            eprintln!("warning: failed to write trace: {}", e);
+        }
+    }
+}
+
+/// Where the keypad input of a run comes from.
+enum Input {
+    Keyboard,
+    /// The keyboard, recording every frame.
+    Record(Recorder<File>),
+    Play(Player),
+}
+
+impl Input {
+    fn new(config: &Config) -> Input {
+        if let Some(ref movie) = config.movie {
+            return Input::Play(Player::new(movie.clone()));
+        }
+        let filename = match config.record_file {
+            Some(ref f) => f,
+            None => return Input::Keyboard,
+        };
+        let movie = Movie::new(config.seed, config.insns_per_frame, &config.rom);
+        match File::create(filename).and_then(|f| Recorder::new(f, &movie)) {
+            Ok(r) => Input::Record(r),
+            Err(e) => {
+                eprintln!("chip8_emu: {}: {}", filename, e);
+                process::exit(1);
+            },
+        }
+    }
+
+    /// The keys held down in the next frame, given those
+    /// held down on the keyboard. At the end of a movie,
+    /// or if recording fails, the keyboard takes over.
+    fn next_frame(&mut self, keyboard: KeyState) -> KeyState {
+        match *self {
+            Input::Keyboard => return keyboard,
+            Input::Record(ref mut r) => match r.record(&keyboard) {
+                Ok(()) => return keyboard,
+                Err(e) => eprintln!("warning: recording stopped after {} frames: {}", r.frames(), e),
+            },
+            Input::Play(ref mut p) => match p.next_frame() {
+                Some(keys) => return keys,
+                None => println!("movie ended after {} frames, the keyboard takes over", p.frame()),
+            },
+        }
+        *self = Input::Keyboard;
+        keyboard
+    }
+}
+
+/// The machine `m`, set up as "config" says and with its
+/// font and game loaded, and the cheats enabled. Exit if they
+/// do not fit in memory.
+fn load_machine<C: Clock>(mut m: Machine<C>, config: &Config) -> Machine<C> {
+    m.set_insns_per_frame(config.insns_per_frame);
+    let c = m.cpu_mut();
+    c.set_quirks(config.quirks);
+    c.set_fault_policy(config.fault_policy);
+    c.set_seed(config.seed);
+    if let Err(e) = c.set_rpl_file(&config.rpl_file) {
+        eprintln!("warning: failed to load RPL flags from {}: {}", config.rpl_file, e);
+    }
+    let font = config.font.as_ref().map(|f| (f, font::FONT_START));
+    for (rom, offset) in font.into_iter().chain(Some((&config.rom, cpu::PC_START))) {
+        if let Err(e) = c.load_rom(&rom[..], offset) {
+            eprintln!("chip8_emu: {}", e);
+            process::exit(1);
+        }
+    }
+    for cheat in &config.cheats {
+        if let Err(e) = cheat.enable(c) {
+            eprintln!("chip8_emu: {}", e);
+            process::exit(1);
+        }
+    }
+    if config.profile || config.profile_file.is_some() {
+        c.enable_profiler();
+    }
+    m
+}
+
+/// Print or save the profile of the run, as asked for in
+/// "config".
+fn report_profile(c: &cpu::CPU, config: &Config) {
+    let p = match c.profiler() {
+        Some(p) => p,
+        None => return,
+    };
+    if config.profile {
+        eprint!("{}", p.report());
+    }
+    if let Some(ref filename) = config.profile_file {
+        let result = File::create(filename).and_then(|f| {
+            let mut w = BufWriter::new(f);
+            if filename.ends_with(".json") {
+                p.write_json(&mut w)
+            } else {
+                w.write_all(p.folded().as_bytes())
+            }
+        });
+        if let Err(e) = result {
+            eprintln!("warning: failed to write profile to {}: {}", filename, e);
+        }
+    }
+}
+
+/// Run the game in a window.
+pub fn chip8_run(config: &Config) {
+    let  s = screen::Screen::new(
+        u32::from(SCREEN_WIDTH), 
+        u32::from(SCREEN_HEIGHT), 
+        config.scale_factor,
+        renderer(config));
+    let mut k = s.keyboard(&config.keymap);
+    let speaker = if config.volume > 0 {
+        s.speaker(config.frequency, config.volume)
+            .map_err(|e| eprintln!("warning: no sound: {}", e))
+            .ok()
+    } else {
+        None
+    };
+
+    let mut m = load_machine(Machine::new(Box::new(s), MonotonicClock::new()), config);
+    if let Some(speaker) = speaker {
+        m.set_audio(Box::new(speaker));
+    }
+    let m = run_interactive(m, &mut k, config);
+    report_profile(m.cpu(), config);
+}
+
+/// Run the game in the terminal.
+pub fn chip8_run_tui(config: &Config) {
+    let t = tui::Terminal::new(renderer(config));
+    let mut m = load_machine(Machine::new(Box::new(t), MonotonicClock::new()), config);
+    if config.volume > 0 {
+        m.set_audio(Box::new(tui::Bell));
+    }
+    // Only now that the game is loaded, so that errors are
+    // not printed in raw mode.
+    let guard = tui::TerminalGuard::enter().unwrap_or_else(|e| {
+        eprintln!("chip8_emu: cannot use the terminal: {}", e);
+        process::exit(1);
+    });
+    let mut k = tui::TermKeyboard::new(&config.keymap, guard.reports_releases());
+    let m = run_interactive(m, &mut k, config);
+    drop(guard);
+    report_profile(m.cpu(), config);
+}
+
+/// Run the game on `m` until it exits or the user quits, with
+/// the keypad driven by `k` or the movie of "config". Return
+/// the machine, as the game left it.
+fn run_interactive<K: Controls>(mut m: Machine<MonotonicClock>, k: &mut K,
+                                config: &Config) -> Machine<MonotonicClock> {
+    let mut rewind_counter = 0;
+    let mut rewind = RewindBuffer::new(REWIND_CAPACITY);
+    let mut debugger = if config.debug { Some(Debugger::new()) } else { None };
+    let mut input = Input::new(config);
+    let mut capture = Capture::new(config);
+    let mut trace = Trace::new(config);
+
+    // The keypad is updated once per frame, from the keyboard
+    // or the movie.
+    loop {
+        m.set_keys(input.next_frame(k.keys()));
+        match m.run_frame_with(|c| execute_insn(c, &mut debugger, &mut trace, config.jit)) {
+            Some(ref events) if !events.contains(&Event::Exited) => {},
+            _ => break,
+        }
+        capture.frame(m.cpu());
+        if let Some(h) = k.poll_hotkey() {
+            // Going back in time would make the recorded input
+            // or the movie out of step with the game.
+            match input {
+                _ if h == Hotkey::Quit => break,
+                Input::Keyboard =>
+                    handle_hotkey(m.cpu_mut(), h, &config.state_file, &mut rewind, &mut capture),
+                _ if h == Hotkey::Screenshot => capture.screenshot(m.cpu()),
+                _ => eprintln!("warning: {:?} is disabled while recording or playing a movie", h),
+            }
+        }
+        rewind_counter += 1;
+        if rewind_counter == REWIND_INTERVAL {
+            rewind.push(m.cpu().save_state());
+            rewind_counter = 0;
+        }
+    }
+    capture.finish();
+    trace.finish();
+    m
+}
+
+/// Run the game for `ninsns` instructions, or until it exits,
+/// without opening a window. Keys are pressed as in the movie
+/// of "config", if any, and the run stops at its end; without
+/// a movie no key is ever pressed. Time is kept by a virtual
+/// clock, so the run is deterministic and as fast as the host
+/// allows. A screenshot of the end of the run is saved if
+/// there is a screenshot file.
+pub fn chip8_run_headless(config: &Config, ninsns: u64) {
+    let mut count = 0;
+    let mut debugger = if config.debug { Some(Debugger::new()) } else { None };
+    let mut player = config.movie.clone().map(Player::new);
+    let mut capture = Capture::new(config);
+    let mut trace = Trace::new(config);
+    let mut m = load_machine(Machine::headless(), config);
+
+    let mut exec = |c: &mut cpu::CPU| {
+        count += 1;
+        count <= ninsns && execute_insn(c, &mut debugger, &mut trace, config.jit)
+    };
+    loop {
+        if let Some(ref mut p) = player {
+            match p.next_frame() {
+                Some(keys) => m.set_keys(keys),
+                None => break,
+            }
+        }
+        match m.run_frame_with(&mut exec) {
+            Some(ref events) if !events.contains(&Event::Exited) => {},
+            _ => break,
+        }
+        capture.frame(m.cpu());
+    }
+    capture.screenshot(m.cpu());
+    capture.finish();
+    trace.finish();
+    report_profile(m.cpu(), config);
+}
@@ -0,0 +1,328 @@
+// profile.rs
+
+// A profiler for CHIP-8 programs: how many times each address
+// and each opcode is executed, how many instructions run per
+// frame, and which subroutines the time goes to. Subroutines
+// are followed through "2nnn" and "00ee".
+//
+// The profile can be shown as a report, written as JSON, or as
+// folded stacks for flamegraph.pl and similar tools.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as FmtWrite;
+use std::io;
+use std::io::prelude::*;
+
+use disasm;
+use insn::{self, Instruction};
+
+/// Size of the memory the addresses are counted in: the
+/// largest there is, XO-CHIP's.
+const MEM_SIZE: usize = 0x10000;
+
+/// The most subroutines followed on the call stack. Programs
+/// which call without returning would grow it forever; the
+/// outermost calls are then forgotten.
+pub const MAX_DEPTH: usize = 64;
+
+/// Number of entries in each table of the report.
+const REPORT_ROWS: usize = 10;
+
+/// The opcode pattern of an instruction, like "8xy4".
+pub fn pattern(insn: Instruction) -> &'static str {
+    use insn::Instruction::*;
+
+    match insn {
+        ScrollDown(_) => "00cn",
+        ScrollUp(_) => "00dn",
+        Clear => "00e0",
+        Return => "00ee",
+        ScrollRight => "00fb",
+        ScrollLeft => "00fc",
+        Exit => "00fd",
+        Lores => "00fe",
+        Hires => "00ff",
+        Jump(_) => "1nnn",
+        Call(_) => "2nnn",
+        SkipIfEqConst(..) => "3xnn",
+        SkipIfNeConst(..) => "4xnn",
+        SkipIfEq(..) => "5xy0",
+        StoreRange(..) => "5xy2",
+        LoadRange(..) => "5xy3",
+        SetConst(..) => "6xnn",
+        AddConst(..) => "7xnn",
+        Assign(..) => "8xy0",
+        Or(..) => "8xy1",
+        And(..) => "8xy2",
+        Xor(..) => "8xy3",
+        Add(..) => "8xy4",
+        Sub(..) => "8xy5",
+        ShiftRight(..) => "8xy6",
+        SubReverse(..) => "8xy7",
+        ShiftLeft(..) => "8xye",
+        SkipIfNe(..) => "9xy0",
+        SetI(_) => "annn",
+        JumpOffset(_) => "bnnn",
+        Random(..) => "cxnn",
+        Draw(..) => "dxyn",
+        SkipIfKey(_) => "ex9e",
+        SkipIfNotKey(_) => "exa1",
+        LongI => "f000",
+        Planes(_) => "fn01",
+        Audio => "f002",
+        GetDelay(_) => "fx07",
+        WaitKey(_) => "fx0a",
+        SetDelay(_) => "fx15",
+        SetSound(_) => "fx18",
+        AddI(_) => "fx1e",
+        Font(_) => "fx29",
+        BigFont(_) => "fx30",
+        Bcd(_) => "fx33",
+        Pitch(_) => "fx3a",
+        Store(_) => "fx55",
+        Load(_) => "fx65",
+        StoreFlags(_) => "fx75",
+        LoadFlags(_) => "fx85",
+    }
+}
+
+/// Instructions executed by a subroutine.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SubroutineProfile {
+    /// Number of times it was called.
+    pub calls: u64,
+    /// Instructions executed in the subroutine itself.
+    pub self_count: u64,
+    /// Instructions executed in the subroutine and in the
+    /// subroutines it called.
+    pub total_count: u64,
+}
+
+/// Counts of what a program executed.
+pub struct Profiler {
+    /// Executions of the instruction at each address.
+    addr_counts: Vec<u64>,
+    /// The opcode last executed at each address.
+    opcodes: Vec<u16>,
+    /// Executions of each opcode.
+    opcode_counts: Vec<u64>,
+    /// Instructions executed in each frame so far.
+    frame_counts: Vec<u32>,
+    /// Instructions executed since the last frame started.
+    frame_count: u32,
+    /// The entry points of the subroutines on the call stack,
+    /// from the outermost one, which is where the program
+    /// started.
+    stack: Vec<usize>,
+    /// Instructions executed with each call stack, and the
+    /// index of the current one.
+    stack_counts: Vec<(Vec<usize>, u64)>,
+    stack_index: HashMap<Vec<usize>, usize>,
+    current: usize,
+    /// Number of calls to each subroutine.
+    calls: BTreeMap<usize, u64>,
+}
+
+impl Profiler {
+    /// A profiler for a program which starts at `entry`.
+    pub fn new(entry: usize) -> Profiler {
+        let mut p = Profiler {
+            addr_counts: vec![0; MEM_SIZE],
+            opcodes: vec![0; MEM_SIZE],
+            opcode_counts: vec![0; 1 << 16],
+            frame_counts: Vec::new(),
+            frame_count: 0,
+            stack: vec![entry],
+            stack_counts: Vec::new(),
+            stack_index: HashMap::new(),
+            current: 0,
+            calls: BTreeMap::new(),
+        };
+        p.current = p.stack_id();
+        p
+    }
+
+    /// The index of the current call stack in `stack_counts`.
+    fn stack_id(&mut self) -> usize {
+        if let Some(&id) = self.stack_index.get(&self.stack) {
+            return id;
+        }
+        self.stack_counts.push((self.stack.clone(), 0));
+        self.stack_index.insert(self.stack.clone(), self.stack_counts.len() - 1);
+        self.stack_counts.len() - 1
+    }
+
+    /// The instruction "insn" at `pc` is being executed.
+    pub fn record(&mut self, pc: usize, insn: Instruction) {
+        let pc = pc % MEM_SIZE;
+        let opcode = insn.encode();
+        self.addr_counts[pc] += 1;
+        self.opcodes[pc] = opcode;
+        self.opcode_counts[usize::from(opcode)] += 1;
+        self.frame_count += 1;
+        self.stack_counts[self.current].1 += 1;
+        match insn {
+            Instruction::Call(addr) => {
+                *self.calls.entry(addr).or_insert(0) += 1;
+                if self.stack.len() == MAX_DEPTH {
+                    self.stack.remove(1);
+                }
+                self.stack.push(addr);
+                self.current = self.stack_id();
+            },
+            Instruction::Return if self.stack.len() > 1 => {
+                self.stack.pop();
+                self.current = self.stack_id();
+            },
+            _ => {},
+        }
+    }
+
+    /// A new frame starts. Frames in which nothing was
+    /// executed, before the program started or after it
+    /// exited, are left out.
+    pub fn frame(&mut self) {
+        if self.frame_count > 0 {
+            self.frame_counts.push(self.frame_count);
+            self.frame_count = 0;
+        }
+    }
+
+    /// Number of instructions executed.
+    pub fn total(&self) -> u64 {
+        self.addr_counts.iter().sum()
+    }
+
+    /// Executions of the instruction at `addr`.
+    pub fn count_at(&self, addr: usize) -> u64 {
+        self.addr_counts[addr % MEM_SIZE]
+    }
+
+    /// Executions of the instructions of each opcode pattern.
+    pub fn pattern_counts(&self) -> BTreeMap<&'static str, u64> {
+        let mut counts = BTreeMap::new();
+        for (opcode, &n) in self.opcode_counts.iter().enumerate().filter(|&(_, &n)| n > 0) {
+            if let Ok(insn) = insn::decode(opcode as u16) {
+                *counts.entry(pattern(insn)).or_insert(0) += n;
+            }
+        }
+        counts
+    }
+
+    /// The instructions executed in each frame which ended.
+    pub fn frame_counts(&self) -> &[u32] {
+        &self.frame_counts
+    }
+
+    /// The subroutines, by entry point. The outermost one is
+    /// where the program started.
+    pub fn subroutines(&self) -> BTreeMap<usize, SubroutineProfile> {
+        let mut subs: BTreeMap<usize, SubroutineProfile> = BTreeMap::new();
+        for &(ref stack, n) in &self.stack_counts {
+            subs.entry(*stack.last().unwrap()).or_default().self_count += n;
+            let mut seen = Vec::new();
+            for &addr in stack {
+                if !seen.contains(&addr) {
+                    subs.entry(addr).or_default().total_count += n;
+                    seen.push(addr);
+                }
+            }
+        }
+        for (&addr, &n) in &self.calls {
+            subs.entry(addr).or_default().calls = n;
+        }
+        subs
+    }
+
+    /// The profile as folded stacks, one line per call stack,
+    /// like "0x200;0x2d4 1234": the subroutines on the stack
+    /// and the instructions executed with it. flamegraph.pl
+    /// draws them as a flame graph.
+    pub fn folded(&self) -> String {
+        let mut lines: Vec<String> = self.stack_counts.iter()
+            .filter(|&&(_, n)| n > 0)
+            .map(|&(ref stack, n)| {
+                let names: Vec<String> = stack.iter().map(|a| format!("0x{:03x}", a)).collect();
+                format!("{} {}\n", names.join(";"), n)
+            })
+            .collect();
+        lines.sort();
+        lines.concat()
+    }
+
+    /// The profile as JSON.
+    pub fn write_json<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let (min, max, mean) = self.frame_stats();
+        writeln!(w, "{{")?;
+        writeln!(w, "  \"instructions\": {},", self.total())?;
+        writeln!(w, "  \"frames\": {},", self.frame_counts.len())?;
+        writeln!(w, "  \"insns_per_frame\": {{\"min\": {}, \"max\": {}, \"mean\": {:.2}}},", min, max, mean)?;
+        let addrs: Vec<String> = (0..MEM_SIZE).filter(|&a| self.addr_counts[a] > 0)
+            .map(|a| format!("    {{\"addr\": {}, \"opcode\": \"0x{:04x}\", \"count\": {}}}",
+                             a, self.opcodes[a], self.addr_counts[a]))
+            .collect();
+        writeln!(w, "  \"addresses\": [\n{}\n  ],", addrs.join(",\n"))?;
+        let patterns: Vec<String> = self.pattern_counts().iter()
+            .map(|(p, n)| format!("    \"{}\": {}", p, n))
+            .collect();
+        writeln!(w, "  \"opcodes\": {{\n{}\n  }},", patterns.join(",\n"))?;
+        let subs: Vec<String> = self.subroutines().iter()
+            .map(|(a, s)| format!("    {{\"addr\": {}, \"calls\": {}, \"self\": {}, \"total\": {}}}",
+                                  a, s.calls, s.self_count, s.total_count))
+            .collect();
+        writeln!(w, "  \"subroutines\": [\n{}\n  ]", subs.join(",\n"))?;
+        writeln!(w, "}}")
+    }
+
+    /// The fewest, most and mean instructions per frame.
+    fn frame_stats(&self) -> (u32, u32, f64) {
+        let n = self.frame_counts.len();
+        if n == 0 {
+            return (0, 0, 0.0);
+        }
+        let sum: u64 = self.frame_counts.iter().map(|&c| u64::from(c)).sum();
+        (*self.frame_counts.iter().min().unwrap(), *self.frame_counts.iter().max().unwrap(),
+         sum as f64 / n as f64)
+    }
+
+    /// A summary of the profile: the hottest addresses, opcodes
+    /// and subroutines.
+    pub fn report(&self) -> String {
+        let total = self.total();
+        let percent = |n: u64| if total == 0 { 0.0 } else { 100.0 * n as f64 / total as f64 };
+        let (min, max, mean) = self.frame_stats();
+        let mut out = String::new();
+        let _ = writeln!(out, "{} instructions in {} frames, {:.1} per frame (min {}, max {})",
+                         total, self.frame_counts.len(), mean, min, max);
+
+        let _ = writeln!(out, "\nHottest addresses:");
+        let mut addrs: Vec<usize> = (0..MEM_SIZE).filter(|&a| self.addr_counts[a] > 0).collect();
+        addrs.sort_by_key(|&a| (!self.addr_counts[a], a));
+        for &a in addrs.iter().take(REPORT_ROWS) {
+            let n = self.addr_counts[a];
+            let _ = writeln!(out, "  0x{:03x} {:>10} {:5.1}%  {}", a, n, percent(n),
+                             disasm::disassemble_insn(self.opcodes[a]));
+        }
+
+        let _ = writeln!(out, "\nHottest opcodes:");
+        let mut patterns: Vec<(&str, u64)> = self.pattern_counts().into_iter().collect();
+        patterns.sort_by_key(|&(p, n)| (!n, p));
+        for &(p, n) in patterns.iter().take(REPORT_ROWS) {
+            let _ = writeln!(out, "  {} {:>10} {:5.1}%", p, n, percent(n));
+        }
+
+        let _ = writeln!(out, "\nHottest subroutines (self, with callees, calls):");
+        let mut subs: Vec<(usize, SubroutineProfile)> = self.subroutines().into_iter().collect();
+        subs.sort_by_key(|&(a, s)| (!s.self_count, a));
+        for &(a, s) in subs.iter().take(REPORT_ROWS) {
+            let _ = writeln!(out, "  0x{:03x} {:>10} {:5.1}% {:>10} {:5.1}% {:>8}", a,
+                             s.self_count, percent(s.self_count),
+                             s.total_count, percent(s.total_count), s.calls);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+#[path="./profile_test.rs"]
+mod profile_test;
@@ -0,0 +1,84 @@
+use super::*;
+
+use cpu::{CPU, PC_START};
+
+// A loop calling a subroutine, which calls another one.
+const ROM: [u8; 16] = [
+    0x22, 0x06,  // 200: call 0x206
+    0x12, 0x00,  // 202: jump 0x200
+    0x00, 0xe0,  // 204: clear, never run
+    0x70, 0x01,  // 206: v0 += 0x01
+    0x22, 0x0c,  // 208: call 0x20c
+    0x00, 0xee,  // 20a: return
+    0x71, 0x01,  // 20c: v1 += 0x01
+    0x00, 0xee,  // 20e: return
+];
+
+/// A CPU which ran ROM for `ninsns` instructions, profiled,
+/// with `ipf` instructions per frame.
+fn profiled(ninsns: u32, ipf: u32) -> CPU {
+    let mut c = CPU::headless();
+    c.load_rom(&ROM[..], PC_START).unwrap();
+    c.enable_profiler();
+    for n in 0..ninsns {
+        if n % ipf == 0 {
+            c.vblank();
+        }
+        c.execute_insn().unwrap();
+    }
+    c
+}
+
+#[test]
+fn test_counts() {
+    // 7 instructions per iteration of the loop.
+    let c = profiled(70, 7);
+    let p = c.profiler().unwrap();
+    assert_eq!(p.total(), 70);
+    for &addr in &[0x200, 0x202, 0x206, 0x208, 0x20a, 0x20c, 0x20e] {
+        assert_eq!(p.count_at(addr), 10);
+    }
+    assert_eq!(p.count_at(0x204), 0);
+    let patterns = p.pattern_counts();
+    assert_eq!(patterns["2nnn"], 20);
+    assert_eq!(patterns["00ee"], 20);
+    assert_eq!(patterns["7xnn"], 20);
+    assert_eq!(patterns["1nnn"], 10);
+    assert_eq!(pattern(Instruction::Add(1, 2)), "8xy4");
+
+    // The first frame is not over yet.
+    assert_eq!(p.frame_counts(), &[7; 9][..]);
+}
+
+#[test]
+fn test_subroutines() {
+    let c = profiled(70, 7);
+    let p = c.profiler().unwrap();
+    let subs = p.subroutines();
+    assert_eq!(subs[&0x200], SubroutineProfile { calls: 0, self_count: 20, total_count: 70 });
+    assert_eq!(subs[&0x206], SubroutineProfile { calls: 10, self_count: 30, total_count: 50 });
+    assert_eq!(subs[&0x20c], SubroutineProfile { calls: 10, self_count: 20, total_count: 20 });
+    assert_eq!(p.folded(), "0x200 20\n0x200;0x206 30\n0x200;0x206;0x20c 20\n");
+
+    let report = p.report();
+    assert!(report.starts_with("70 instructions in 9 frames"));
+    assert!(report.contains("0x206         30  42.9%         50  71.4%       10"));
+
+    let mut json = Vec::new();
+    p.write_json(&mut json).unwrap();
+    let json = String::from_utf8(json).unwrap();
+    assert!(json.contains("\"instructions\": 70,"));
+    assert!(json.contains("{\"addr\": 518, \"calls\": 10, \"self\": 30, \"total\": 50}"));
+}
+
+#[test]
+fn test_deep_recursion() {
+    // "call 0x200" at 0x200 calls itself forever.
+    let mut p = Profiler::new(PC_START);
+    for _ in 0..1000 {
+        p.record(PC_START, insn::decode(0x2200).unwrap());
+    }
+    assert_eq!(p.subroutines()[&0x200].calls, 1000);
+    let longest = p.folded().lines().map(|l| l.split(';').count()).max().unwrap();
+    assert_eq!(longest, MAX_DEPTH);
+}
@@ -0,0 +1,134 @@
+// quirks.rs
+
+// References:
+// (1) <https://github.com/Chromatophore/HP48-Superchip#behavior-and-quirk-differences>
+// (2) <https://chip-8.github.io/extensions/>
+
+use std::fmt;
+use std::str::FromStr;
+
+/// CHIP-8 interpreters disagree on what some instructions
+/// do. Games are written for one particular interpreter, so
+/// the behaviour of these instructions is configurable.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quirks {
+    /// "8xy6" and "8xye" shift v[y] and store the result in
+    /// v[x]. Otherwise v[x] is shifted in place and v[y] is
+    /// ignored.
+    pub shift_uses_vy: bool,
+
+    /// "fx55" and "fx65" leave "i" pointing just past the last
+    /// location accessed. Otherwise "i" is not changed.
+    pub load_store_increments_i: bool,
+
+    /// "bnnn" behaves as "bxnn": jump to xnn + v[x].
+    /// Otherwise it jumps to nnn + v[0].
+    pub jump_uses_vx: bool,
+
+    /// Sprites are clipped at the edges of the screen.
+    /// Otherwise the part of the sprite that goes beyond an
+    /// edge wraps around to the opposite edge.
+    pub clip_sprites: bool,
+
+    /// "8xy1", "8xy2" and "8xy3" set v[f] to 0.
+    /// Otherwise v[f] is not changed.
+    pub vf_reset: bool,
+
+    /// "dxyn" waits for the next vertical blank (the start of
+    /// the next 60 Hz frame) before drawing, so at most one
+    /// sprite is drawn per frame. Otherwise it draws at once.
+    pub display_wait: bool,
+
+    /// Memory is 64 KiB, as on XO-CHIP, rather than 4 KiB.
+    /// "f000 nnnn" can load any address in it into "i".
+    pub large_memory: bool,
+}
+
+/// Names accepted by `Quirks::from_str`.
+pub const PRESET_NAMES: [&str; 4] = ["default", "chip8", "schip", "xochip"];
+
+impl Quirks {
+    /// The behaviour this emulator has always had. It
+    /// follows the Python interpreter mentioned in cpu.rs.
+    pub fn default_preset() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            jump_uses_vx: false,
+            clip_sprites: false,
+            vf_reset: false,
+            display_wait: false,
+            large_memory: false,
+        }
+    }
+
+    /// The original COSMAC VIP interpreter.
+    pub fn chip8() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            clip_sprites: true,
+            vf_reset: true,
+            display_wait: true,
+            large_memory: false,
+        }
+    }
+
+    /// SUPER-CHIP 1.1 on the HP48 calculators.
+    pub fn schip() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            jump_uses_vx: true,
+            clip_sprites: true,
+            vf_reset: false,
+            display_wait: false,
+            large_memory: false,
+        }
+    }
+
+    /// XO-CHIP, as implemented by Octo.
+    pub fn xochip() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            clip_sprites: false,
+            vf_reset: false,
+            display_wait: false,
+            large_memory: true,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::default_preset()
+    }
+}
+
+/// Error returned when a quirks preset name is not known.
+#[derive(Debug)]
+pub struct UnknownPreset(String);
+
+impl fmt::Display for UnknownPreset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown quirks preset '{}', expected one of: {}",
+               self.0, PRESET_NAMES.join(", "))
+    }
+}
+
+impl FromStr for Quirks {
+    type Err = UnknownPreset;
+
+    fn from_str(s: &str) -> Result<Quirks, UnknownPreset> {
+        match s {
+            "default" => Ok(Quirks::default_preset()),
+            "chip8" => Ok(Quirks::chip8()),
+            "schip" => Ok(Quirks::schip()),
+            "xochip" => Ok(Quirks::xochip()),
+            _ => Err(UnknownPreset(s.to_string())),
+        }
+    }
+}
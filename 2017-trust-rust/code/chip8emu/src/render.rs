@@ -0,0 +1,239 @@
+// render.rs
+
+// Turns the pixels of the CHIP-8 display into a picture:
+// each pixel is given a colour from the palette, pixels
+// turned off may fade out slowly like on a phosphor screen,
+// and the picture may be scaled up by a pixel art filter.
+// Used by the window, screenshots and GIF capture alike.
+
+use std::fmt;
+use std::str::FromStr;
+
+use palette::{Palette, Rgb};
+
+/// A picture in RGB format, 3 bytes per pixel, row by row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub rgb: Vec<u8>,
+}
+
+impl Image {
+    /// The image scaled up `factor` times, each pixel
+    /// becoming a `factor` x `factor` square.
+    pub fn scaled(&self, factor: u32) -> Image {
+        let (width, height) = (self.width * factor, self.height * factor);
+        let mut rgb = Vec::with_capacity((width * height * 3) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let i = (((y / factor) * self.width + x / factor) * 3) as usize;
+                rgb.extend_from_slice(&self.rgb[i..i + 3]);
+            }
+        }
+        Image { width, height, rgb }
+    }
+}
+
+/// How the picture is scaled up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Filter {
+    /// One pixel of the picture per CHIP-8 pixel.
+    #[default]
+    None,
+    /// The Scale2x pixel art filter: twice as large, with
+    /// the edges of diagonal lines smoothed.
+    Scale2x,
+    /// Like Scale2x, three times as large.
+    Scale3x,
+}
+
+impl Filter {
+    /// How many times larger the filter makes the picture.
+    pub fn factor(self) -> u32 {
+        match self {
+            Filter::None => 1,
+            Filter::Scale2x => 2,
+            Filter::Scale3x => 3,
+        }
+    }
+}
+
+/// Error returned when a filter name is not known.
+#[derive(Debug)]
+pub struct UnknownFilter(String);
+
+impl fmt::Display for UnknownFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown filter '{}', expected one of: none, scale2x, scale3x", self.0)
+    }
+}
+
+impl FromStr for Filter {
+    type Err = UnknownFilter;
+
+    fn from_str(s: &str) -> Result<Filter, UnknownFilter> {
+        match s {
+            "none" => Ok(Filter::None),
+            "scale2x" => Ok(Filter::Scale2x),
+            "scale3x" => Ok(Filter::Scale3x),
+            _ => Err(UnknownFilter(s.to_string())),
+        }
+    }
+}
+
+/// The pixel at (x, y) of a `width` x `height` picture, with
+/// coordinates clamped to the edges.
+fn at<T: Copy>(src: &[T], width: u32, height: u32, x: i64, y: i64) -> T {
+    let x = x.max(0).min(i64::from(width) - 1);
+    let y = y.max(0).min(i64::from(height) - 1);
+    src[(y * i64::from(width) + x) as usize]
+}
+
+/// Scale a picture up twice with the Scale2x algorithm
+/// (<https://www.scale2x.it/algorithm>).
+pub fn scale2x<T: Copy + PartialEq>(src: &[T], width: u32, height: u32) -> Vec<T> {
+    let mut dst = Vec::with_capacity(src.len() * 4);
+    for y in 0..i64::from(height) {
+        let mut rows = (Vec::new(), Vec::new());
+        for x in 0..i64::from(width) {
+            let p = |dx, dy| at(src, width, height, x + dx, y + dy);
+            let (e, b, d, f, h) = (p(0, 0), p(0, -1), p(-1, 0), p(1, 0), p(0, 1));
+            if b != h && d != f {
+                rows.0.push(if d == b { d } else { e });
+                rows.0.push(if b == f { f } else { e });
+                rows.1.push(if d == h { d } else { e });
+                rows.1.push(if h == f { f } else { e });
+            } else {
+                rows.0.extend_from_slice(&[e, e]);
+                rows.1.extend_from_slice(&[e, e]);
+            }
+        }
+        dst.extend(rows.0);
+        dst.extend(rows.1);
+    }
+    dst
+}
+
+/// Scale a picture up three times with the Scale3x algorithm.
+pub fn scale3x<T: Copy + PartialEq>(src: &[T], width: u32, height: u32) -> Vec<T> {
+    let mut dst = Vec::with_capacity(src.len() * 9);
+    for y in 0..i64::from(height) {
+        let mut rows = (Vec::new(), Vec::new(), Vec::new());
+        for x in 0..i64::from(width) {
+            let p = |dx, dy| at(src, width, height, x + dx, y + dy);
+            let (a, b, c) = (p(-1, -1), p(0, -1), p(1, -1));
+            let (d, e, f) = (p(-1, 0), p(0, 0), p(1, 0));
+            let (g, h, i) = (p(-1, 1), p(0, 1), p(1, 1));
+            if b != h && d != f {
+                rows.0.push(if d == b { d } else { e });
+                rows.0.push(if (d == b && e != c) || (b == f && e != a) { b } else { e });
+                rows.0.push(if b == f { f } else { e });
+                rows.1.push(if (d == b && e != g) || (d == h && e != a) { d } else { e });
+                rows.1.push(e);
+                rows.1.push(if (b == f && e != i) || (h == f && e != c) { f } else { e });
+                rows.2.push(if d == h { d } else { e });
+                rows.2.push(if (d == h && e != i) || (h == f && e != g) { h } else { e });
+                rows.2.push(if h == f { f } else { e });
+            } else {
+                rows.0.extend_from_slice(&[e, e, e]);
+                rows.1.extend_from_slice(&[e, e, e]);
+                rows.2.extend_from_slice(&[e, e, e]);
+            }
+        }
+        dst.extend(rows.0);
+        dst.extend(rows.1);
+        dst.extend(rows.2);
+    }
+    dst
+}
+
+pub struct Renderer {
+    palette: Palette,
+    filter: Filter,
+    /// The part of its brightness a pixel turned off keeps
+    /// from one frame to the next, from 0 (none, the pixel
+    /// goes dark at once) to 1.
+    persistence: f32,
+    /// The colour of each pixel at the end of the previous
+    /// frame, for the phosphor effect.
+    glow: Vec<[f32; 3]>,
+    /// The colour of each pixel in the last picture.
+    shown: Vec<[f32; 3]>,
+}
+
+impl Renderer {
+    /// A renderer with `persistence` percent of the brightness
+    /// of pixels turned off kept from one frame to the next;
+    /// 0 turns the phosphor effect off.
+    pub fn new(palette: Palette, filter: Filter, persistence: u32) -> Renderer {
+        Renderer {
+            palette,
+            filter,
+            persistence: persistence.min(100) as f32 / 100.0,
+            glow: Vec::new(),
+            shown: Vec::new(),
+        }
+    }
+
+    /// Return true if pictures change from frame to frame
+    /// even when the pixels do not, because of the phosphor
+    /// effect.
+    pub fn fades(&self) -> bool {
+        self.persistence > 0.0
+    }
+
+    /// Size of the pictures of a `width` x `height` display.
+    pub fn size(&self, width: u32, height: u32) -> (u32, u32) {
+        (width * self.filter.factor(), height * self.filter.factor())
+    }
+
+    /// The picture of a `width` x `height` display whose pixel
+    /// values, row by row, are `pixels`. With the phosphor
+    /// effect, pixels turned off since the previous frame
+    /// still glow.
+    pub fn render(&mut self, pixels: &[u8], width: u32, height: u32) -> Image {
+        let background = to_f32(self.palette.background());
+        if self.glow.len() != pixels.len() {
+            self.glow = vec![background; pixels.len()];
+        }
+        let k = self.persistence;
+        self.shown = pixels.iter().zip(&self.glow)
+            .map(|(&val, glow)| match val {
+                0 => blend(*glow, background, k),
+                _ => to_f32(self.palette.color(val)),
+            })
+            .collect();
+        let colors: Vec<Rgb> = self.shown.iter()
+            .map(|c| [c[0].round() as u8, c[1].round() as u8, c[2].round() as u8])
+            .collect();
+        let colors = match self.filter {
+            Filter::None => colors,
+            Filter::Scale2x => scale2x(&colors, width, height),
+            Filter::Scale3x => scale3x(&colors, width, height),
+        };
+        let (width, height) = self.size(width, height);
+        Image { width, height, rgb: colors.iter().flat_map(|c| c.iter().cloned()).collect() }
+    }
+
+    /// Move on to the next frame: the last picture rendered
+    /// is what pixels turned off fade from.
+    pub fn next_frame(&mut self) {
+        if self.shown.len() == self.glow.len() {
+            self.glow.copy_from_slice(&self.shown);
+        }
+    }
+}
+
+fn to_f32(c: Rgb) -> [f32; 3] {
+    [f32::from(c[0]), f32::from(c[1]), f32::from(c[2])]
+}
+
+/// `k` parts of colour `a` mixed with 1 - `k` parts of `b`.
+fn blend(a: [f32; 3], b: [f32; 3], k: f32) -> [f32; 3] {
+    [a[0] * k + b[0] * (1.0 - k), a[1] * k + b[1] * (1.0 - k), a[2] * k + b[2] * (1.0 - k)]
+}
+
+#[cfg(test)]
+#[path="./render_test.rs"]
+mod render_test;
@@ -0,0 +1,82 @@
+use super::*;
+
+#[test]
+fn test_scale2x() {
+    // The corner between two diagonal pixels gets filled...
+    let src = [0, 1, 0,
+               1, 0, 0,
+               0, 0, 0];
+    let dst = scale2x(&src, 3, 3);
+    assert_eq!(dst.len(), 36);
+    assert_eq!((&dst[0..2], &dst[6..8]), (&[0, 0][..], &[0, 1][..]));
+    // ...but a straight one does not.
+    let src = [1, 1,
+               0, 0];
+    assert_eq!(scale2x(&src, 2, 2), [1, 1, 1, 1,
+                                      1, 1, 1, 1,
+                                      0, 0, 0, 0,
+                                      0, 0, 0, 0]);
+}
+
+#[test]
+fn test_scale3x() {
+    let src = [0, 1, 0,
+               1, 1, 1,
+               0, 1, 0];
+    let dst = scale3x(&src, 3, 3);
+    assert_eq!(dst.len(), 81);
+    // The arms of the plus sign stay solid, and the inner
+    // corners between them are filled in.
+    assert_eq!(&dst[0..9], &[0, 0, 0, 1, 1, 1, 0, 0, 0]);
+    assert_eq!(&dst[18..27], &[0, 1, 1, 1, 1, 1, 1, 1, 0]);
+    assert!(dst[36..45].iter().all(|&p| p == 1));
+    assert!(scale3x(&[7; 6], 3, 2).iter().all(|&p| p == 7));
+}
+
+#[test]
+fn test_render() {
+    let palette: Palette = "000000,ff0000,00ff00".parse().unwrap();
+    let mut r = Renderer::new(palette, Filter::None, 0);
+    let img = r.render(&[0, 1, 2, 0], 2, 2);
+    assert_eq!((img.width, img.height), (2, 2));
+    assert_eq!(img.rgb, [0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0, 0]);
+
+    let mut r = Renderer::new(Palette::default(), Filter::Scale2x, 0);
+    assert_eq!(r.size(64, 32), (128, 64));
+    let img = r.render(&[1, 0], 2, 1);
+    assert_eq!((img.width, img.height), (4, 2));
+    assert_eq!(&img.rgb[0..6], &[250; 6]);
+    assert_eq!(&img.rgb[6..12], &[0; 6]);
+}
+
+#[test]
+fn test_phosphor() {
+    let palette: Palette = "000000,c8c8c8".parse().unwrap();
+    let mut r = Renderer::new(palette, Filter::None, 50);
+    assert!(r.fades());
+    assert_eq!(r.render(&[1], 1, 1).rgb, [200; 3]);
+    r.next_frame();
+    // Turned off, the pixel fades out over a few frames.
+    assert_eq!(r.render(&[0], 1, 1).rgb, [100; 3]);
+    // Rendering again in the same frame does not fade it more.
+    assert_eq!(r.render(&[0], 1, 1).rgb, [100; 3]);
+    r.next_frame();
+    assert_eq!(r.render(&[0], 1, 1).rgb, [50; 3]);
+    r.next_frame();
+    assert_eq!(r.render(&[1], 1, 1).rgb, [200; 3]);
+
+    let mut r = Renderer::new(Palette::default(), Filter::None, 0);
+    assert!(!r.fades());
+    r.render(&[1], 1, 1);
+    r.next_frame();
+    assert_eq!(r.render(&[0], 1, 1).rgb, [0; 3]);
+}
+
+#[test]
+fn test_image_scaled() {
+    let img = Image { width: 2, height: 1, rgb: vec![1, 2, 3, 4, 5, 6] };
+    let big = img.scaled(2);
+    assert_eq!((big.width, big.height), (4, 2));
+    assert_eq!(big.rgb, [1, 2, 3, 1, 2, 3, 4, 5, 6, 4, 5, 6,
+                         1, 2, 3, 1, 2, 3, 4, 5, 6, 4, 5, 6]);
+}
@@ -0,0 +1,132 @@
+// rom.rs
+
+// Where ROMs come from. A ROM name given on the command line
+// may be:
+//
+//   roms/PONG          a file
+//   -                  the standard input
+//   games.zip          the only ROM in a zip bundle
+//   games.zip:PONG     the ROM named PONG in a zip bundle
+//
+// A ROM in a bundle may be named by its full path in the
+// archive, or by its file name alone.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Seek};
+use std::path::Path;
+
+use zip::ZipArchive;
+use zip::result::ZipError;
+
+/// Directory of the ROMs which come with the emulator.
+pub const BUNDLED_ROMS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/roms");
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RomSource {
+    File(String),
+    Stdin,
+    /// A zip bundle and, optionally, the name of the ROM in it.
+    Zip(String, Option<String>),
+}
+
+impl RomSource {
+    /// The source named `name` on the command line.
+    pub fn parse(name: &str) -> RomSource {
+        if name == "-" {
+            return RomSource::Stdin;
+        }
+        if let Some(pos) = name.find(".zip:") {
+            let (path, entry) = name.split_at(pos + 4);
+            return RomSource::Zip(path.to_string(), Some(entry[1..].to_string()));
+        }
+        if name.to_lowercase().ends_with(".zip") {
+            return RomSource::Zip(name.to_string(), None);
+        }
+        RomSource::File(name.to_string())
+    }
+
+    /// Read the whole ROM.
+    pub fn read(&self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        match *self {
+            RomSource::File(ref path) => { File::open(path)?.read_to_end(&mut buf)?; },
+            RomSource::Stdin => { io::stdin().read_to_end(&mut buf)?; },
+            RomSource::Zip(ref path, ref entry) =>
+                buf = read_zip(File::open(path)?, entry.as_ref().map(|e| e.as_str()))?,
+        }
+        Ok(buf)
+    }
+
+    /// A name for the ROM: its file name, without the
+    /// directory. "stdin" for the standard input.
+    pub fn rom_name(&self) -> String {
+        let file_name = |p: &str| {
+            Path::new(p).file_name().map_or(p.to_string(), |n| n.to_string_lossy().into_owned())
+        };
+        match *self {
+            RomSource::File(ref path) | RomSource::Zip(ref path, None) => file_name(path),
+            RomSource::Zip(_, Some(ref entry)) => file_name(entry),
+            RomSource::Stdin => "stdin".to_string(),
+        }
+    }
+}
+
+fn zip_error(e: ZipError) -> io::Error {
+    match e {
+        ZipError::Io(e) => e,
+        ZipError::FileNotFound => io::Error::new(io::ErrorKind::NotFound, "no such ROM in the zip file"),
+        e => io::Error::new(io::ErrorKind::InvalidData, e.to_string()),
+    }
+}
+
+/// Read the ROM named `entry` from the zip bundle `r`, or
+/// the only ROM in it if `entry` is None.
+pub fn read_zip<R: Read + Seek>(r: R, entry: Option<&str>) -> io::Result<Vec<u8>> {
+    let mut archive = ZipArchive::new(r).map_err(zip_error)?;
+    let mut names = Vec::new();
+    for index in 0..archive.len() {
+        let f = archive.by_index(index).map_err(zip_error)?;
+        if !f.is_dir() {
+            names.push((index, f.name().to_string()));
+        }
+    }
+    let matches: Vec<usize> = names.iter()
+        .filter(|&(_, name)| match entry {
+            Some(e) => name == e || Path::new(name).file_name() == Some(e.as_ref()),
+            None => true,
+        })
+        .map(|&(index, _)| index)
+        .collect();
+    if matches.len() != 1 {
+        let list: Vec<&str> = names.iter().map(|(_, n)| n.as_str()).collect();
+        let msg = match (entry, matches.len()) {
+            (Some(e), 0) => format!("no ROM named '{}' in the zip file", e),
+            (Some(e), _) => format!("several ROMs named '{}' in the zip file", e),
+            (None, 0) => "no ROM in the zip file".to_string(),
+            (None, _) => format!("several ROMs in the zip file, pick one of: {}", list.join(", ")),
+        };
+        return Err(io::Error::new(io::ErrorKind::NotFound, msg));
+    }
+    let mut buf = Vec::new();
+    archive.by_index(matches[0]).map_err(zip_error)?.read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// The ROMs in directory `dir`, as (file name, size in
+/// bytes), sorted by name.
+pub fn list_roms<P: AsRef<Path>>(dir: P) -> io::Result<Vec<(String, u64)>> {
+    let mut roms = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if meta.is_file() {
+            roms.push((entry.file_name().to_string_lossy().into_owned(), meta.len()));
+        }
+    }
+    roms.sort();
+    Ok(roms)
+}
+
+#[cfg(test)]
+#[path="./rom_test.rs"]
+mod rom_test;
@@ -0,0 +1,64 @@
+use super::*;
+use std::io::{Cursor, Write};
+use zip::ZipWriter;
+use zip::write::FileOptions;
+
+/// A zip file holding the given (name, contents) pairs.
+fn make_zip(files: &[(&str, &[u8])]) -> Cursor<Vec<u8>> {
+    let mut w = ZipWriter::new(Cursor::new(Vec::new()));
+    for &(name, data) in files {
+        w.start_file(name, FileOptions::default()).unwrap();
+        w.write_all(data).unwrap();
+    }
+    let mut zip = w.finish().unwrap();
+    zip.set_position(0);
+    zip
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(RomSource::parse("roms/PONG"), RomSource::File("roms/PONG".to_string()));
+    assert_eq!(RomSource::parse("-"), RomSource::Stdin);
+    assert_eq!(RomSource::parse("a/games.ZIP"), RomSource::Zip("a/games.ZIP".to_string(), None));
+    assert_eq!(RomSource::parse("games.zip:x/PONG"),
+               RomSource::Zip("games.zip".to_string(), Some("x/PONG".to_string())));
+}
+
+#[test]
+fn test_rom_name() {
+    assert_eq!(RomSource::parse("roms/PONG").rom_name(), "PONG");
+    assert_eq!(RomSource::parse("-").rom_name(), "stdin");
+    assert_eq!(RomSource::parse("a/games.zip").rom_name(), "games.zip");
+    assert_eq!(RomSource::parse("games.zip:x/PONG").rom_name(), "PONG");
+}
+
+#[test]
+fn test_read_file() {
+    let src = RomSource::parse(&format!("{}/PONG", BUNDLED_ROMS_DIR));
+    assert_eq!(src.read().unwrap().len(), 246);
+}
+
+#[test]
+fn test_read_zip() {
+    let files: [(&str, &[u8]); 3] = [("games/", b""), ("games/PONG", b"\x12\x00"), ("BRIX", b"\x00\xe0")];
+    assert_eq!(read_zip(make_zip(&files), Some("games/PONG")).unwrap(), b"\x12\x00");
+    assert_eq!(read_zip(make_zip(&files), Some("PONG")).unwrap(), b"\x12\x00");
+    assert_eq!(read_zip(make_zip(&files), Some("BRIX")).unwrap(), b"\x00\xe0");
+
+    let err = read_zip(make_zip(&files), None).unwrap_err();
+    assert_eq!(err.to_string(), "several ROMs in the zip file, pick one of: games/PONG, BRIX");
+    let err = read_zip(make_zip(&files), Some("TANK")).unwrap_err();
+    assert_eq!(err.to_string(), "no ROM named 'TANK' in the zip file");
+
+    // The directory does not count.
+    assert_eq!(read_zip(make_zip(&files[..2]), None).unwrap(), b"\x12\x00");
+    assert!(read_zip(Cursor::new(b"not a zip".to_vec()), None).is_err());
+}
+
+#[test]
+fn test_list_roms() {
+    let roms = list_roms(BUNDLED_ROMS_DIR).unwrap();
+    assert_eq!(roms.len(), 22);
+    assert_eq!(roms[0], ("15PUZZLE".to_string(), 384));
+    assert!(roms.contains(&("PONG".to_string(), 246)));
+}
@@ -0,0 +1,94 @@
+// scheduler.rs
+
+// Runs the CPU in 60 Hz frames. Each frame starts with a
+// vertical blank, which decrements the timers, then executes a
+// fixed number of instructions and waits for the frame's time
+// slot to end. Frame deadlines are computed from the start
+// time, so rounding errors do not add up.
+
+use std::time::Duration;
+
+use clock::Clock;
+use cpu::CPU;
+
+/// Frames per second. The delay and sound timers tick at
+/// this rate.
+pub const FRAME_RATE: u64 = 60;
+
+/// Instructions executed per frame, unless configured
+/// otherwise. About 900 instructions per second, close to
+/// the speed of the original interpreter.
+pub const DEFAULT_INSNS_PER_FRAME: u32 = 15;
+
+/// If the host falls behind by more than this many frames,
+/// for example while stopped in the debugger, the missed
+/// frames are dropped instead of being run as fast as
+/// possible to catch up.
+const MAX_LAG_FRAMES: u64 = 6;
+
+pub struct Scheduler<C: Clock> {
+    clock: C,
+    insns_per_frame: u32,
+    /// Time at which frame number 0 started.
+    start: Duration,
+    /// Number of frames run since "start".
+    frames: u64,
+}
+
+/// Time from the start of frame 0 to the start of frame "n".
+fn frame_time(n: u64) -> Duration {
+    let nanos = n * 1_000_000_000 / FRAME_RATE;
+    Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32)
+}
+
+impl<C: Clock> Scheduler<C> {
+    pub fn new(clock: C, insns_per_frame: u32) -> Scheduler<C> {
+        let start = clock.now();
+        Scheduler { clock, insns_per_frame, start, frames: 0 }
+    }
+
+    pub fn set_insns_per_frame(&mut self, insns_per_frame: u32) {
+        self.insns_per_frame = insns_per_frame;
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    pub fn clock_mut(&mut self) -> &mut C {
+        &mut self.clock
+    }
+
+    /// Run one frame. Instructions are executed by calling
+    /// "exec", which returns false to stop the emulator.
+    /// Return false if it did, or if the program exited;
+    /// the rest of the frame is then skipped.
+    pub fn run_frame<F>(&mut self, cpu: &mut CPU, mut exec: F) -> bool
+        where F: FnMut(&mut CPU) -> bool {
+        cpu.vblank();
+        for _ in 0..self.insns_per_frame {
+            if !exec(cpu) || cpu.exited() {
+                return false;
+            }
+        }
+        self.wait_for_next_frame();
+        true
+    }
+
+    /// Sleep until the next frame is due.
+    fn wait_for_next_frame(&mut self) {
+        self.frames += 1;
+        let deadline = self.start + frame_time(self.frames);
+        let now = self.clock.now();
+        if now < deadline {
+            self.clock.sleep(deadline - now);
+        } else if now - deadline > frame_time(MAX_LAG_FRAMES) {
+            self.start = now;
+            self.frames = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+#[path="./scheduler_test.rs"]
+mod scheduler_test;
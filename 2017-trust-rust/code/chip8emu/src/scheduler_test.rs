@@ -0,0 +1,131 @@
+use super::*;
+use asm;
+use clock::VirtualClock;
+use cpu::PC_START;
+use quirks::Quirks;
+
+/// A CPU running the program "src".
+fn cpu_with_program(src: &str) -> CPU {
+    let mut c = CPU::headless();
+    let mut st = c.save_state();
+    let rom = asm::assemble(src, PC_START).unwrap();
+    st.mem[PC_START..PC_START + rom.len()].copy_from_slice(&rom);
+    c.load_state(&st).unwrap();
+    c
+}
+
+fn run_insn(c: &mut CPU) -> bool {
+    c.execute_insn().is_ok()
+}
+
+#[test]
+fn test_frame_time() {
+    assert_eq!(frame_time(0), Duration::from_secs(0));
+    assert_eq!(frame_time(1), Duration::new(0, 16_666_666));
+    assert_eq!(frame_time(60), Duration::from_secs(1));
+    assert_eq!(frame_time(61), Duration::new(1, 16_666_666));
+}
+
+#[test]
+fn test_timers_tick_at_60hz() {
+    let mut c = cpu_with_program(": loop\njump loop");
+    let mut st = c.save_state();
+    st.delay = 60;
+    st.sound = 30;
+    c.load_state(&st).unwrap();
+
+    let mut s = Scheduler::new(VirtualClock::new(), 10);
+    for _ in 0..30 {
+        assert!(s.run_frame(&mut c, run_insn));
+    }
+    assert_eq!((c.delay(), c.sound()), (30, 0));
+    assert_eq!(s.clock().now(), Duration::new(0, 500_000_000));
+    for _ in 0..30 {
+        s.run_frame(&mut c, run_insn);
+    }
+    assert_eq!(c.delay(), 0);
+    assert_eq!(s.clock().now(), Duration::from_secs(1));
+}
+
+#[test]
+fn test_insns_per_frame() {
+    let mut c = CPU::headless();
+    let mut count = 0;
+    let mut s = Scheduler::new(VirtualClock::new(), 7);
+    for _ in 0..3 {
+        s.run_frame(&mut c, |_| { count += 1; true });
+    }
+    assert_eq!(count, 21);
+}
+
+#[test]
+fn test_stop() {
+    let mut c = CPU::headless();
+    let mut count = 0;
+    let mut s = Scheduler::new(VirtualClock::new(), 10);
+    assert!(!s.run_frame(&mut c, |_| { count += 1; count < 3 }));
+    assert_eq!(count, 3);
+    // The rest of the frame is skipped.
+    assert_eq!(s.clock().now(), Duration::from_secs(0));
+}
+
+#[test]
+fn test_exit() {
+    let mut c = cpu_with_program("v0 := 1\nexit");
+    let mut s = Scheduler::new(VirtualClock::new(), 10);
+    assert!(!s.run_frame(&mut c, run_insn));
+    assert!(c.exited());
+}
+
+#[test]
+fn test_slow_host() {
+    let mut c = CPU::headless();
+    let mut s = Scheduler::new(VirtualClock::new(), 1);
+
+    // Half a frame late: the next frame starts on time.
+    s.clock_mut().advance(Duration::new(0, 8_000_000));
+    s.run_frame(&mut c, |_| true);
+    assert_eq!(s.clock().now(), frame_time(1));
+
+    // Two frames late: no sleep until the frames catch up.
+    s.clock_mut().advance(frame_time(3));
+    s.run_frame(&mut c, |_| true);
+    assert_eq!(s.clock().now(), frame_time(4));
+    s.run_frame(&mut c, |_| true);
+    assert_eq!(s.clock().now(), frame_time(4));
+    s.run_frame(&mut c, |_| true);
+    assert_eq!(s.clock().now(), frame_time(4));
+
+    // Ten seconds late: the missed frames are dropped.
+    s.clock_mut().advance(Duration::from_secs(10));
+    let late = s.clock().now();
+    s.run_frame(&mut c, |_| true);
+    assert_eq!(s.clock().now(), late);
+    s.run_frame(&mut c, |_| true);
+    assert_eq!(s.clock().now(), late + frame_time(1));
+}
+
+#[test]
+fn test_display_wait() {
+    let src = "
+        sprite v0 v0 1
+        sprite v0 v0 1
+        : loop
+        jump loop
+    ";
+    let mut c = cpu_with_program(src);
+    c.set_quirks(Quirks { display_wait: true, ..Quirks::default() });
+    let mut s = Scheduler::new(VirtualClock::new(), 10);
+
+    // Only the first sprite is drawn in the first frame.
+    s.run_frame(&mut c, run_insn);
+    assert_eq!(c.pc(), PC_START + 2);
+    s.run_frame(&mut c, run_insn);
+    assert_eq!(c.pc(), PC_START + 4);
+
+    // Without the quirk, both are drawn at once.
+    let mut c = cpu_with_program(src);
+    let mut s = Scheduler::new(VirtualClock::new(), 10);
+    s.run_frame(&mut c, run_insn);
+    assert_eq!(c.pc(), PC_START + 4);
+}
@@ -1,75 +1,50 @@
 // screen.rs
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
 use sdl2;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
+use sdl2::pixels::PixelFormatEnum;
 //use sdl2::event::Event;
 //use sdl2::keyboard::Keycode;
 use sdl2::EventPump;
 use sdl2::render::Canvas;
 use sdl2::video::Window;
 use sdl2::keyboard::Keycode;
-use sdl2::event::Event;
+use sdl2::event::{Event, WindowEvent};
 
-/// Default screen height in pixels
-pub const SCREEN_HEIGHT:u16 = 32;
-
-/// Default screen width in pixels
-pub const SCREEN_WIDTH:u16 = 64;
+use display::{Display, KeyState, SCREEN_WIDTH, SCREEN_HEIGHT};
+use frontend::{Controls, Hotkey};
+use keymap::Keymap;
+use render::Renderer;
+use speaker::Speaker;
 
 pub const DEFAULT_SCALE_FACTOR: u32 = 5;
 
 static WINDOW_TITLE: &'static str = "CHIP-8 Demo!";
 
-/// CHIP-8 uses keys from 0,1,...9 and a, b, ... f.
-/// These are assigned codes from 0, 1, ... 0xf.
-lazy_static! {
-    static ref KEYCODES:HashMap<u8, Keycode> = hashmap! {
-         0x0 => Keycode::Num0,
-         0x1 => Keycode::Num1,
-         0x2 => Keycode::Num2,
-         0x3 => Keycode::Num3,
-         0x4 => Keycode::Num4,
-         0x5 => Keycode::Num5,
-         0x6 => Keycode::Num6,
-         0x7 => Keycode::Num7,
-         0x8 => Keycode::Num8,
-         0x9 => Keycode::Num9,
-         0xa => Keycode::A,
-         0xb => Keycode::B,
-         0xc => Keycode::C,
-         0xd => Keycode::D,
-         0xe => Keycode::E,
-         0xf => Keycode::F,
-    };
-}
-
-/// CHIP-8 uses only two colors: 0 for OFF and
-/// 1 for ON.
-lazy_static! {
-    pub static ref PIXEL_COLORS:[Color; 2] = 
-        [Color::RGBA(0, 0, 0, 255), 
-         Color::RGBA(250, 250, 250, 255)];
-}
-
 pub struct Screen {
-    scale_factor: u32,
-    pub canvas: Canvas<Window>,
-    pub events: EventPump,
+    sdl: sdl2::Sdl,
+    /// The canvas has a logical size equal to the size of
+    /// the picture made by the renderer; SDL scales it up to
+    /// fill the window.
+    canvas: Canvas<Window>,
+    /// Turns the pixels into the picture shown.
+    renderer: Renderer,
+    /// Width of the CHIP-8 screen in pixels.
+    width: u32,
+    /// Height of the CHIP-8 screen in pixels.
+    height: u32,
     /// `mem' is a representation of the display within the
     /// virtual machine. If mem[i] is 1, the corresponding 
     /// pixel on the real screen is ON, otherwise OFF.
     ///  
-    /// Had to use this because rust-sdl2 
-    /// does not seem to provide an easy way to get the
-    /// color of a pixel.
-    mem: [u8; (SCREEN_WIDTH * SCREEN_HEIGHT) as usize],
+    /// The picture in the window is rendered from it
+    /// whenever the CHIP-8 program draws.
+    mem: Vec<u8>,
 }
 
 impl Screen {
-    pub fn new(width: u32, height: u32, scale_factor: u32) -> Screen {
+    pub fn new(width: u32, height: u32, scale_factor: u32, renderer: Renderer) -> Screen {
         let ctxt = sdl2::init().expect("SDL2 library initialization failed.");
         let video = ctxt.video().expect("Unable to get video subsystem.");
         let window = 
@@ -79,72 +54,142 @@ impl Screen {
             .build()
             .expect("Unable to get Window");
         
-        let mut canvas = window.into_canvas().build().expect("Unable to get canvas");
+        let canvas = window.into_canvas().build().expect("Unable to get canvas");
+
+        let mut screen = Screen{ 
+            sdl: ctxt, canvas, renderer,
+            width: 0,
+            height: 0,
+            mem: Vec::new(),
+        };
+        screen.set_resolution(u32::from(SCREEN_WIDTH), u32::from(SCREEN_HEIGHT));
+        screen.present();
+        screen
+    }
 
-        canvas.set_draw_color(PIXEL_COLORS[0]);
-        canvas.clear();
-        canvas.present();
-        
-        let events = ctxt.event_pump().expect("Unable to get event pump"); 
+    /// Get the keyboard of the window, with the key bindings
+    /// of `keymap`. SDL allows only one event pump, so this
+    /// should be called only once.
+    pub fn keyboard(&self, keymap: &Keymap) -> Keyboard {
+        let events = self.sdl.event_pump().expect("Unable to get event pump");
+        let mut bindings = HashMap::new();
+        for (name, &key) in keymap.bindings() {
+            match Keycode::from_name(name) {
+                Some(k) => { bindings.insert(k, key); },
+                None => eprintln!("warning: unknown key '{}' in key bindings", name),
+            }
+        }
+        Keyboard { events, bindings, keys: KeyState::new(), hotkeys: VecDeque::new() }
+    }
 
-        Screen{ 
-            scale_factor: scale_factor,
-            canvas: canvas, events: events,
-            mem: [0; (SCREEN_WIDTH * SCREEN_HEIGHT) as usize],
+    /// Get a speaker playing a square wave of `frequency` Hz
+    /// at `volume` percent of the maximum.
+    pub fn speaker(&self, frequency: u32, volume: u32) -> Result<Speaker, String> {
+        Speaker::new(&self.sdl, frequency, volume)
+    }
+}
+
+impl Display for Screen {
+    fn clear(&mut self) {
+        for p in self.mem.iter_mut() {
+            *p = 0;
         }
     }
 
-    pub fn draw_pixel(&mut self, x: u32, y: u32, color:Color) {
-        self.canvas.set_draw_color(color);
-        self.canvas.fill_rect(
-            Rect::new(
-                x as i32 * self.scale_factor as i32, 
-                y as i32 * self.scale_factor as i32,
-                self.scale_factor, self.scale_factor)).expect("Error in draw_point");
-        let c = if color == PIXEL_COLORS[0] { 0 } else { 1 };
-        self.mem[(y * u32::from(SCREEN_WIDTH) + x) as usize] = c;
+    fn set_resolution(&mut self, width: u32, height: u32) {
+        let (w, h) = self.renderer.size(width, height);
+        self.canvas.set_logical_size(w, h).expect("Unable to set logical size");
+        self.width = width;
+        self.height = height;
+        self.mem = vec![0; (width * height) as usize];
     }
 
-    /// Map an SDL Keycode to the numeric key value used
-    /// by CHIP-8.
-    fn keycode_to_keyval(k: Keycode) -> Option<u8> {
-        for (chip8_val, sdl_code) in KEYCODES.iter() {
-            if *sdl_code == k {
-                return Some(*chip8_val);
-            }
+    fn draw_pixel(&mut self, x: u32, y: u32, val: u8) {
+        self.mem[(y * self.width + x) as usize] = val;
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> u8 {
+        self.mem[(y * self.width + x) as usize]
+    }
+
+    fn present(&mut self) {
+        let img = self.renderer.render(&self.mem, self.width, self.height);
+        let textures = self.canvas.texture_creator();
+        let mut texture = textures
+            .create_texture_streaming(PixelFormatEnum::RGB24, img.width, img.height)
+            .expect("Unable to create texture");
+        texture.update(None, &img.rgb, (img.width * 3) as usize).expect("Unable to update texture");
+        self.canvas.copy(&texture, None, None).expect("Unable to copy texture");
+        self.canvas.present();
+    }
+
+    fn vblank(&mut self) {
+        self.renderer.next_frame();
+        if self.renderer.fades() {
+            self.present();
         }
-        None
     }
+}
 
-    /// Do a blocking read from the keyboard. Return the
-    /// associated CHIP-8 key value if a keypress is 
-    /// detected and the pressed key is valid.
-    pub fn read_key_blocking(&mut self) -> Option<u8> {
-        loop {
-            let e = self.events.wait_event();
-            if let Event::KeyDown { keycode: Some(k), ..} = e {
-                break Screen::keycode_to_keyval(k);
-            }
+/// The keyboard of the SDL window, used as the CHIP-8 keypad.
+pub struct Keyboard {
+    events: EventPump,
+    /// The CHIP-8 key bound to each host key.
+    bindings: HashMap<Keycode, u8>,
+    /// The CHIP-8 keys held down.
+    keys: KeyState,
+    /// Hotkeys pressed but not read yet.
+    hotkeys: VecDeque<Hotkey>,
+}
+
+impl Keyboard {
+    /// Map an SDL Keycode to a Hotkey.
+    fn keycode_to_hotkey(k: Keycode) -> Option<Hotkey> {
+        match k {
+            Keycode::F5 => Some(Hotkey::SaveState),
+            Keycode::F9 => Some(Hotkey::LoadState),
+            Keycode::Backspace => Some(Hotkey::Rewind),
+            Keycode::F12 => Some(Hotkey::Screenshot),
+            _ => None,
         }
     }
 
-    /// Do a non-blocking read from the keyboard. Return
-    /// the associated CHIP-8 key value if a keypress is
-    /// detected and the pressed key is valid.
-    pub fn read_key_noblocking(&mut self) -> Option<u8> {
-        if let Some(e) = self.events.poll_event() {
-            match e {
-                Event::KeyDown { keycode: Some(k), ..} => {
-                        Screen::keycode_to_keyval(k)
-                },
-                _ => None,
-            }
-        } else {
-            None
+    /// Update the pressed keys, or queue up the hotkey
+    /// pressed, from an SDL event.
+    fn handle_event(&mut self, e: Event) {
+        match e {
+            Event::KeyDown { keycode: Some(k), repeat, .. } => {
+                if let Some(h) = Keyboard::keycode_to_hotkey(k) {
+                    if !repeat {
+                        self.hotkeys.push_back(h);
+                    }
+                } else if let Some(&v) = self.bindings.get(&k) {
+                    self.keys.press(v);
+                }
+            },
+            Event::KeyUp { keycode: Some(k), .. } => {
+                if let Some(&v) = self.bindings.get(&k) {
+                    self.keys.release(v);
+                }
+            },
+            Event::Window { win_event: WindowEvent::FocusLost, .. } => {
+                self.keys.release_all();
+            },
+            Event::Quit { .. } => self.hotkeys.push_back(Hotkey::Quit),
+            _ => {},
         }
     }
+}
 
-    pub fn get_pixel(&mut self, x: u32, y: u32) -> u8 {
-        self.mem[(y * u32::from(SCREEN_WIDTH) + x) as usize]
+impl Controls for Keyboard {
+    fn poll_hotkey(&mut self) -> Option<Hotkey> {
+        while let Some(e) = self.events.poll_event() {
+            self.handle_event(e);
+        }
+        self.hotkeys.pop_front()
     }
- }
+
+    fn keys(&self) -> KeyState {
+        self.keys
+    }
+}
@@ -0,0 +1,98 @@
+// speaker.rs
+
+// The buzzer, played through SDL audio.
+
+use sdl2;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+
+use audio::{Audio, Waveform, PATTERN_SIZE};
+
+/// Samples per second asked of the sound card.
+const SAMPLE_RATE: i32 = 44100;
+
+/// Generates the samples. It runs in the SDL audio thread,
+/// and is only touched from the main thread with the device
+/// locked.
+struct Wave {
+    waveform: Waveform,
+    /// Frequency of the square wave, in Hz.
+    frequency: f32,
+    /// Amplitude, from 0 to 1.
+    volume: f32,
+    /// Samples per second produced by the sound card.
+    sample_rate: f32,
+    /// Position within the current period: from 0 to 1 for
+    /// the square wave, from 0 to the number of bits in the
+    /// pattern for an audio pattern.
+    phase: f32,
+}
+
+impl AudioCallback for Wave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            let high = match self.waveform {
+                Waveform::Square => {
+                    self.phase = (self.phase + self.frequency / self.sample_rate) % 1.0;
+                    self.phase < 0.5
+                },
+                Waveform::Pattern(ref pattern, rate) => {
+                    let bits = (PATTERN_SIZE * 8) as f32;
+                    self.phase = (self.phase + rate as f32 / self.sample_rate) % bits;
+                    let bit = self.phase as usize;
+                    (pattern[bit / 8] >> (7 - bit % 8)) & 1 == 1
+                },
+            };
+            *x = if high { self.volume } else { -self.volume };
+        }
+    }
+}
+
+pub struct Speaker {
+    device: AudioDevice<Wave>,
+    playing: bool,
+}
+
+impl Speaker {
+    /// Open the default audio device. The square wave has a
+    /// frequency of `frequency` Hz; `volume` is a percentage
+    /// of the maximum.
+    pub fn new(sdl: &sdl2::Sdl, frequency: u32, volume: u32) -> Result<Speaker, String> {
+        let spec = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: None,
+        };
+        let device = sdl.audio()?.open_playback(None, &spec, |spec| Wave {
+            waveform: Waveform::Square,
+            frequency: frequency as f32,
+            volume: volume.min(100) as f32 / 100.0,
+            sample_rate: spec.freq as f32,
+            phase: 0.0,
+        })?;
+        Ok(Speaker { device, playing: false })
+    }
+}
+
+impl Audio for Speaker {
+    fn set_playing(&mut self, playing: bool) {
+        if playing == self.playing {
+            return;
+        }
+        if playing {
+            self.device.resume();
+        } else {
+            self.device.pause();
+        }
+        self.playing = playing;
+    }
+
+    fn set_waveform(&mut self, waveform: Waveform) {
+        let mut wave = self.device.lock();
+        if wave.waveform != waveform {
+            wave.waveform = waveform;
+            wave.phase = 0.0;
+        }
+    }
+}
@@ -0,0 +1,176 @@
+// state.rs
+
+// Save states: a snapshot of the whole machine which can be
+// written to a file and loaded back later.
+
+use std::collections::VecDeque;
+use std::io;
+use std::io::prelude::*;
+
+/// Every save state file starts with these bytes.
+const MAGIC: &[u8; 4] = b"C8ST";
+
+/// Version of the save state file format. Increment this
+/// whenever the format changes; files with another version
+/// are rejected.
+pub const STATE_VERSION: u8 = 3;
+
+/// A snapshot of the CPU and the display.
+#[derive(Clone, Debug, PartialEq)]
+pub struct State {
+    pub mem: Vec<u8>,
+    pub v: Vec<u8>,
+    pub i: usize,
+    pub pc: usize,
+    pub sp: usize,
+    pub delay: u8,
+    pub sound: u8,
+    /// The XO-CHIP audio pattern, empty if none was loaded.
+    pub pattern: Vec<u8>,
+    /// The XO-CHIP pitch register.
+    pub pitch: u8,
+    pub hires: bool,
+    /// The XO-CHIP bit planes selected, as a mask.
+    pub planes: u8,
+    pub exited: bool,
+    pub rpl: Vec<u8>,
+    /// Width of the display in pixels.
+    pub width: u32,
+    /// Height of the display in pixels.
+    pub height: u32,
+    /// The value of each pixel, row by row: a bit per plane,
+    /// so 0 or 1 unless the program used XO-CHIP planes.
+    pub pixels: Vec<u8>,
+}
+
+pub fn write_u16<W: Write>(w: &mut W, val: usize) -> io::Result<()> {
+    w.write_all(&[(val >> 8) as u8, val as u8])
+}
+
+pub fn read_u16<R: Read>(r: &mut R) -> io::Result<usize> {
+    let mut buf = [0; 2];
+    r.read_exact(&mut buf)?;
+    Ok((usize::from(buf[0]) << 8) | usize::from(buf[1]))
+}
+
+pub fn write_u32<W: Write>(w: &mut W, val: usize) -> io::Result<()> {
+    write_u16(w, (val >> 16) & 0xffff)?;
+    write_u16(w, val & 0xffff)
+}
+
+pub fn read_u32<R: Read>(r: &mut R) -> io::Result<usize> {
+    Ok((read_u16(r)? << 16) | read_u16(r)?)
+}
+
+pub fn read_u8<R: Read>(r: &mut R) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+/// Write a byte array preceded by its length.
+fn write_bytes<W: Write>(w: &mut W, bytes: &[u8]) -> io::Result<()> {
+    write_u32(w, bytes.len())?;
+    w.write_all(bytes)
+}
+
+/// Read `len` bytes. Memory is only allocated for the bytes
+/// actually there, so a corrupt length does not make it
+/// allocate gigabytes.
+fn read_len<R: Read>(r: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    r.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() != len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated save state"));
+    }
+    Ok(buf)
+}
+
+/// Read a byte array written by `write_bytes`.
+fn read_bytes<R: Read>(r: &mut R) -> io::Result<Vec<u8>> {
+    let len = read_u32(r)?;
+    read_len(r, len)
+}
+
+pub fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl State {
+    /// Write the state in the save state file format. All
+    /// multi byte values are stored in big-endian format.
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&[STATE_VERSION])?;
+        write_bytes(w, &self.mem)?;
+        write_bytes(w, &self.v)?;
+        write_u16(w, self.i)?;
+        write_u16(w, self.pc)?;
+        write_u16(w, self.sp)?;
+        w.write_all(&[self.delay, self.sound, self.hires as u8, self.exited as u8])?;
+        write_bytes(w, &self.pattern)?;
+        w.write_all(&[self.pitch, self.planes])?;
+        write_bytes(w, &self.rpl)?;
+        write_u16(w, self.width as usize)?;
+        write_u16(w, self.height as usize)?;
+        w.write_all(&self.pixels)
+    }
+
+    /// Read a state written by `State::write`.
+    pub fn read<R: Read>(r: &mut R) -> io::Result<State> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a CHIP-8 save state"));
+        }
+        if read_u8(r)? != STATE_VERSION {
+            return Err(invalid_data("unsupported save state version"));
+        }
+        let mem = read_bytes(r)?;
+        let v = read_bytes(r)?;
+        let (i, pc, sp) = (read_u16(r)?, read_u16(r)?, read_u16(r)?);
+        let (delay, sound) = (read_u8(r)?, read_u8(r)?);
+        let (hires, exited) = (read_u8(r)? != 0, read_u8(r)? != 0);
+        let pattern = read_bytes(r)?;
+        let (pitch, planes) = (read_u8(r)?, read_u8(r)?);
+        let rpl = read_bytes(r)?;
+        let width = read_u16(r)? as u32;
+        let height = read_u16(r)? as u32;
+        let pixels = read_len(r, width as usize * height as usize)?;
+        Ok(State { mem, v, i, pc, sp, delay, sound, pattern, pitch, hires, planes, exited,
+                   rpl, width, height, pixels })
+    }
+}
+
+/// A ring buffer of the most recent states, used to rewind
+/// the game. When it is full, pushing a state drops the
+/// oldest one.
+pub struct RewindBuffer {
+    states: VecDeque<State>,
+    capacity: usize,
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize) -> RewindBuffer {
+        RewindBuffer {
+            states: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, state: State) {
+        if self.states.len() == self.capacity {
+            self.states.pop_front();
+        }
+        self.states.push_back(state);
+    }
+
+    /// Remove and return the most recent state.
+    pub fn pop(&mut self) -> Option<State> {
+        self.states.pop_back()
+    }
+}
+
+#[cfg(test)]
+#[path="./state_test.rs"]
+mod state_test;
@@ -0,0 +1,81 @@
+use super::*;
+
+fn test_state() -> State {
+    State {
+        mem: (0..4096).map(|n| n as u8).collect(),
+        v: vec![7; 16],
+        i: 0x123,
+        pc: 0x456,
+        sp: 0xea2,
+        delay: 10,
+        sound: 20,
+        pattern: (0..16).collect(),
+        pitch: 70,
+        hires: false,
+        planes: 3,
+        exited: false,
+        rpl: vec![1, 2, 3, 4, 5, 6, 7, 8],
+        width: 64,
+        height: 32,
+        pixels: (0..64 * 32).map(|n| (n % 4) as u8).collect(),
+    }
+}
+
+#[test]
+fn test_write_read() {
+    let st = test_state();
+    let mut buf = Vec::new();
+    st.write(&mut buf).unwrap();
+    assert_eq!(&buf[..5], b"C8ST\x03");
+
+    let st2 = State::read(&mut &buf[..]).unwrap();
+    assert_eq!(st, st2);
+}
+
+#[test]
+fn test_read_bad_magic() {
+    let mut buf = Vec::new();
+    test_state().write(&mut buf).unwrap();
+    buf[0] = b'X';
+    assert!(State::read(&mut &buf[..]).is_err());
+}
+
+#[test]
+fn test_read_bad_version() {
+    let mut buf = Vec::new();
+    test_state().write(&mut buf).unwrap();
+    buf[4] = STATE_VERSION + 1;
+    assert!(State::read(&mut &buf[..]).is_err());
+}
+
+#[test]
+fn test_read_truncated() {
+    let mut buf = Vec::new();
+    test_state().write(&mut buf).unwrap();
+    buf.pop();
+    assert!(State::read(&mut &buf[..]).is_err());
+}
+
+#[test]
+fn test_read_bad_length() {
+    let mut buf = Vec::new();
+    test_state().write(&mut buf).unwrap();
+    // The length of the memory, right after the version.
+    buf[5..9].copy_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+    let e = State::read(&mut &buf[..]).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn test_rewind_buffer() {
+    let mut r = RewindBuffer::new(2);
+    for pc in 1..4 {
+        let mut st = test_state();
+        st.pc = pc;
+        r.push(st);
+    }
+    // The oldest state (pc == 1) was dropped.
+    assert_eq!(r.pop().unwrap().pc, 3);
+    assert_eq!(r.pop().unwrap().pc, 2);
+    assert!(r.pop().is_none());
+}
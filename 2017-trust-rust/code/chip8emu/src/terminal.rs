@@ -0,0 +1,111 @@
+// terminal.rs
+
+// The parts of the terminal frontend which do not need a
+// terminal: drawing a picture with text, and telling which
+// keys are held down from the key presses a terminal reports.
+
+use std::fmt::Write;
+
+use display::{Keypad, KeyState, NUM_KEYS};
+use palette::Rgb;
+use render::Image;
+
+/// Frames a key is held down for after it is pressed, when
+/// the terminal does not report key releases. It must be
+/// longer than the delay before the key repeats, or a key held
+/// down would be released in between.
+pub const INITIAL_HOLD_FRAMES: u32 = 30;
+
+/// Frames a key is held down for after it repeats. It must be
+/// longer than the time between two repeats.
+pub const REPEAT_HOLD_FRAMES: u32 = 5;
+
+fn pixel(img: &Image, x: u32, y: u32) -> Rgb {
+    let i = ((y * img.width + x) * 3) as usize;
+    [img.rgb[i], img.rgb[i + 1], img.rgb[i + 2]]
+}
+
+/// Draw `img` with ANSI escape sequences and Unicode half
+/// blocks: each character shows two pixels, one above the
+/// other, as an upper half block in the colour of the top
+/// pixel on a background of the colour of the bottom one.
+/// Lines end with "\r\n", as terminals in raw mode need.
+pub fn half_blocks(img: &Image) -> String {
+    let mut out = String::new();
+    for y in (0..img.height).step_by(2) {
+        let mut last = None;
+        for x in 0..img.width {
+            let top = pixel(img, x, y);
+            let bottom = if y + 1 < img.height { pixel(img, x, y + 1) } else { [0; 3] };
+            if last != Some((top, bottom)) {
+                let _ = write!(out, "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m",
+                               top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]);
+                last = Some((top, bottom));
+            }
+            out.push('\u{2580}');
+        }
+        out.push_str("\x1b[0m\r\n");
+    }
+    out
+}
+
+/// The keys of the keypad held down, from the key presses
+/// reported by a terminal. Most terminals report only key
+/// presses, and again while a key is held down; a key is then
+/// taken as released when it has not been reported for a few
+/// frames.
+pub struct KeyHold {
+    keys: KeyState,
+    /// Whether the terminal reports key releases.
+    reports_releases: bool,
+    /// Frames left before each key is released.
+    frames_left: [u32; NUM_KEYS],
+}
+
+impl KeyHold {
+    pub fn new(reports_releases: bool) -> KeyHold {
+        KeyHold { keys: KeyState::new(), reports_releases, frames_left: [0; NUM_KEYS] }
+    }
+
+    /// Key `key` was pressed, or repeated.
+    pub fn press(&mut self, key: u8) {
+        let k = usize::from(key) % NUM_KEYS;
+        self.frames_left[k] = if self.keys.is_pressed(key) {
+            REPEAT_HOLD_FRAMES
+        } else {
+            INITIAL_HOLD_FRAMES
+        };
+        self.keys.press(key);
+    }
+
+    /// Key `key` was released.
+    pub fn release(&mut self, key: u8) {
+        self.frames_left[usize::from(key) % NUM_KEYS] = 0;
+        self.keys.release(key);
+    }
+
+    /// Move on to the next frame, releasing the keys which
+    /// were not reported for long enough.
+    pub fn next_frame(&mut self) {
+        if self.reports_releases {
+            return;
+        }
+        for key in 0..NUM_KEYS {
+            if self.frames_left[key] > 0 {
+                self.frames_left[key] -= 1;
+                if self.frames_left[key] == 0 {
+                    self.keys.release(key as u8);
+                }
+            }
+        }
+    }
+
+    /// The keys held down.
+    pub fn keys(&self) -> KeyState {
+        self.keys
+    }
+}
+
+#[cfg(test)]
+#[path="./terminal_test.rs"]
+mod terminal_test;
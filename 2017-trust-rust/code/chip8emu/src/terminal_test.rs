@@ -0,0 +1,50 @@
+use super::*;
+
+#[test]
+fn test_half_blocks() {
+    // 2x3: the last line has a black bottom half.
+    let img = Image {
+        width: 2,
+        height: 3,
+        rgb: vec![1, 1, 1, 1, 1, 1,
+                  2, 2, 2, 3, 3, 3,
+                  4, 4, 4, 4, 4, 4],
+    };
+    assert_eq!(half_blocks(&img),
+               "\x1b[38;2;1;1;1m\x1b[48;2;2;2;2m\u{2580}\
+                \x1b[38;2;1;1;1m\x1b[48;2;3;3;3m\u{2580}\x1b[0m\r\n\
+                \x1b[38;2;4;4;4m\x1b[48;2;0;0;0m\u{2580}\u{2580}\x1b[0m\r\n");
+}
+
+#[test]
+fn test_hold_without_releases() {
+    let mut h = KeyHold::new(false);
+    h.press(5);
+    for _ in 1..INITIAL_HOLD_FRAMES {
+        h.next_frame();
+    }
+    assert!(h.keys().is_pressed(5));
+    // The key repeats, so it is still held down.
+    h.press(5);
+    for _ in 0..REPEAT_HOLD_FRAMES {
+        assert!(h.keys().is_pressed(5));
+        h.next_frame();
+    }
+    assert!(!h.keys().is_pressed(5));
+    // A release, if reported, counts at once.
+    h.press(6);
+    h.release(6);
+    assert_eq!(h.keys(), KeyState::new());
+}
+
+#[test]
+fn test_hold_with_releases() {
+    let mut h = KeyHold::new(true);
+    h.press(0xa);
+    for _ in 0..100 {
+        h.next_frame();
+    }
+    assert!(h.keys().is_pressed(0xa));
+    h.release(0xa);
+    assert!(!h.keys().is_pressed(0xa));
+}
@@ -0,0 +1,259 @@
+// trace.rs
+
+// Execution traces: one line per instruction executed, with
+// the state of the CPU before it, to compare the emulator with
+// the logs of other emulators. A line looks like
+//
+//   42 pc=0208 op=d015 v0=0a ... vf=00 i=0216 sp=ea0 dt=00 st=00 ; sprite v0 v1 5
+//
+// that is the cycle (the number of instructions executed
+// before), then fields written name=value in hex, then the
+// mnemonic after a ";". Lines starting with "#" are comments.
+// Two traces are compared field by field, for the cycles and
+// fields they both have, so a reference trace may leave some
+// out or have been filtered.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use cpu::CPU;
+use disasm;
+use insn;
+use profile;
+
+/// An inclusive range of addresses, like "0x200-0x2ff".
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AddrRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Error returned when a trace filter can not be parsed.
+#[derive(Debug, PartialEq)]
+pub struct BadFilter(String);
+
+impl fmt::Display for BadFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "bad trace filter: {}", self.0)
+    }
+}
+
+fn parse_hex(s: &str) -> Option<usize> {
+    let s = s.trim();
+    usize::from_str_radix(s.trim_start_matches("0x"), 16).ok()
+}
+
+impl FromStr for AddrRange {
+    type Err = BadFilter;
+
+    fn from_str(s: &str) -> Result<AddrRange, BadFilter> {
+        let bad = || BadFilter(format!("'{}', expected an address range like 0x200-0x2ff", s));
+        let mut parts = s.splitn(2, '-');
+        let start = parts.next().and_then(parse_hex).ok_or_else(bad)?;
+        let end = match parts.next() {
+            Some(end) => parse_hex(end).ok_or_else(bad)?,
+            None => start,
+        };
+        if end < start {
+            return Err(bad());
+        }
+        Ok(AddrRange { start, end })
+    }
+}
+
+/// Classes of opcodes, like "dxyn,8,00e0": either a pattern,
+/// as in the profiles, or a single hex digit, for all the
+/// opcodes starting with it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpClasses(BTreeSet<String>);
+
+impl OpClasses {
+    /// Return true if `opcode` is in one of the classes.
+    pub fn contains(&self, opcode: u16) -> bool {
+        self.0.contains(&format!("{:x}", opcode >> 12)) ||
+            insn::decode(opcode).is_ok_and(|insn| self.0.contains(profile::pattern(insn)))
+    }
+}
+
+impl FromStr for OpClasses {
+    type Err = BadFilter;
+
+    fn from_str(s: &str) -> Result<OpClasses, BadFilter> {
+        let patterns: BTreeSet<&str> = (0..=0xffff)
+            .filter_map(|opcode| insn::decode(opcode).ok())
+            .map(profile::pattern)
+            .collect();
+        let mut classes = BTreeSet::new();
+        for class in s.split(',').map(|c| c.trim().to_lowercase()) {
+            let digit = class.len() == 1 && class.chars().all(|c| c.is_ascii_hexdigit());
+            if !digit && !patterns.contains(class.as_str()) {
+                return Err(BadFilter(format!("unknown opcode class '{}', expected a hex digit or a pattern like dxyn", class)));
+            }
+            classes.insert(class);
+        }
+        Ok(OpClasses(classes))
+    }
+}
+
+/// Which instructions are written to a trace: those at an
+/// address in `range` and in one of the classes `ops`, or all
+/// of them for a filter which is None.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TraceFilter {
+    pub range: Option<AddrRange>,
+    pub ops: Option<OpClasses>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, pc: usize, opcode: u16) -> bool {
+        self.range.is_none_or(|r| r.start <= pc && pc <= r.end) &&
+            self.ops.as_ref().is_none_or(|ops| ops.contains(opcode))
+    }
+}
+
+/// Writes the trace of a run to `out`.
+pub struct Tracer<W: Write> {
+    out: W,
+    filter: TraceFilter,
+    /// Number of instructions executed so far.
+    cycle: u64,
+}
+
+impl<W: Write> Tracer<W> {
+    /// A tracer writing the instructions which pass `filter`
+    /// to `out`, starting with a comment naming the fields.
+    pub fn new(mut out: W, filter: TraceFilter) -> io::Result<Tracer<W>> {
+        writeln!(out, "# cycle pc op v0-vf i sp dt st ; mnemonic")?;
+        Ok(Tracer { out, filter, cycle: 0 })
+    }
+
+    /// Trace the instruction `cpu` is about to execute.
+    pub fn record(&mut self, cpu: &CPU) -> io::Result<()> {
+        let (pc, mem) = (cpu.pc(), cpu.mem());
+        let opcode = (u16::from(mem[pc % mem.len()]) << 8) | u16::from(mem[(pc + 1) % mem.len()]);
+        self.cycle += 1;
+        if !self.filter.matches(pc, opcode) {
+            return Ok(());
+        }
+        write!(self.out, "{} pc={:04x} op={:04x}", self.cycle - 1, pc, opcode)?;
+        for (r, v) in cpu.v().iter().enumerate() {
+            write!(self.out, " v{:x}={:02x}", r, v)?;
+        }
+        writeln!(self.out, " i={:04x} sp={:03x} dt={:02x} st={:02x} ; {}", cpu.i(), cpu.sp(),
+                 cpu.delay(), cpu.sound(), disasm::disassemble_at(mem, pc))
+    }
+
+    /// Write out what is buffered.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// A line of a trace.
+#[derive(Debug, PartialEq)]
+pub struct Record<'a> {
+    /// Number of the line in its trace, from 1.
+    pub line: usize,
+    pub cycle: u64,
+    /// The fields, as (name, value), without the mnemonic.
+    pub fields: Vec<(&'a str, &'a str)>,
+    /// The whole line.
+    pub text: &'a str,
+}
+
+/// Parse the lines of `trace` which are not comments or blank.
+pub fn parse(trace: &str) -> Result<Vec<Record<'_>>, String> {
+    let mut records = Vec::new();
+    for (n, text) in trace.lines().enumerate() {
+        let fields = text.split(';').next().unwrap_or("").trim();
+        if fields.is_empty() || fields.starts_with('#') {
+            continue;
+        }
+        let mut words = fields.split_whitespace();
+        let bad = || format!("line {}: expected a cycle then name=value fields", n + 1);
+        let cycle = words.next().and_then(|w| w.parse().ok()).ok_or_else(bad)?;
+        let fields = words
+            .map(|w| {
+                let mut nv = w.splitn(2, '=');
+                match (nv.next(), nv.next()) {
+                    (Some(name), Some(value)) => Ok((name, value)),
+                    _ => Err(bad()),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        records.push(Record { line: n + 1, cycle, fields, text });
+    }
+    Ok(records)
+}
+
+/// Where two traces first differ.
+#[derive(Debug, PartialEq)]
+pub enum Divergence<'a> {
+    /// At the same cycle, the fields named differ.
+    Fields { a: Record<'a>, b: Record<'a>, names: Vec<&'a str> },
+    /// A trace ends before the other, whose first record
+    /// past its end is given.
+    Ends { shorter: &'static str, next: Record<'a> },
+}
+
+impl<'a> fmt::Display for Divergence<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Divergence::Fields { ref a, ref b, ref names } => {
+                writeln!(f, "traces diverge at cycle {}: {} differ", a.cycle, names.join(", "))?;
+                writeln!(f, "a:{}: {}", a.line, a.text)?;
+                write!(f, "b:{}: {}", b.line, b.text)
+            },
+            Divergence::Ends { shorter, ref next } =>
+                write!(f, "traces agree until trace {} ends, before cycle {}", shorter, next.cycle),
+        }
+    }
+}
+
+/// Return true if `a` and `b` are the same value: the same
+/// number in hex, whatever the case and the leading zeros, or
+/// else the same text.
+fn same_value(a: &str, b: &str) -> bool {
+    match (parse_hex(a), parse_hex(b)) {
+        (Some(x), Some(y)) => x == y,
+        _ => a == b,
+    }
+}
+
+/// Compare the traces `a` and `b`, and return where they
+/// first differ, if they do. Records are aligned by cycle;
+/// a cycle missing from one of them, as it was filtered out,
+/// is not compared.
+pub fn diff<'a>(a: Vec<Record<'a>>, b: Vec<Record<'a>>) -> Option<Divergence<'a>> {
+    let (mut a, mut b) = (a.into_iter().peekable(), b.into_iter().peekable());
+    loop {
+        let (ca, cb) = match (a.peek(), b.peek()) {
+            (Some(ra), Some(rb)) => (ra.cycle, rb.cycle),
+            (None, Some(_)) => return Some(Divergence::Ends { shorter: "a", next: b.next().unwrap() }),
+            (Some(_), None) => return Some(Divergence::Ends { shorter: "b", next: a.next().unwrap() }),
+            (None, None) => return None,
+        };
+        if ca < cb {
+            a.next();
+        } else if cb < ca {
+            b.next();
+        } else {
+            let (ra, rb) = (a.next().unwrap(), b.next().unwrap());
+            let names: Vec<&str> = ra.fields.iter()
+                .filter(|&&(name, value)| {
+                    rb.fields.iter().any(|&(n, v)| n == name && !same_value(v, value))
+                })
+                .map(|&(name, _)| name)
+                .collect();
+            if !names.is_empty() {
+                return Some(Divergence::Fields { a: ra, b: rb, names });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[path="./trace_test.rs"]
+mod trace_test;
@@ -0,0 +1,98 @@
+use super::*;
+
+use cpu::PC_START;
+use asm;
+
+/// The trace of the program "src", run for `ninsns`
+/// instructions with `filter`.
+fn trace_of(src: &str, ninsns: usize, filter: TraceFilter) -> String {
+    let mut c = CPU::headless();
+    c.load_rom(&asm::assemble(src, PC_START).unwrap()[..], PC_START).unwrap();
+    let mut t = Tracer::new(Vec::new(), filter).unwrap();
+    for _ in 0..ninsns {
+        t.record(&c).unwrap();
+        c.execute_insn().unwrap();
+    }
+    String::from_utf8(t.out).unwrap()
+}
+
+const LOOP: &str = "v1 := 0xab\n: loop\nv0 += 1\ni := long 0x1234\njump loop";
+
+#[test]
+fn test_tracer() {
+    let t = trace_of(LOOP, 4, TraceFilter::default());
+    let lines: Vec<&str> = t.lines().collect();
+    assert_eq!(lines.len(), 5);
+    assert_eq!(lines[0], "# cycle pc op v0-vf i sp dt st ; mnemonic");
+    assert_eq!(lines[3], concat!("2 pc=0204 op=f000 v0=01 v1=ab v2=00 v3=00 v4=00 v5=00 v6=00 ",
+                                 "v7=00 v8=00 v9=00 va=00 vb=00 vc=00 vd=00 ve=00 vf=00 ",
+                                 "i=0000 sp=e9e dt=00 st=00 ; i := long 0x1234"));
+    assert!(lines[4].starts_with("3 pc=0208 op=1202 v0=01"));
+    assert!(lines[4].contains(" i=1234 "));
+}
+
+#[test]
+fn test_filters() {
+    let range: AddrRange = "0x202-204".parse().unwrap();
+    assert_eq!(range, AddrRange { start: 0x202, end: 0x204 });
+    assert_eq!("0x300".parse(), Ok(AddrRange { start: 0x300, end: 0x300 }));
+    assert!("0x300-0x200".parse::<AddrRange>().is_err());
+    assert!("start-end".parse::<AddrRange>().is_err());
+
+    let ops: OpClasses = "1, F000".parse().unwrap();
+    assert!(ops.contains(0x1202) && ops.contains(0xf000));
+    assert!(!ops.contains(0x7001) && !ops.contains(0xf055));
+    let err = "dxyn,9xy1".parse::<OpClasses>().unwrap_err();
+    assert!(err.to_string().contains("unknown opcode class '9xy1'"));
+
+    let cycles = |filter| {
+        trace_of(LOOP, 10, filter).lines().skip(1)
+            .map(|l| l.split(' ').next().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(cycles(TraceFilter { range: Some(range), ops: None }), ["1", "2", "4", "5", "7", "8"]);
+    assert_eq!(cycles(TraceFilter { range: Some(range), ops: Some(ops) }), ["2", "5", "8"]);
+}
+
+#[test]
+fn test_parse() {
+    let records = parse("# comment\n\n7 pc=0200 v0=0a ; v0 += 1\n").unwrap();
+    assert_eq!(records, vec![Record {
+        line: 3,
+        cycle: 7,
+        fields: vec![("pc", "0200"), ("v0", "0a")],
+        text: "7 pc=0200 v0=0a ; v0 += 1",
+    }]);
+    assert_eq!(parse("x pc=0200"), Err("line 1: expected a cycle then name=value fields".to_string()));
+    assert!(parse("1 pc").is_err());
+}
+
+#[test]
+fn test_diff() {
+    let a = trace_of(LOOP, 10, TraceFilter::default());
+    assert_eq!(diff(parse(&a).unwrap(), parse(&a).unwrap()), None);
+
+    // A reference in another format: other fields, fewer
+    // cycles, other case and widths.
+    let b = "0 pc=0x200 v1=0\n3 pc=0x208 v1=AB x=1\n5 pc=0x204 v0=2\n9 pc=0x208";
+    assert_eq!(diff(parse(&a).unwrap(), parse(b).unwrap()), None);
+    match diff(parse(&a).unwrap(), parse("0 pc=200\n3 pc=208").unwrap()) {
+        Some(Divergence::Ends { shorter: "b", ref next }) => assert_eq!(next.cycle, 4),
+        d => panic!("{:?}", d),
+    }
+
+    let b = "3 pc=208 v1=ab\n6 pc=20a v0=3 v1=ab ; v0 += 1";
+    let d = diff(parse(&a).unwrap(), parse(b).unwrap()).unwrap();
+    match d {
+        Divergence::Fields { ref a, ref b, ref names } => {
+            assert_eq!((a.cycle, b.line), (6, 2));
+            assert_eq!(names, &["pc", "v0"]);
+        },
+        _ => panic!("{:?}", d),
+    }
+    assert!(d.to_string().starts_with("traces diverge at cycle 6: pc, v0 differ\na:8: 6 pc=0208"));
+
+    let b = "3 pc=208\n10 pc=208";
+    let d = diff(parse(&a).unwrap(), parse(b).unwrap()).unwrap();
+    assert_eq!(d.to_string(), "traces agree until trace a ends, before cycle 10");
+}
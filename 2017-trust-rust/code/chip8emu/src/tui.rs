@@ -0,0 +1,260 @@
+// tui.rs
+
+// The terminal frontend, for when there is no window to open,
+// like over SSH. The screen is drawn with Unicode half blocks
+// in 24 bit colour, and keys are read from the terminal in
+// raw mode.
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::io::prelude::*;
+use std::time::Duration;
+
+use crossterm::{cursor, execute};
+use crossterm::terminal as term;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::event::{KeyboardEnhancementFlags, PopKeyboardEnhancementFlags,
+                       PushKeyboardEnhancementFlags};
+
+use audio::{Audio, Waveform};
+use display::{Display, KeyState, SCREEN_WIDTH, SCREEN_HEIGHT};
+use frontend::{Controls, Hotkey};
+use keymap::Keymap;
+use render::Renderer;
+use terminal::{half_blocks, KeyHold};
+
+/// Puts the terminal in raw mode and switches to its
+/// alternate screen, for as long as it lives.
+pub struct TerminalGuard {
+    /// Whether the terminal was asked to report key releases.
+    reports_releases: bool,
+}
+
+impl TerminalGuard {
+    pub fn enter() -> io::Result<TerminalGuard> {
+        term::enable_raw_mode()?;
+        let mut out = io::stdout();
+        execute!(out, term::EnterAlternateScreen, cursor::Hide,
+                 term::Clear(term::ClearType::All))?;
+        let reports_releases = term::supports_keyboard_enhancement().unwrap_or(false);
+        if reports_releases {
+            execute!(out, PushKeyboardEnhancementFlags(
+                KeyboardEnhancementFlags::REPORT_EVENT_TYPES))?;
+        }
+        Ok(TerminalGuard { reports_releases })
+    }
+
+    /// Return true if the terminal reports key releases, so
+    /// that keys do not have to be released after a while.
+    pub fn reports_releases(&self) -> bool {
+        self.reports_releases
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let mut out = io::stdout();
+        if self.reports_releases {
+            let _ = execute!(out, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(out, term::LeaveAlternateScreen, cursor::Show);
+        let _ = term::disable_raw_mode();
+    }
+}
+
+/// The CHIP-8 display, drawn in the terminal. Terminals are
+/// slow, over SSH in particular, so it is drawn at most once
+/// per frame rather than on every "dxyn".
+pub struct Terminal {
+    renderer: Renderer,
+    width: u32,
+    height: u32,
+    /// The value of each pixel, row by row.
+    mem: Vec<u8>,
+    /// Whether pixels were drawn since the screen was last
+    /// drawn in the terminal.
+    dirty: bool,
+    /// Whether the terminal must be cleared, because the
+    /// resolution changed.
+    resized: bool,
+}
+
+impl Terminal {
+    pub fn new(renderer: Renderer) -> Terminal {
+        let (width, height) = (u32::from(SCREEN_WIDTH), u32::from(SCREEN_HEIGHT));
+        Terminal {
+            renderer,
+            width,
+            height,
+            mem: vec![0; (width * height) as usize],
+            dirty: true,
+            resized: false,
+        }
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        let img = self.renderer.render(&self.mem, self.width, self.height);
+        let mut text = String::from(if self.resized { "\x1b[2J\x1b[H" } else { "\x1b[H" });
+        text.push_str(&half_blocks(&img));
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        out.write_all(text.as_bytes())?;
+        out.flush()
+    }
+}
+
+impl Display for Terminal {
+    fn clear(&mut self) {
+        for p in self.mem.iter_mut() {
+            *p = 0;
+        }
+    }
+
+    fn set_resolution(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.mem = vec![0; (width * height) as usize];
+        self.resized = true;
+    }
+
+    fn draw_pixel(&mut self, x: u32, y: u32, val: u8) {
+        self.mem[(y * self.width + x) as usize] = val;
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> u8 {
+        self.mem[(y * self.width + x) as usize]
+    }
+
+    fn present(&mut self) {
+        self.dirty = true;
+    }
+
+    fn vblank(&mut self) {
+        self.renderer.next_frame();
+        if self.dirty || self.renderer.fades() {
+            self.draw().expect("Unable to draw in the terminal");
+            self.dirty = false;
+            self.resized = false;
+        }
+    }
+}
+
+/// Map a key name of a Keymap to a terminal key. Terminals
+/// do not tell the keys of the numeric keypad from the others,
+/// so "Keypad 5" is "5".
+fn key_code(name: &str) -> Option<KeyCode> {
+    let name = name.trim_start_matches("keypad ");
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => return Some(KeyCode::Char(c)),
+        (None, _) => return None,
+        _ => {},
+    }
+    match name {
+        "up" => Some(KeyCode::Up),
+        "down" => Some(KeyCode::Down),
+        "left" => Some(KeyCode::Left),
+        "right" => Some(KeyCode::Right),
+        "space" => Some(KeyCode::Char(' ')),
+        "return" | "enter" => Some(KeyCode::Enter),
+        "tab" => Some(KeyCode::Tab),
+        "home" => Some(KeyCode::Home),
+        "end" => Some(KeyCode::End),
+        "insert" => Some(KeyCode::Insert),
+        "delete" => Some(KeyCode::Delete),
+        "pageup" => Some(KeyCode::PageUp),
+        "pagedown" => Some(KeyCode::PageDown),
+        _ => None,
+    }
+}
+
+/// The keyboard of the terminal, used as the CHIP-8 keypad.
+pub struct TermKeyboard {
+    /// The CHIP-8 key bound to each terminal key.
+    bindings: HashMap<KeyCode, u8>,
+    keys: KeyHold,
+    /// Hotkeys pressed but not read yet.
+    hotkeys: VecDeque<Hotkey>,
+}
+
+impl TermKeyboard {
+    /// The keyboard with the key bindings of `keymap`.
+    /// `reports_releases` tells whether the terminal reports
+    /// key releases.
+    pub fn new(keymap: &Keymap, reports_releases: bool) -> TermKeyboard {
+        let mut bindings = HashMap::new();
+        for (name, &key) in keymap.bindings() {
+            match key_code(name) {
+                Some(k) => { bindings.insert(k, key); },
+                None => eprintln!("warning: unknown key '{}' in key bindings", name),
+            }
+        }
+        TermKeyboard { bindings, keys: KeyHold::new(reports_releases), hotkeys: VecDeque::new() }
+    }
+
+    /// Map a terminal key to a Hotkey.
+    fn key_to_hotkey(k: &KeyEvent) -> Option<Hotkey> {
+        match k.code {
+            KeyCode::F(5) => Some(Hotkey::SaveState),
+            KeyCode::F(9) => Some(Hotkey::LoadState),
+            KeyCode::Backspace => Some(Hotkey::Rewind),
+            KeyCode::F(12) => Some(Hotkey::Screenshot),
+            KeyCode::Esc => Some(Hotkey::Quit),
+            KeyCode::Char('c') if k.modifiers.contains(KeyModifiers::CONTROL) => Some(Hotkey::Quit),
+            _ => None,
+        }
+    }
+
+    /// Update the pressed keys, or queue up the hotkey
+    /// pressed, from a terminal key event.
+    fn handle_key(&mut self, k: KeyEvent) {
+        if let Some(h) = TermKeyboard::key_to_hotkey(&k) {
+            if k.kind == KeyEventKind::Press {
+                self.hotkeys.push_back(h);
+            }
+            return;
+        }
+        let code = match k.code {
+            KeyCode::Char(c) => KeyCode::Char(c.to_ascii_lowercase()),
+            code => code,
+        };
+        if let Some(&v) = self.bindings.get(&code) {
+            match k.kind {
+                KeyEventKind::Press | KeyEventKind::Repeat => self.keys.press(v),
+                KeyEventKind::Release => self.keys.release(v),
+            }
+        }
+    }
+}
+
+impl Controls for TermKeyboard {
+    fn poll_hotkey(&mut self) -> Option<Hotkey> {
+        self.keys.next_frame();
+        while event::poll(Duration::from_secs(0)).unwrap_or(false) {
+            if let Ok(Event::Key(k)) = event::read() {
+                self.handle_key(k);
+            }
+        }
+        self.hotkeys.pop_front()
+    }
+
+    fn keys(&self) -> KeyState {
+        self.keys.keys()
+    }
+}
+
+/// The buzzer of the terminal: it rings the bell when the
+/// sound starts.
+pub struct Bell;
+
+impl Audio for Bell {
+    fn set_playing(&mut self, playing: bool) {
+        if playing {
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            let _ = out.write_all(b"\x07").and_then(|_| out.flush());
+        }
+    }
+
+    fn set_waveform(&mut self, _waveform: Waveform) {}
+}
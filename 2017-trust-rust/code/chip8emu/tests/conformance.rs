@@ -0,0 +1,197 @@
+// conformance.rs
+
+// End-to-end tests of the emulator. Each case listed in
+// tests/conformance/cases runs a ROM on a headless machine for
+// a number of frames, holding down keys on the way, and the
+// screen it ends with is compared with its golden file, a text
+// dump of the framebuffer in tests/conformance/golden. Run with
+//
+//   cargo test --test conformance [-- [--bless] [NAME...]]
+//
+// to run the cases whose name contains one of the NAMEs, or
+// all of them. "--bless" writes their screens as the new golden
+// files, for when a change is meant to alter them; look at
+// what changed with "git diff" before committing.
+//
+// The test programs are written for these tests, in the syntax
+// of src/asm.rs, rather than taken from the test ROMs of the
+// CHIP-8 community, so that they are small and every mark they
+// draw is explained in their source: opcodes.8o and xochip.8o
+// draw a square for each check which passed and a cross for
+// each which failed, and quirks.8o a square for each quirk the
+// interpreter has. The games in roms/ are run too.
+
+extern crate chip8;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use chip8::asm;
+use chip8::cpu::PC_START;
+use chip8::display::KeyState;
+use chip8::machine::Machine;
+use chip8::quirks::Quirks;
+
+/// Key `key` is held from frame `start` until before frame
+/// `end`.
+struct Press {
+    key: u8,
+    start: u32,
+    end: u32,
+}
+
+struct Case {
+    name: String,
+    /// Path of the ROM, from the top of the crate.
+    rom: String,
+    quirks: Quirks,
+    frames: u32,
+    presses: Vec<Press>,
+}
+
+/// Parse a key press, like "c@60-100".
+fn parse_press(s: &str) -> Result<Press, String> {
+    let bad = || format!("bad key press '{}', expected KEY@START-END", s);
+    let mut parts = s.splitn(2, '@');
+    let key = parts.next().and_then(|k| u8::from_str_radix(k, 16).ok()).filter(|&k| k < 16);
+    let mut frames = parts.next().ok_or_else(bad)?.splitn(2, '-').map(|f| f.parse().ok());
+    match (key, frames.next(), frames.next()) {
+        (Some(key), Some(Some(start)), Some(Some(end))) => Ok(Press { key, start, end }),
+        _ => Err(bad()),
+    }
+}
+
+/// Parse the list of cases.
+fn parse_cases(text: &str) -> Result<Vec<Case>, String> {
+    let mut cases = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let words: Vec<&str> = line.split('#').next().unwrap_or("").split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+        let bad = |e: String| format!("cases:{}: {}", n + 1, e);
+        if words.len() < 4 {
+            return Err(bad("expected a name, a ROM, a quirks preset and a number of frames".to_string()));
+        }
+        cases.push(Case {
+            name: words[0].to_string(),
+            rom: words[1].to_string(),
+            quirks: words[2].parse().map_err(|e| bad(format!("{}", e)))?,
+            frames: words[3].parse().map_err(|_| bad(format!("bad number of frames '{}'", words[3])))?,
+            presses: words[4..].iter().map(|p| parse_press(p)).collect::<Result<_, _>>().map_err(bad)?,
+        });
+    }
+    Ok(cases)
+}
+
+/// The screen as text, a line per row and a character per
+/// pixel: "." if it is off, "#" if it is on in the first plane
+/// only, and otherwise the digit of its planes.
+fn dump(width: u32, pixels: &[u8]) -> String {
+    let mut text = String::new();
+    for row in pixels.chunks(width as usize) {
+        for &p in row {
+            text.push(match p {
+                0 => '.',
+                1 => '#',
+                _ => std::char::from_digit(u32::from(p), 16).unwrap_or('?'),
+            });
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Run `case`, and return its screen as text.
+fn run(case: &Case, root: &Path) -> Result<String, String> {
+    let path = root.join(&case.rom);
+    let rom = if case.rom.ends_with(".8o") {
+        let src = fs::read_to_string(&path).map_err(|e| format!("{}: {}", case.rom, e))?;
+        asm::assemble(&src, PC_START).map_err(|e| format!("{}: {}", case.rom, e))?
+    } else {
+        fs::read(&path).map_err(|e| format!("{}: {}", case.rom, e))?
+    };
+    let mut m = Machine::headless();
+    m.cpu_mut().set_quirks(case.quirks);
+    m.load_rom(&rom[..]).map_err(|e| e.to_string())?;
+    for frame in 0..case.frames {
+        let mut keys = KeyState::new();
+        for p in case.presses.iter().filter(|p| p.start <= frame && frame < p.end) {
+            keys.press(p.key);
+        }
+        m.set_keys(keys);
+        m.run_frame().map_err(|e| format!("frame {}: {}", frame, e))?;
+        if m.exited() {
+            break;
+        }
+    }
+    let (width, _, pixels) = m.framebuffer();
+    Ok(dump(width, &pixels))
+}
+
+/// Describe how the screen `got` differs from `expected`,
+/// row by row.
+fn differences(expected: &str, got: &str) -> String {
+    let (expected, got): (Vec<&str>, Vec<&str>) = (expected.lines().collect(), got.lines().collect());
+    let mut text = String::new();
+    if expected.len() != got.len() {
+        text += &format!("  expected {} rows, got {}\n", expected.len(), got.len());
+    }
+    for (y, (e, g)) in expected.iter().zip(&got).enumerate().filter(|&(_, (e, g))| e != g) {
+        text += &format!("  row {}:\n    expected {}\n    got      {}\n", y, e, g);
+    }
+    text
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let bless = args.iter().any(|a| a == "--bless");
+    let names: Vec<&String> = args.iter().filter(|a| !a.starts_with('-')).collect();
+
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let dir = root.join("tests/conformance");
+    let cases = fs::read_to_string(dir.join("cases")).map_err(|e| e.to_string())
+        .and_then(|text| parse_cases(&text))
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(2);
+        });
+    let cases: Vec<Case> = cases.into_iter()
+        .filter(|c| names.is_empty() || names.iter().any(|n| c.name.contains(n.as_str())))
+        .collect();
+
+    println!("\nrunning {} conformance cases", cases.len());
+    let mut failed = Vec::new();
+    for case in &cases {
+        let golden = dir.join("golden").join(&case.name);
+        let result = run(case, root).and_then(|screen| {
+            if bless {
+                return fs::write(&golden, screen).map(|()| "blessed").map_err(|e| e.to_string());
+            }
+            match fs::read_to_string(&golden) {
+                Ok(ref expected) if *expected == screen => Ok("ok"),
+                Ok(expected) => Err(format!("the screen differs from {}:\n{}",
+                                            golden.display(), differences(&expected, &screen))),
+                Err(e) => Err(format!("{}: {}, run with --bless to write it", golden.display(), e)),
+            }
+        });
+        match result {
+            Ok(status) => println!("case {} ... {}", case.name, status),
+            Err(e) => {
+                println!("case {} ... FAILED", case.name);
+                failed.push((&case.name, e));
+            },
+        }
+    }
+
+    for &(name, ref e) in &failed {
+        println!("\n---- {} ----\n{}", name, e);
+    }
+    let status = if failed.is_empty() { "ok" } else { "FAILED" };
+    println!("\ntest result: {}. {} passed; {} failed\n", status, cases.len() - failed.len(), failed.len());
+    if !failed.is_empty() {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,120 @@
+// lib.rs
+
+// The emulator for web pages, built with wasm-bindgen:
+//
+//   wasm-pack build --target web
+//
+// The page runs step_frame() 60 times a second, draws
+// framebuffer() in a canvas and passes key events on to
+// key_down/key_up. The tests run in Node with
+//
+//   wasm-pack test --node
+
+extern crate chip8;
+extern crate wasm_bindgen;
+#[cfg(test)]
+extern crate wasm_bindgen_test;
+
+use wasm_bindgen::prelude::*;
+
+use chip8::clock::VirtualClock;
+use chip8::machine::Machine;
+use chip8::quirks::Quirks;
+use chip8::state::State;
+
+/// A CHIP-8 machine running a ROM. Errors are thrown to
+/// JavaScript as strings.
+#[wasm_bindgen]
+pub struct Chip8 {
+    /// Its clock never sleeps: the page decides when frames
+    /// run.
+    machine: Machine<VirtualClock>,
+}
+
+#[wasm_bindgen]
+impl Chip8 {
+    /// A machine with `rom` loaded at 0x200.
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8]) -> Result<Chip8, String> {
+        let mut machine = Machine::headless();
+        machine.load_rom(rom).map_err(|e| e.to_string())?;
+        Ok(Chip8 { machine })
+    }
+
+    /// Use the quirks preset named `name`, like "schip".
+    pub fn set_quirks(&mut self, name: &str) -> Result<(), String> {
+        let quirks: Quirks = name.parse().map_err(|e: chip8::quirks::UnknownPreset| e.to_string())?;
+        self.machine.cpu_mut().set_quirks(quirks);
+        Ok(())
+    }
+
+    /// Restart the random number generator from `seed`.
+    /// Pages should pass a random one, like
+    /// `Math.random() * 2 ** 53`.
+    pub fn set_seed(&mut self, seed: f64) {
+        self.machine.cpu_mut().set_seed(seed as u64);
+    }
+
+    /// Set the number of instructions run per frame.
+    pub fn set_speed(&mut self, insns_per_frame: u32) {
+        self.machine.set_insns_per_frame(insns_per_frame);
+    }
+
+    /// Run one 60 Hz frame. Return false once the program
+    /// has exited.
+    pub fn step_frame(&mut self) -> Result<bool, String> {
+        self.machine.run_frame().map_err(|e| e.to_string())?;
+        Ok(!self.machine.exited())
+    }
+
+    /// Width of the display in pixels: 64, or 128 in the
+    /// SUPER-CHIP high resolution mode.
+    pub fn width(&self) -> u32 {
+        self.machine.framebuffer().0
+    }
+
+    /// Height of the display in pixels.
+    pub fn height(&self) -> u32 {
+        self.machine.framebuffer().1
+    }
+
+    /// The value of each pixel (0 if OFF, otherwise its bit
+    /// planes), row by row.
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.machine.framebuffer().2
+    }
+
+    /// Return true while the buzzer sounds.
+    pub fn sound_playing(&self) -> bool {
+        self.machine.cpu().sound() > 0
+    }
+
+    /// Press the CHIP-8 key `key` (0 to 0xf).
+    pub fn key_down(&mut self, key: u8) {
+        self.machine.press(key);
+    }
+
+    /// Release the CHIP-8 key `key`.
+    pub fn key_up(&mut self, key: u8) {
+        self.machine.release(key);
+    }
+
+    /// A snapshot of the machine, in the format of the save
+    /// state files of the desktop emulator.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.machine.cpu().save_state().write(&mut buf).expect("writing to a Vec cannot fail");
+        buf
+    }
+
+    /// Restore a snapshot taken by `save_state`.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), String> {
+        let mut r = state;
+        let st = State::read(&mut r).map_err(|e| e.to_string())?;
+        self.machine.cpu_mut().load_state(&st).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+#[path="./lib_test.rs"]
+mod lib_test;
@@ -0,0 +1,69 @@
+use super::*;
+
+// Run as plain tests too outside WebAssembly, by `cargo test`.
+use wasm_bindgen_test::*;
+
+/// Bundled in the test, as there are no files in WebAssembly.
+const PONG: &[u8] = include_bytes!("../../roms/PONG");
+
+#[wasm_bindgen_test(unsupported = test)]
+fn test_step_frame_draws() {
+    let mut c = Chip8::new(PONG).unwrap();
+    assert_eq!((c.width(), c.height()), (64, 32));
+    assert!(c.framebuffer().iter().all(|&p| p == 0));
+    for _ in 0..10 {
+        assert!(c.step_frame().unwrap());
+    }
+    let fb = c.framebuffer();
+    assert_eq!(fb.len(), 64 * 32);
+    assert!(fb.contains(&1));
+}
+
+#[wasm_bindgen_test(unsupported = test)]
+fn test_rom_too_large() {
+    assert!(Chip8::new(&[0; 4096]).is_err());
+}
+
+#[wasm_bindgen_test(unsupported = test)]
+fn test_fault() {
+    // 0x0000 is not a valid instruction.
+    let mut c = Chip8::new(&[0, 0]).unwrap();
+    assert!(c.step_frame().is_err());
+}
+
+#[wasm_bindgen_test(unsupported = test)]
+fn test_exit() {
+    // SUPER-CHIP "00fd" exits.
+    let mut c = Chip8::new(&[0x00, 0xfd]).unwrap();
+    assert!(!c.step_frame().unwrap());
+}
+
+#[wasm_bindgen_test(unsupported = test)]
+fn test_keys() {
+    // v0 = wait for key; jump to itself.
+    let mut c = Chip8::new(&[0xf0, 0x0a, 0x12, 0x02]).unwrap();
+    c.step_frame().unwrap();
+    c.key_down(7);
+    c.step_frame().unwrap();
+    c.key_up(7);
+    c.step_frame().unwrap();
+    let st = State::read(&mut &c.save_state()[..]).unwrap();
+    assert_eq!(st.v[0], 7);
+}
+
+#[wasm_bindgen_test(unsupported = test)]
+fn test_save_and_load_state() {
+    let mut c = Chip8::new(PONG).unwrap();
+    for _ in 0..30 {
+        c.step_frame().unwrap();
+    }
+    let saved = c.save_state();
+    let fb = c.framebuffer();
+    for _ in 0..30 {
+        c.step_frame().unwrap();
+    }
+    c.load_state(&saved).unwrap();
+    assert_eq!(c.framebuffer(), fb);
+    assert_eq!(c.save_state(), saved);
+    assert!(c.load_state(&saved[..10]).is_err());
+}